@@ -0,0 +1,401 @@
+//! Access Policy - what each access zone may reach
+//!
+//! The security middleware resolves every request to an `AccessZone` (and,
+//! with a managed API key, an identity). This module decides what that caller
+//! may do, at two points:
+//!
+//! - **Routes**, in the security middleware: per-zone allow/deny route
+//!   patterns, and `/mcp/p/{profile}` for profiles built from a preset that
+//!   `requires_localhost` only from `Localhost` or `TrustedMesh`.
+//! - **Tools**, in [`crate::executor::ToolExecutor::begin`]: a tool's
+//!   security level is the highest of the built-in tool groups it belongs to
+//!   (`standard` when it belongs to none), and the zone must be able to
+//!   access it; rules may also allow or deny groups outright.
+//!
+//! Rules come from `/etc/op-dbus/access-policy.json`, per zone (`Debug`
+//! names) and per authenticated API key (by name). A key rule narrows its
+//! zone's: deny lists add up, a non-empty allow list replaces the zone's, and
+//! `max_security` is the key's when it sets one, the zone's otherwise:
+//!
+//! ```json
+//! {
+//!   "zones": {
+//!     "Public": {
+//!       "routes": { "deny": ["/admin/**", "/groups-admin/**", "POST /api/tools/batch"] },
+//!       "max_security": "standard",
+//!       "groups": { "deny": ["shell"] }
+//!     }
+//!   },
+//!   "api_keys": { "ci-runner": { "max_security": "elevated" } }
+//! }
+//! ```
+//!
+//! Without a rule, `PrivateNetwork` and `Public` are kept out of `/admin`,
+//! macro writes, the audit log, job lists and status, job cancellation and cache
+//! clearing, `Public` also out of `/groups-admin`, and tools are limited to
+//! what the zone can access. Denials are explained in the response and audited.
+
+use axum::http::Method;
+use op_core::security::{AccessZone, SecurityLevel};
+use op_tools::registry::ToolDefinition;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+use crate::audit::Caller;
+use crate::groups_admin::GROUPS_CONFIG;
+use crate::scope_policy::glob_match;
+use crate::tool_profiles;
+
+const ACCESS_POLICY_CONFIG_PATH: &str = "/etc/op-dbus/access-policy.json";
+
+/// Security levels, lowest first
+const LEVELS: &[&str] = &["public", "standard", "elevated", "restricted"];
+
+/// Level of a tool outside every built-in group
+const UNGROUPED_LEVEL: &str = "standard";
+
+lazy_static::lazy_static! {
+    /// For the security middleware, which runs outside `AppState`
+    static ref GLOBAL_POLICY: RwLock<Option<Arc<AccessPolicy>>> = RwLock::new(None);
+}
+
+/// Make `policy` available through [`global`]
+pub fn install(policy: Arc<AccessPolicy>) {
+    *GLOBAL_POLICY.write().unwrap() = Some(policy);
+}
+
+/// The policy installed by `AppState`, if any
+pub fn global() -> Option<Arc<AccessPolicy>> {
+    GLOBAL_POLICY.read().unwrap().clone()
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AllowDeny {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AccessRule {
+    /// `/path/glob` or `METHOD /path/glob`
+    #[serde(default)]
+    pub routes: AllowDeny,
+    /// Highest tool security level; defaults to what the zone can access
+    #[serde(default)]
+    pub max_security: Option<String>,
+    /// Built-in tool group ids
+    #[serde(default)]
+    pub groups: AllowDeny,
+}
+
+/// `/etc/op-dbus/access-policy.json`
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct AccessConfig {
+    #[serde(default)]
+    pub zones: HashMap<String, AccessRule>,
+    #[serde(default)]
+    pub api_keys: HashMap<String, AccessRule>,
+}
+
+impl AccessConfig {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(ACCESS_POLICY_CONFIG_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<AccessConfig>(&mut raw) } {
+            Ok(config) => {
+                info!(
+                    "Loaded access policy from {}: {} zone rules, {} API key rules",
+                    ACCESS_POLICY_CONFIG_PATH,
+                    config.zones.len(),
+                    config.api_keys.len()
+                );
+                config
+            }
+            Err(e) => {
+                warn!("Invalid access policy {}: {}, using defaults", ACCESS_POLICY_CONFIG_PATH, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+fn parse_zone(name: &str) -> Option<AccessZone> {
+    match name {
+        "Localhost" => Some(AccessZone::Localhost),
+        "TrustedMesh" => Some(AccessZone::TrustedMesh),
+        "PrivateNetwork" => Some(AccessZone::PrivateNetwork),
+        "Public" => Some(AccessZone::Public),
+        _ => None,
+    }
+}
+
+fn security_level(name: &str) -> SecurityLevel {
+    match name {
+        "public" => SecurityLevel::Public,
+        "standard" => SecurityLevel::Standard,
+        "elevated" => SecurityLevel::Elevated,
+        _ => SecurityLevel::Restricted,
+    }
+}
+
+fn rank(level: &str) -> usize {
+    LEVELS.iter().position(|l| *l == level).unwrap_or(LEVELS.len() - 1)
+}
+
+fn is_local(zone: &AccessZone) -> bool {
+    matches!(zone, AccessZone::Localhost | AccessZone::TrustedMesh)
+}
+
+/// Routes that change shared state or expose other callers' activity; the
+/// ones [`crate::api_keys::required_scope`] reserves for `admin` or that act
+/// across callers
+const LOCAL_ONLY_ROUTES: &[&str] = &[
+    "/admin/**",
+    "POST /api/macros",
+    "PUT /api/macros/*",
+    "DELETE /api/macros/*",
+    "/api/audit",
+    "GET /api/jobs",
+    "GET /api/jobs/*",
+    "DELETE /api/jobs/*",
+    "DELETE /api/cache",
+];
+
+/// Rule for a zone with nothing configured
+fn default_rule(zone: &AccessZone) -> AccessRule {
+    let mut deny: Vec<String> = match zone {
+        AccessZone::Localhost | AccessZone::TrustedMesh => Vec::new(),
+        _ => LOCAL_ONLY_ROUTES.iter().map(|d| d.to_string()).collect(),
+    };
+    if matches!(zone, AccessZone::Public) {
+        deny.push("/groups-admin/**".to_string());
+    }
+    AccessRule {
+        routes: AllowDeny {
+            allow: Vec::new(),
+            deny,
+        },
+        ..Default::default()
+    }
+}
+
+/// `zone` narrowed by `key`: both deny lists, and the key's allow list if it has one
+fn merge(zone: AllowDeny, key: &AllowDeny) -> AllowDeny {
+    let mut deny = zone.deny;
+    for pattern in &key.deny {
+        if !deny.contains(pattern) {
+            deny.push(pattern.clone());
+        }
+    }
+    AllowDeny {
+        allow: if key.allow.is_empty() { zone.allow } else { key.allow.clone() },
+        deny,
+    }
+}
+
+/// Whether `pattern` (`/glob` or `METHOD /glob`) covers the request
+fn route_matches(pattern: &str, method: &Method, path: &str) -> bool {
+    match pattern.split_once(' ') {
+        Some((want, glob)) => want.eq_ignore_ascii_case(method.as_str()) && glob_match(glob.trim(), path),
+        None => glob_match(pattern, path),
+    }
+}
+
+pub struct AccessPolicy {
+    config: AccessConfig,
+}
+
+impl AccessPolicy {
+    /// Policy from `/etc/op-dbus/access-policy.json`
+    pub fn load() -> Self {
+        Self::new(AccessConfig::load())
+    }
+
+    pub fn new(config: AccessConfig) -> Self {
+        Self { config }
+    }
+
+    fn rule(&self, zone: &AccessZone, api_key: Option<&str>) -> AccessRule {
+        let zone_rule = self
+            .config
+            .zones
+            .get(&format!("{:?}", zone))
+            .cloned()
+            .unwrap_or_else(|| default_rule(zone));
+        match api_key.and_then(|key| self.config.api_keys.get(key)) {
+            Some(key_rule) => AccessRule {
+                routes: merge(zone_rule.routes, &key_rule.routes),
+                max_security: key_rule.max_security.clone().or(zone_rule.max_security),
+                groups: merge(zone_rule.groups, &key_rule.groups),
+            },
+            None => zone_rule,
+        }
+    }
+
+    /// Decide a request; the error explains the denial
+    pub async fn authorize_route(
+        &self,
+        zone: &AccessZone,
+        api_key: Option<&str>,
+        method: &Method,
+        path: &str,
+    ) -> Result<(), String> {
+        let rule = self.rule(zone, api_key);
+        if let Some(pattern) = rule.routes.deny.iter().find(|p| route_matches(p, method, path)) {
+            return Err(format!("{} {} is denied to zone {:?} (rule '{}')", method, path, zone, pattern));
+        }
+        if !rule.routes.allow.is_empty() && !rule.routes.allow.iter().any(|p| route_matches(p, method, path)) {
+            return Err(format!("{} {} is not among the routes allowed to zone {:?}", method, path, zone));
+        }
+
+        // Profiles built from a localhost-only preset
+        if let Some(profile) = path.strip_prefix("/mcp/p/").and_then(|rest| rest.split('/').next()) {
+            if !is_local(zone) && requires_localhost(profile).await {
+                return Err(format!(
+                    "MCP profile '{}' uses a preset that requires localhost; zone {:?} may not use it",
+                    profile, zone
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Decide a tool execution by `caller`. A caller without a (known) zone
+    /// is treated as `Public`; transports that never see HTTP attach an
+    /// explicit local identity instead (see [`crate::audit::ClientIdentity::local`])
+    pub fn authorize_tool(&self, caller: &Caller, tool: &ToolDefinition) -> Result<(), String> {
+        let zone = caller.zone.as_deref().and_then(parse_zone).unwrap_or(AccessZone::Public);
+        let rule = self.rule(&zone, caller.api_key.as_deref());
+        let groups = tool_profiles::groups_of(tool);
+
+        if let Some((group, _)) = groups.iter().find(|(id, _)| rule.groups.deny.contains(id)) {
+            return Err(format!("Tool '{}' is in group '{}', which is denied to zone {:?}", tool.name, group, zone));
+        }
+        if !rule.groups.allow.is_empty() && !groups.iter().any(|(id, _)| rule.groups.allow.contains(id)) {
+            return Err(format!("Tool '{}' is in none of the groups allowed to zone {:?}", tool.name, zone));
+        }
+
+        let (group, level) = groups
+            .iter()
+            .max_by_key(|(_, level)| rank(level))
+            .map(|(id, level)| (Some(id.as_str()), level.as_str()))
+            .unwrap_or((None, UNGROUPED_LEVEL));
+        let permitted = match &rule.max_security {
+            Some(max) => rank(level) <= rank(max),
+            None => zone.can_access(security_level(level)),
+        };
+        if !permitted {
+            let source = group.map(|g| format!(" (group '{}')", g)).unwrap_or_default();
+            return Err(format!(
+                "Tool '{}' requires {} access{}; zone {:?} does not have it",
+                tool.name, level, source, zone
+            ));
+        }
+        Ok(())
+    }
+}
+
+async fn requires_localhost(profile: &str) -> bool {
+    let Some(preset) = GROUPS_CONFIG.get_profile(profile).await.and_then(|p| p.preset) else {
+        return false;
+    };
+    op_mcp_aggregator::builtin_presets()
+        .iter()
+        .any(|p| p.id.to_string() == preset && p.requires_localhost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str) -> ToolDefinition {
+        ToolDefinition {
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: simd_json::json!({ "type": "object" }),
+            schema_version: String::new(),
+            category: String::new(),
+            tags: Vec::new(),
+            namespace: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_routes_keep_admin_local() {
+        let policy = AccessPolicy::new(AccessConfig::default());
+        let post = Method::POST;
+
+        assert!(policy.authorize_route(&AccessZone::Public, None, &post, "/admin/registry/refresh").await.is_err());
+        assert!(policy.authorize_route(&AccessZone::PrivateNetwork, None, &post, "/admin/prompt/reload").await.is_err());
+        assert!(policy.authorize_route(&AccessZone::Localhost, None, &post, "/admin/prompt/reload").await.is_ok());
+        assert!(policy.authorize_route(&AccessZone::Public, None, &post, "/api/chat").await.is_ok());
+
+        let delete = Method::DELETE;
+        assert!(policy.authorize_route(&AccessZone::Public, None, &post, "/api/macros").await.is_err());
+        assert!(policy.authorize_route(&AccessZone::PrivateNetwork, None, &delete, "/api/jobs/42").await.is_err());
+        assert!(policy.authorize_route(&AccessZone::Public, None, &Method::GET, "/api/jobs/42").await.is_err());
+        assert!(policy.authorize_route(&AccessZone::Public, None, &delete, "/api/cache").await.is_err());
+        assert!(policy.authorize_route(&AccessZone::Public, None, &Method::GET, "/api/audit").await.is_err());
+        assert!(policy.authorize_route(&AccessZone::Public, None, &Method::GET, "/api/macros").await.is_ok());
+        assert!(policy.authorize_route(&AccessZone::Localhost, None, &delete, "/api/cache").await.is_ok());
+    }
+
+    #[test]
+    fn test_rules_limit_tools_by_level_and_group() {
+        let mut raw = r#"{
+            "zones": { "Public": { "max_security": "public", "routes": { "deny": ["POST /api/tool"] } } },
+            "api_keys": { "ci-runner": { "groups": { "allow": ["no-such-group"] } } }
+        }"#
+        .to_string();
+        let policy = AccessPolicy::new(unsafe { simd_json::from_str(&mut raw) }.unwrap());
+        let public = Caller { zone: Some("Public".to_string()), ..Default::default() };
+        let keyed = Caller { api_key: Some("ci-runner".to_string()), ..public.clone() };
+        let unidentified = Caller::default();
+        let local = Caller { zone: Some("Localhost".to_string()), ..Default::default() };
+
+        // Ungrouped tools are `standard`, above the configured ceiling
+        let denied = policy.authorize_tool(&public, &tool("zz_unlisted_probe")).unwrap_err();
+        assert!(denied.contains("requires standard access"), "{}", denied);
+        assert!(policy.authorize_tool(&keyed, &tool("zz_unlisted_probe")).is_err());
+        // No identity is the most restrictive zone, not the least
+        assert!(policy.authorize_tool(&unidentified, &tool("zz_unlisted_probe")).is_err());
+        assert!(policy.authorize_tool(&local, &tool("zz_unlisted_probe")).is_ok());
+
+        assert!(route_matches("POST /api/tool", &Method::POST, "/api/tool"));
+        assert!(!route_matches("POST /api/tool", &Method::GET, "/api/tool"));
+    }
+
+    #[tokio::test]
+    async fn test_key_rule_narrows_its_zone() {
+        let mut raw = r#"{
+            "zones": { "Public": { "max_security": "public", "routes": { "deny": ["POST /api/tool"] } } },
+            "api_keys": {
+                "ci-runner": { "routes": { "deny": ["/api/cache"] } },
+                "deployer": { "max_security": "elevated" }
+            }
+        }"#
+        .to_string();
+        let policy = AccessPolicy::new(unsafe { simd_json::from_str(&mut raw) }.unwrap());
+        let zone = AccessZone::Public;
+
+        // The zone's denials still hold for a key with a rule of its own
+        assert!(policy.authorize_route(&zone, Some("ci-runner"), &Method::POST, "/api/tool").await.is_err());
+        assert!(policy.authorize_route(&zone, Some("ci-runner"), &Method::GET, "/api/cache").await.is_err());
+        assert!(policy.authorize_route(&zone, None, &Method::GET, "/api/cache").await.is_ok());
+
+        // The zone's ceiling applies unless the key sets its own
+        let keyed = |key: &str| Caller {
+            zone: Some("Public".to_string()),
+            api_key: Some(key.to_string()),
+            ..Default::default()
+        };
+        assert!(policy.authorize_tool(&keyed("ci-runner"), &tool("zz_unlisted_probe")).is_err());
+        assert!(policy.authorize_tool(&keyed("deployer"), &tool("zz_unlisted_probe")).is_ok());
+    }
+}
@@ -0,0 +1,495 @@
+//! API Keys - managed credentials for non-interactive clients
+//!
+//! Keys are created, listed, rotated and revoked through `/admin/api-keys`
+//! and kept in `/var/lib/op-dbus/api-keys.json` (mode 0600) alongside the
+//! rest of the server state. The `StateStore` in `state.db` only models
+//! execution jobs, so keys are not stored there. Only a SHA-256 hash of each
+//! key is stored; the key itself is returned once, when it is created or
+//! rotated.
+//!
+//! A request carrying a valid key (`X-API-Key`, `X-Op-Mcp-Token` or
+//! `Authorization: Bearer`) is treated as `TrustedMesh` regardless of its
+//! IP, limited to the key's scopes (see [`required_scope`]):
+//!
+//! | Scope           | Grants                                                  |
+//! |-----------------|---------------------------------------------------------|
+//! | `chat`          | `/api/chat*`, `/ws`, reading `/api/llm`                 |
+//! | `tools:read`    | reading tools, jobs, macros, audit, cache, limits,      |
+//! |                 | agents and `/api/events`                                |
+//! | `tools:execute` | running tools, jobs and agents (implies `tools:read`)   |
+//! | `mcp:<profile>` | `/mcp/p/<profile>`; `mcp:*` for every MCP endpoint      |
+//! | `admin`         | everything, including `/admin`, macro changes,          |
+//! |                 | switching the LLM and any other unlisted change         |
+//!
+//! Reads of routes not listed here (health, status, OpenAPI) are open to any
+//! key; every other unlisted request needs `admin`.
+//!
+//! Unknown, expired and revoked keys grant nothing; the request is zoned by
+//! its IP as if it carried no key.
+
+use axum::http::Method;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock as StdRwLock};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const API_KEYS_PATH: &str = "/var/lib/op-dbus/api-keys.json";
+
+/// Prefix of generated keys, so they are recognizable in configs and logs
+pub(crate) const KEY_PREFIX: &str = "opk_";
+const KEY_RANDOM_LEN: usize = 40;
+
+/// Characters of a key kept in the clear to tell keys apart
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// `last_used_at` is persisted at most this often per key
+const LAST_USED_PERSIST_SECS: i64 = 60;
+
+lazy_static::lazy_static! {
+    /// For the security middleware, which runs outside `AppState`
+    static ref GLOBAL_STORE: StdRwLock<Option<Arc<ApiKeyStore>>> = StdRwLock::new(None);
+}
+
+/// Make `store` available through [`global`]
+pub fn install(store: Arc<ApiKeyStore>) {
+    *GLOBAL_STORE.write().unwrap() = Some(store);
+}
+
+/// The store installed by `AppState`, if any
+pub fn global() -> Option<Arc<ApiKeyStore>> {
+    GLOBAL_STORE.read().unwrap().clone()
+}
+
+/// What a key may do
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Scope {
+    Chat,
+    ToolsRead,
+    ToolsExecute,
+    Admin,
+    /// An MCP tool profile, or `*` for all MCP endpoints
+    McpProfile(String),
+}
+
+impl Scope {
+    /// Whether holding `self` satisfies a requirement of `required`
+    pub fn satisfies(&self, required: &Scope) -> bool {
+        match (self, required) {
+            (Scope::Admin, _) => true,
+            (Scope::ToolsExecute, Scope::ToolsRead) => true,
+            (Scope::McpProfile(held), Scope::McpProfile(wanted)) => held == "*" || held == wanted,
+            (held, wanted) => held == wanted,
+        }
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "chat" => Ok(Scope::Chat),
+            "tools:read" => Ok(Scope::ToolsRead),
+            "tools:execute" => Ok(Scope::ToolsExecute),
+            "admin" => Ok(Scope::Admin),
+            _ => match value.strip_prefix("mcp:") {
+                Some(profile) if !profile.is_empty() => Ok(Scope::McpProfile(profile.to_string())),
+                _ => Err(format!(
+                    "Unknown scope '{}' (expected chat, tools:read, tools:execute, admin or mcp:<profile>)",
+                    value
+                )),
+            },
+        }
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.to_string()
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Chat => write!(f, "chat"),
+            Scope::ToolsRead => write!(f, "tools:read"),
+            Scope::ToolsExecute => write!(f, "tools:execute"),
+            Scope::Admin => write!(f, "admin"),
+            Scope::McpProfile(profile) => write!(f, "mcp:{}", profile),
+        }
+    }
+}
+
+/// Scope a key needs for `method path`; `None` for reads open to any key
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let reads = *method == Method::GET || *method == Method::HEAD;
+
+    if path.starts_with("/admin") || path.starts_with("/groups-admin") {
+        return Some(Scope::Admin);
+    }
+    if path.starts_with("/api/chat") || path == "/ws" {
+        return Some(Scope::Chat);
+    }
+    if let Some(rest) = path.strip_prefix("/mcp/p/") {
+        let profile = rest.split('/').next().unwrap_or_default();
+        return Some(Scope::McpProfile(profile.to_string()));
+    }
+    if path.starts_with("/mcp") || path == "/jsonrpc" || path == "/rpc" {
+        return Some(Scope::McpProfile("*".to_string()));
+    }
+    if path.starts_with("/api/macros") && !reads {
+        return Some(Scope::Admin);
+    }
+    // Switching the provider or model changes it for every caller
+    if path.starts_with("/api/llm") {
+        return Some(if reads { Scope::Chat } else { Scope::Admin });
+    }
+    if path == "/api/events" {
+        return Some(Scope::ToolsRead);
+    }
+
+    const TOOL_PATHS: &[&str] = &[
+        "/api/tool",
+        "/api/jobs",
+        "/api/macros",
+        "/api/audit",
+        "/api/cache",
+        "/api/limits",
+        "/api/agents",
+    ];
+    if TOOL_PATHS.iter().any(|prefix| path.starts_with(prefix)) {
+        return Some(if reads { Scope::ToolsRead } else { Scope::ToolsExecute });
+    }
+    // Deny by default: unlisted changes are for admin keys
+    if reads {
+        None
+    } else {
+        Some(Scope::Admin)
+    }
+}
+
+/// A key as shown by the admin API; never includes the secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    /// Leading characters of the key, to recognize it
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub rotated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires| expires > now)
+    }
+
+    /// Whether the key grants `required`
+    pub fn allows(&self, required: &Scope) -> bool {
+        self.scopes.iter().any(|scope| scope.satisfies(required))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKey {
+    #[serde(flatten)]
+    key: ApiKey,
+    /// Hex SHA-256 of the key
+    hash: String,
+}
+
+/// Body of `POST /admin/api-keys`
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKey {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub enum ApiKeyError {
+    Invalid(String),
+    NotFound,
+    /// Another unrevoked key already has this name
+    Conflict(String),
+}
+
+impl fmt::Display for ApiKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiKeyError::Invalid(reason) => write!(f, "{}", reason),
+            ApiKeyError::NotFound => write!(f, "API key not found"),
+            ApiKeyError::Conflict(name) => write!(f, "An API key named '{}' already exists", name),
+        }
+    }
+}
+
+impl std::error::Error for ApiKeyError {}
+
+fn hash_key(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn generate_secret() -> String {
+    let random: String = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(KEY_RANDOM_LEN)
+        .map(char::from)
+        .collect();
+    format!("{}{}", KEY_PREFIX, random)
+}
+
+pub struct ApiKeyStore {
+    path: Option<PathBuf>,
+    keys: RwLock<HashMap<String, StoredKey>>,
+}
+
+impl ApiKeyStore {
+    /// Open the key store at its standard location
+    pub async fn open() -> Self {
+        Self::open_at(Some(PathBuf::from(API_KEYS_PATH))).await
+    }
+
+    /// `None` keeps keys in memory only
+    pub async fn open_at(path: Option<PathBuf>) -> Self {
+        let mut keys = HashMap::new();
+        if let Some(path) = &path {
+            if let Ok(content) = tokio::fs::read_to_string(path).await {
+                let mut raw = content;
+                match unsafe { simd_json::from_str::<Vec<StoredKey>>(&mut raw) } {
+                    Ok(stored) => {
+                        keys = stored.into_iter().map(|s| (s.key.id.clone(), s)).collect();
+                        info!("Loaded {} API keys from {}", keys.len(), path.display());
+                    }
+                    Err(e) => warn!("Invalid API key store {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        Self {
+            path,
+            keys: RwLock::new(keys),
+        }
+    }
+
+    /// All keys, newest first, including revoked and expired ones
+    pub async fn list(&self) -> Vec<ApiKey> {
+        let mut keys: Vec<ApiKey> = self.keys.read().await.values().map(|s| s.key.clone()).collect();
+        keys.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        keys
+    }
+
+    /// Create a key; returns it with its secret, which is not stored
+    pub async fn create(&self, request: CreateApiKey) -> Result<(ApiKey, String), ApiKeyError> {
+        let name = request.name.trim();
+        if name.is_empty() {
+            return Err(ApiKeyError::Invalid("API key name must not be empty".to_string()));
+        }
+        if request.scopes.is_empty() {
+            return Err(ApiKeyError::Invalid("API key needs at least one scope".to_string()));
+        }
+        let now = Utc::now();
+        if request.expires_at.is_some_and(|expires| expires <= now) {
+            return Err(ApiKeyError::Invalid("expires_at is in the past".to_string()));
+        }
+
+        let secret = generate_secret();
+        let key = ApiKey {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            prefix: secret[..DISPLAY_PREFIX_LEN].to_string(),
+            scopes: request.scopes,
+            created_at: now,
+            expires_at: request.expires_at,
+            last_used_at: None,
+            rotated_at: None,
+            revoked_at: None,
+        };
+        {
+            // Policies select keys by name, so names must be unambiguous
+            let mut keys = self.keys.write().await;
+            if keys.values().any(|stored| stored.key.revoked_at.is_none() && stored.key.name == key.name) {
+                return Err(ApiKeyError::Conflict(key.name));
+            }
+            keys.insert(
+                key.id.clone(),
+                StoredKey {
+                    key: key.clone(),
+                    hash: hash_key(&secret),
+                },
+            );
+        }
+        self.save().await;
+
+        info!("API key created: {} ({}) scopes={:?}", key.name, key.id, key.scopes);
+        Ok((key, secret))
+    }
+
+    /// Replace a key's secret; the old one stops working immediately
+    pub async fn rotate(&self, id: &str) -> Result<(ApiKey, String), ApiKeyError> {
+        let secret = generate_secret();
+        let key = {
+            let mut keys = self.keys.write().await;
+            let stored = keys.get_mut(id).ok_or(ApiKeyError::NotFound)?;
+            if stored.key.revoked_at.is_some() {
+                return Err(ApiKeyError::Invalid("A revoked API key cannot be rotated".to_string()));
+            }
+            stored.hash = hash_key(&secret);
+            stored.key.prefix = secret[..DISPLAY_PREFIX_LEN].to_string();
+            stored.key.rotated_at = Some(Utc::now());
+            stored.key.clone()
+        };
+        self.save().await;
+
+        info!("API key rotated: {} ({})", key.name, key.id);
+        Ok((key, secret))
+    }
+
+    /// Revoke a key; it stays listed for the record
+    pub async fn revoke(&self, id: &str) -> Result<ApiKey, ApiKeyError> {
+        let key = {
+            let mut keys = self.keys.write().await;
+            let stored = keys.get_mut(id).ok_or(ApiKeyError::NotFound)?;
+            stored.key.revoked_at.get_or_insert_with(Utc::now);
+            stored.key.clone()
+        };
+        self.save().await;
+
+        info!("API key revoked: {} ({})", key.name, key.id);
+        Ok(key)
+    }
+
+    /// The active key matching `secret`, recording its use
+    pub async fn authenticate(&self, secret: &str) -> Option<ApiKey> {
+        let hash = hash_key(secret);
+        let now = Utc::now();
+        let (key, persist) = {
+            let mut keys = self.keys.write().await;
+            let stored = keys.values_mut().find(|s| s.hash == hash)?;
+            if !stored.key.is_active(now) {
+                warn!("Inactive API key presented: {} ({})", stored.key.name, stored.key.id);
+                return None;
+            }
+            let persist = stored
+                .key
+                .last_used_at
+                .is_none_or(|last| (now - last).num_seconds() >= LAST_USED_PERSIST_SECS);
+            stored.key.last_used_at = Some(now);
+            (stored.key.clone(), persist)
+        };
+        if persist {
+            self.save().await;
+        }
+        Some(key)
+    }
+
+    async fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let stored: Vec<StoredKey> = self.keys.read().await.values().cloned().collect();
+        let content = match simd_json::to_string_pretty(&stored) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Failed to serialize API keys: {}", e);
+                return;
+            }
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.ok();
+        }
+        if let Err(e) = tokio::fs::write(path, content).await {
+            warn!("Failed to write API keys to {}: {}", path.display(), e);
+            return;
+        }
+        // Hashes only, but still nothing other users need to read
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await {
+            warn!("Failed to restrict permissions of {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_key_lifecycle() {
+        let store = ApiKeyStore::open_at(None).await;
+        let (key, secret) = store
+            .create(CreateApiKey {
+                name: "ci".to_string(),
+                scopes: vec![Scope::ToolsExecute],
+                expires_at: None,
+            })
+            .await
+            .unwrap();
+        assert!(secret.starts_with(KEY_PREFIX));
+        assert!(secret.starts_with(&key.prefix));
+        let duplicate = CreateApiKey { name: "ci".to_string(), scopes: vec![Scope::Chat], expires_at: None };
+        assert!(matches!(store.create(duplicate).await, Err(ApiKeyError::Conflict(_))));
+
+        let found = store.authenticate(&secret).await.unwrap();
+        assert_eq!(found.id, key.id);
+        assert!(found.last_used_at.is_some());
+        assert!(store.authenticate("opk_not-a-key").await.is_none());
+
+        let (_, rotated) = store.rotate(&key.id).await.unwrap();
+        assert!(store.authenticate(&secret).await.is_none());
+        assert!(store.authenticate(&rotated).await.is_some());
+
+        store.revoke(&key.id).await.unwrap();
+        assert!(store.authenticate(&rotated).await.is_none());
+    }
+
+    #[test]
+    fn test_scopes_cover_their_endpoints() {
+        let key = ApiKey {
+            id: String::new(),
+            name: "mcp".to_string(),
+            prefix: String::new(),
+            scopes: vec!["tools:execute".to_string().try_into().unwrap(), Scope::McpProfile("readonly".to_string())],
+            created_at: Utc::now(),
+            expires_at: None,
+            last_used_at: None,
+            rotated_at: None,
+            revoked_at: None,
+        };
+        let allowed = |method: Method, path: &str| required_scope(&method, path).is_none_or(|s| key.allows(&s));
+
+        assert!(allowed(Method::GET, "/api/tools"));
+        assert!(allowed(Method::POST, "/api/tools/ovs_list_bridges/execute"));
+        assert!(allowed(Method::POST, "/mcp/p/readonly"));
+        assert!(!allowed(Method::POST, "/mcp/p/full"));
+        assert!(!allowed(Method::POST, "/api/chat"));
+        assert!(!allowed(Method::DELETE, "/api/macros/create_internal_bridge"));
+        assert!(!allowed(Method::POST, "/admin/registry/refresh"));
+        assert!(allowed(Method::GET, "/api/health"));
+        assert!(allowed(Method::POST, "/api/agents"));
+        assert!(!allowed(Method::POST, "/api/llm/model"));
+        assert!(!allowed(Method::POST, "/api/privacy/credentials"));
+        assert!(Scope::try_from("tools:write".to_string()).is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Execution Audit - who ran which tool, with what, and how it went
+//!
+//! Every execution through [`crate::executor::ToolExecutor`] produces one
+//! [`AuditRecord`]: the caller (source path, IP, access zone, API key name,
+//! MCP session), the arguments with secrets redacted, duration and outcome.
+//! Requests the access policy denies are recorded too, as failed
+//! `METHOD /path` records (see [`crate::access_policy`]), and calls answered
+//! from the result cache as `cached` records with zero duration.
+//! Records are appended to `/var/lib/op-dbus/audit.jsonl` and the most recent
+//! ones are kept in memory for `GET /api/audit`.
+//!
+//! The caller's network identity is attached by the security middleware for
+//! the lifetime of the request (see [`with_identity`]), so code deep inside a
+//! handler - the orchestrator, an MCP dispatcher - does not need it threaded
+//! through.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::mcp_logging;
+use crate::tool_profiles;
+
+const AUDIT_LOG_PATH: &str = "/var/lib/op-dbus/audit.jsonl";
+
+/// Records kept in memory (and reloaded from the log at startup)
+const MAX_RECORDS: usize = 5000;
+
+/// Default page size for `GET /api/audit`
+const DEFAULT_QUERY_LIMIT: usize = 100;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Argument keys whose values never reach the audit log, matched against
+/// the whole key or a run of its `_`/`-` separated segments
+const SECRET_KEYS: &[&str] = &[
+    "password", "passwd", "passphrase", "secret", "secrets", "token", "api_key", "apikey",
+    "authorization", "credential", "credentials", "private_key", "privatekey",
+];
+
+/// Network identity of a request, attached by the security middleware
+#[derive(Debug, Clone, Default)]
+pub struct ClientIdentity {
+    pub ip: String,
+    /// AccessZone as resolved for this request
+    pub zone: String,
+    /// Name of the authenticated API key; never set from an unverified token
+    pub api_key: Option<String>,
+}
+
+tokio::task_local! {
+    static CURRENT_IDENTITY: ClientIdentity;
+}
+
+/// Run `fut` with `identity` as the caller of any tool it executes
+pub async fn with_identity<F: Future>(identity: ClientIdentity, fut: F) -> F::Output {
+    CURRENT_IDENTITY.scope(identity, fut).await
+}
+
+/// Identity of the request running on this task, to carry into spawned work
+pub fn current_identity() -> Option<ClientIdentity> {
+    CURRENT_IDENTITY.try_with(|i| i.clone()).ok()
+}
+
+/// `tokio::spawn` keeping the current request identity and tool profile, so
+/// tools the task executes are attributed to (and authorized for) the same
+/// caller
+pub fn spawn_with_identity<F>(fut: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let identity = current_identity();
+    let profile = tool_profiles::current_profile();
+    tokio::spawn(tool_profiles::with_profile_name(profile, async move {
+        match identity {
+            Some(identity) => with_identity(identity, fut).await,
+            None => fut.await,
+        }
+    }))
+}
+
+impl ClientIdentity {
+    /// Identity of a transport that never sees HTTP (`--mcp-stdio`): the
+    /// local user running the process
+    pub fn local() -> Self {
+        Self {
+            ip: "127.0.0.1".to_string(),
+            zone: "Localhost".to_string(),
+            api_key: None,
+        }
+    }
+}
+
+/// Who executed a tool
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Caller {
+    /// Execution path: `api`, `job`, `mcp`, `mcp-compact`, `mcp-agents`,
+    /// `chat`; `http` for requests denied by the access policy
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// Tool profile of a `/mcp/p/{profile}` call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl Caller {
+    /// The caller of the current task: its request identity and MCP session
+    pub fn current(source: &str) -> Self {
+        let identity = CURRENT_IDENTITY.try_with(|i| i.clone()).ok();
+        Self {
+            source: source.to_string(),
+            ip: identity.as_ref().map(|i| i.ip.clone()),
+            zone: identity.as_ref().map(|i| i.zone.clone()),
+            api_key: identity.and_then(|i| i.api_key),
+            session: mcp_logging::current_session().map(|s| s.id.clone()),
+            profile: tool_profiles::current_profile(),
+        }
+    }
+}
+
+/// Copy of `arguments` with secret-looking fields replaced
+pub fn redact(arguments: &Value) -> Value {
+    if let Some(obj) = arguments.as_object() {
+        let mut redacted = json!({});
+        if let Some(out) = redacted.as_object_mut() {
+            for (key, value) in obj.iter() {
+                let value = if is_secret_key(key) {
+                    json!(REDACTED)
+                } else {
+                    redact(value)
+                };
+                out.insert(key.clone(), value);
+            }
+        }
+        redacted
+    } else if let Some(items) = arguments.as_array() {
+        Value::from(items.iter().map(redact).collect::<Vec<_>>())
+    } else {
+        arguments.clone()
+    }
+}
+
+/// Whether `key` names a secret: `access_token` and `X-Api-Key` do,
+/// `max_tokens` does not
+fn is_secret_key(key: &str) -> bool {
+    let segments = format!("_{}_", key.to_lowercase().replace('-', "_"));
+    SECRET_KEYS.iter().any(|secret| segments.contains(&format!("_{}_", secret)))
+}
+
+/// One tool execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Same id as the execution's job
+    pub id: Uuid,
+    pub tool_name: String,
+    pub caller: Caller,
+    /// Redacted
+    pub arguments: Value,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Answered from the result cache; the tool did not run
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+impl AuditRecord {
+    pub fn new(
+        id: Uuid,
+        tool_name: &str,
+        caller: Caller,
+        arguments: Value,
+        started_at: DateTime<Utc>,
+        duration: Duration,
+        outcome: &Result<Value, String>,
+    ) -> Self {
+        Self {
+            id,
+            tool_name: tool_name.to_string(),
+            caller,
+            arguments,
+            started_at,
+            duration_ms: duration.as_millis() as u64,
+            success: outcome.is_ok(),
+            error: outcome.as_ref().err().cloned(),
+            cached: false,
+        }
+    }
+}
+
+/// Query for `GET /api/audit`
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub tool: Option<String>,
+    pub source: Option<String>,
+    pub ip: Option<String>,
+    pub zone: Option<String>,
+    pub session: Option<String>,
+    pub success: Option<bool>,
+    /// Only executions started at or after this time (RFC 3339)
+    pub since: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        let caller = &record.caller;
+        self.tool.as_deref().is_none_or(|tool| record.tool_name == tool)
+            && self.source.as_deref().is_none_or(|source| caller.source == source)
+            && self.ip.as_deref().is_none_or(|ip| caller.ip.as_deref() == Some(ip))
+            && self.zone.as_deref().is_none_or(|zone| caller.zone.as_deref() == Some(zone))
+            && self.session.as_deref().is_none_or(|session| caller.session.as_deref() == Some(session))
+            && self.success.is_none_or(|success| record.success == success)
+            && self.since.is_none_or(|since| record.started_at >= since)
+    }
+}
+
+/// Append-only audit log with an in-memory index of recent records
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    recent: Mutex<VecDeque<AuditRecord>>,
+}
+
+impl AuditLog {
+    /// Open the audit log at its standard location, reloading recent records
+    pub async fn open() -> Self {
+        Self::open_at(Some(PathBuf::from(AUDIT_LOG_PATH))).await
+    }
+
+    /// `None` keeps records in memory only
+    pub async fn open_at(path: Option<PathBuf>) -> Self {
+        let mut recent = VecDeque::new();
+        if let Some(path) = &path {
+            if let Ok(content) = tokio::fs::read_to_string(path).await {
+                for line in content.lines() {
+                    let mut line = line.to_string();
+                    if let Ok(record) = unsafe { simd_json::from_str::<AuditRecord>(&mut line) } {
+                        if recent.len() == MAX_RECORDS {
+                            recent.pop_front();
+                        }
+                        recent.push_back(record);
+                    }
+                }
+                info!("Loaded {} audit records from {}", recent.len(), path.display());
+            }
+        }
+
+        Self {
+            path,
+            recent: Mutex::new(recent),
+        }
+    }
+
+    pub async fn record(&self, record: AuditRecord) {
+        info!(
+            "audit: {} by {} ip={} zone={} session={} ok={} {}ms",
+            record.tool_name,
+            record.caller.source,
+            record.caller.ip.as_deref().unwrap_or("-"),
+            record.caller.zone.as_deref().unwrap_or("-"),
+            record.caller.session.as_deref().unwrap_or("-"),
+            record.success,
+            record.duration_ms
+        );
+
+        if let Some(path) = &self.path {
+            if let Err(e) = append_line(path, &record).await {
+                warn!("Failed to write audit record to {}: {}", path.display(), e);
+            }
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == MAX_RECORDS {
+            recent.pop_front();
+        }
+        recent.push_back(record);
+    }
+
+    /// Records matching `query`, newest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditRecord> {
+        self.recent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|record| query.matches(record))
+            .take(query.limit.unwrap_or(DEFAULT_QUERY_LIMIT))
+            .cloned()
+            .collect()
+    }
+}
+
+async fn append_line(path: &PathBuf, record: &AuditRecord) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut line = simd_json::to_string(record)?;
+    line.push('\n');
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_nested_secrets() {
+        let arguments = json!({
+            "user": "admin",
+            "Password": "hunter2",
+            "headers": { "Authorization": "Bearer abc", "accept": "json" },
+            "peers": [{ "name": "wg0", "private_key": "xyz" }],
+            "llm": { "max_tokens": 512, "access_token": "abc", "X-Api-Key": "opk_1" }
+        });
+
+        let redacted = redact(&arguments);
+        assert_eq!(
+            redacted,
+            json!({
+                "user": "admin",
+                "Password": REDACTED,
+                "headers": { "Authorization": REDACTED, "accept": "json" },
+                "peers": [{ "name": "wg0", "private_key": REDACTED }],
+                "llm": { "max_tokens": 512, "access_token": REDACTED, "X-Api-Key": REDACTED }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_filters_newest_first() {
+        let log = AuditLog::open_at(None).await;
+        let caller = |source: &str| Caller { source: source.to_string(), ..Default::default() };
+        let now = Utc::now();
+
+        for (tool, source, outcome) in [
+            ("file_read", "api", Ok(json!({}))),
+            ("file_read", "mcp", Err("denied".to_string())),
+            ("ovs_list_bridges", "mcp", Ok(json!([]))),
+        ] {
+            let record = AuditRecord::new(Uuid::new_v4(), tool, caller(source), json!({}), now, Duration::ZERO, &outcome);
+            log.record(record).await;
+        }
+
+        let mcp = log.query(&AuditQuery { source: Some("mcp".to_string()), ..Default::default() });
+        assert_eq!(mcp.len(), 2);
+        assert_eq!(mcp[0].tool_name, "ovs_list_bridges");
+
+        let failed = log.query(&AuditQuery { success: Some(false), ..Default::default() });
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].error.as_deref(), Some("denied"));
+    }
+}
@@ -0,0 +1,504 @@
+//! Batch Tool Execution - ordered steps with references and rollback
+//!
+//! `POST /api/tools/batch` runs a list of `{tool_name, arguments}` steps,
+//! sequentially or in parallel. In sequential mode a step's arguments may
+//! reference earlier outputs: a string that is exactly `${steps[0].result.bridge}`
+//! is replaced by that value, and references inside longer strings are
+//! interpolated as text. Other `${...}` text (`${HOME}` in a shell command)
+//! is passed through untouched.
+//!
+//! Each finished step is reported as a `progress` output chunk (see
+//! [`crate::tool_output`]), so streaming a macro shows its steps as they run.
+//!
+//! When a step fails and the batch stops, the steps that already succeeded
+//! are compensated in reverse order by calling their inverse tool with the
+//! same arguments (those the inverse's schema accepts). A tool's inverse is
+//! the one it names with `"x-inverse"` in its input schema, else the one
+//! mapped in `/etc/op-dbus/tool-inverses.json`:
+//!
+//! ```json
+//! { "ovs_create_bridge": "ovs_delete_bridge", "dbus_systemd_start_unit": "" }
+//! ```
+//!
+//! which extends the built-in map (OVS bridges, systemd units, link state);
+//! an empty value removes a built-in entry. Tools without an inverse are left
+//! as they are.
+
+use op_tools::ToolRegistry;
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::executor::ToolExecutor;
+use crate::tool_output;
+
+/// Upper bound on steps per batch
+const MAX_STEPS: usize = 100;
+
+const REFERENCE_OPEN: &str = "${";
+
+/// Opening of a step reference
+const STEP_REFERENCE: &str = "${steps[";
+
+/// Output stream step reports are emitted on
+const PROGRESS_STREAM: &str = "progress";
+
+const TOOL_INVERSES_PATH: &str = "/etc/op-dbus/tool-inverses.json";
+
+/// Built-in compensations for tools that do not declare `x-inverse`
+const DEFAULT_INVERSES: &[(&str, &str)] = &[
+    ("ovs_create_bridge", "ovs_delete_bridge"),
+    ("dbus_systemd_start_unit", "dbus_systemd_stop_unit"),
+    ("dbus_systemd_stop_unit", "dbus_systemd_start_unit"),
+    ("dbus_systemd_enable_unit", "dbus_systemd_disable_unit"),
+    ("dbus_systemd_disable_unit", "dbus_systemd_enable_unit"),
+    ("rtnetlink_link_up", "rtnetlink_link_down"),
+    ("rtnetlink_link_down", "rtnetlink_link_up"),
+];
+
+lazy_static::lazy_static! {
+    /// Tool name -> the tool that undoes it
+    static ref INVERSES: HashMap<String, String> = load_inverses();
+}
+
+fn load_inverses() -> HashMap<String, String> {
+    let mut inverses: HashMap<String, String> = DEFAULT_INVERSES
+        .iter()
+        .map(|(tool, inverse)| (tool.to_string(), inverse.to_string()))
+        .collect();
+    let Ok(mut raw) = std::fs::read_to_string(TOOL_INVERSES_PATH) else {
+        return inverses;
+    };
+    match unsafe { simd_json::from_str::<HashMap<String, String>>(&mut raw) } {
+        Ok(configured) => {
+            info!("Loaded {} tool inverses from {}", configured.len(), TOOL_INVERSES_PATH);
+            for (tool, inverse) in configured {
+                if inverse.is_empty() {
+                    inverses.remove(&tool);
+                } else {
+                    inverses.insert(tool, inverse);
+                }
+            }
+        }
+        Err(e) => warn!("Invalid tool inverses in {}: {}", TOOL_INVERSES_PATH, e),
+    }
+    inverses
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchMode {
+    #[default]
+    Sequential,
+    /// All steps start at once; references are not available
+    Parallel,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchStep {
+    pub tool_name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    pub steps: Vec<BatchStep>,
+    #[serde(default)]
+    pub mode: BatchMode,
+    /// Skip the remaining steps after the first failure (sequential mode)
+    #[serde(default = "default_true")]
+    pub stop_on_error: bool,
+    /// Compensate succeeded steps when the batch fails
+    #[serde(default = "default_true")]
+    pub rollback: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Succeeded,
+    Failed,
+    Skipped,
+    /// Succeeded, then undone by its inverse
+    Compensated,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Compensation {
+    pub tool_name: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepResult {
+    pub index: usize,
+    pub tool_name: String,
+    pub status: StepStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub execution_time_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compensation: Option<Compensation>,
+    /// Arguments after reference resolution, used for compensation
+    #[serde(skip)]
+    arguments: Value,
+}
+
+impl StepResult {
+    fn skipped(index: usize, tool_name: &str) -> Self {
+        Self {
+            index,
+            tool_name: tool_name.to_string(),
+            status: StepStatus::Skipped,
+            result: None,
+            error: None,
+            execution_time_ms: 0,
+            compensation: None,
+            arguments: json!({}),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub success: bool,
+    pub mode: BatchMode,
+    pub steps: Vec<StepResult>,
+    pub rolled_back: bool,
+    pub execution_time_ms: u64,
+}
+
+/// Check a batch before anything runs
+pub fn validate(request: &BatchRequest) -> Result<(), String> {
+    if request.steps.is_empty() {
+        return Err("Batch has no steps".to_string());
+    }
+    if request.steps.len() > MAX_STEPS {
+        return Err(format!("Batch has {} steps (max {})", request.steps.len(), MAX_STEPS));
+    }
+    if request.mode == BatchMode::Parallel && request.steps.iter().any(|s| has_reference(&s.arguments)) {
+        return Err("Step references (${steps[N]...}) require sequential mode".to_string());
+    }
+    Ok(())
+}
+
+/// Run a validated batch
+pub async fn run(
+    executor: &Arc<ToolExecutor>,
+    registry: &ToolRegistry,
+    request: BatchRequest,
+) -> BatchResponse {
+    let started = Instant::now();
+    let mode = request.mode;
+    info!("Batch of {} steps ({:?})", request.steps.len(), mode);
+
+    let mut steps = match mode {
+        BatchMode::Sequential => run_sequential(executor, &request).await,
+        BatchMode::Parallel => {
+            let runs = request
+                .steps
+                .iter()
+                .enumerate()
+                .map(|(index, step)| run_step(executor, index, &step.tool_name, step.arguments.clone()));
+            futures::future::join_all(runs).await
+        }
+    };
+
+    let failed = steps.iter().any(|s| s.status == StepStatus::Failed);
+    let stopped = failed && (request.stop_on_error || mode == BatchMode::Parallel);
+    let rolled_back = stopped && request.rollback && compensate(executor, registry, &mut steps).await;
+
+    BatchResponse {
+        success: !failed,
+        mode,
+        steps,
+        rolled_back,
+        execution_time_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+async fn run_sequential(executor: &Arc<ToolExecutor>, request: &BatchRequest) -> Vec<StepResult> {
+    let mut steps: Vec<StepResult> = Vec::with_capacity(request.steps.len());
+    let mut halted = false;
+
+    for (index, step) in request.steps.iter().enumerate() {
+        if halted {
+            steps.push(StepResult::skipped(index, &step.tool_name));
+            continue;
+        }
+
+        let result = match resolve(&step.arguments, &steps) {
+            Ok(arguments) => run_step(executor, index, &step.tool_name, arguments).await,
+            Err(e) => StepResult {
+                status: StepStatus::Failed,
+                error: Some(e),
+                ..StepResult::skipped(index, &step.tool_name)
+            },
+        };
+        halted = result.status == StepStatus::Failed && request.stop_on_error;
+        steps.push(result);
+    }
+    steps
+}
+
+async fn run_step(executor: &Arc<ToolExecutor>, index: usize, tool_name: &str, arguments: Value) -> StepResult {
+    let started = Instant::now();
+    let outcome = executor.execute("batch", tool_name, arguments.clone()).await;
+    let (status, result, error) = match outcome {
+        Ok(result) => (StepStatus::Succeeded, Some(result), None),
+        Err(e) => (StepStatus::Failed, None, Some(e.to_string())),
+    };
+    let elapsed = started.elapsed();
+    tool_output::emit(
+        PROGRESS_STREAM,
+        match &error {
+            None => format!("step {} {}: ok ({} ms)", index, tool_name, elapsed.as_millis()),
+            Some(e) => format!("step {} {}: failed: {}", index, tool_name, e),
+        },
+    );
+
+    StepResult {
+        index,
+        tool_name: tool_name.to_string(),
+        status,
+        result,
+        error,
+        execution_time_ms: elapsed.as_millis() as u64,
+        compensation: None,
+        arguments,
+    }
+}
+
+/// Undo succeeded steps, newest first. Returns whether anything was compensated.
+async fn compensate(executor: &Arc<ToolExecutor>, registry: &ToolRegistry, steps: &mut [StepResult]) -> bool {
+    let mut compensated = false;
+
+    for step in steps.iter_mut().rev().filter(|s| s.status == StepStatus::Succeeded) {
+        let Some(inverse) = inverse_of(registry, &step.tool_name).await else {
+            continue;
+        };
+        let arguments = match registry.get_definition(&inverse).await {
+            Some(definition) => arguments_for(&definition.input_schema, &step.arguments),
+            None => step.arguments.clone(),
+        };
+
+        info!("Batch rollback: {} compensates step {} ({})", inverse, step.index, step.tool_name);
+        let outcome = executor.execute("batch-rollback", &inverse, arguments).await;
+        if let Err(e) = &outcome {
+            warn!("Compensation {} for step {} failed: {}", inverse, step.index, e);
+        }
+        if outcome.is_ok() {
+            step.status = StepStatus::Compensated;
+        }
+        step.compensation = Some(Compensation {
+            tool_name: inverse,
+            success: outcome.is_ok(),
+            error: outcome.err().map(|e| e.to_string()),
+        });
+        compensated = true;
+    }
+    compensated
+}
+
+/// The registered tool that undoes `tool_name`: its `x-inverse`, else the
+/// configured one
+pub async fn inverse_of(registry: &ToolRegistry, tool_name: &str) -> Option<String> {
+    let inverse = registry
+        .get_definition(tool_name)
+        .await
+        .and_then(|d| d.input_schema.get("x-inverse").and_then(|i| i.as_str()).map(String::from))
+        .or_else(|| INVERSES.get(tool_name).cloned())?;
+    registry.get(&inverse).await.map(|_| inverse)
+}
+
+/// Keep only the arguments the inverse's schema accepts (all, if it lists none)
+fn arguments_for(schema: &Value, arguments: &Value) -> Value {
+    let (Some(properties), Some(args)) = (
+        schema.get("properties").and_then(|p| p.as_object()),
+        arguments.as_object(),
+    ) else {
+        return arguments.clone();
+    };
+    if properties.is_empty() {
+        return arguments.clone();
+    }
+
+    let mut filtered = json!({});
+    if let Some(obj) = filtered.as_object_mut() {
+        for (key, value) in args.iter().filter(|(key, _)| properties.contains_key(key.as_str())) {
+            obj.insert(key.clone(), value.clone());
+        }
+    }
+    filtered
+}
+
+fn has_reference(value: &Value) -> bool {
+    if let Some(s) = value.as_str() {
+        s.contains(STEP_REFERENCE)
+    } else if let Some(obj) = value.as_object() {
+        obj.values().any(has_reference)
+    } else if let Some(items) = value.as_array() {
+        items.iter().any(has_reference)
+    } else {
+        false
+    }
+}
+
+/// Replace `${steps[N].result...}` references with earlier outputs
+fn resolve(value: &Value, steps: &[StepResult]) -> Result<Value, String> {
+    substitute(value, STEP_REFERENCE, &|reference| lookup(reference, steps))
+}
+
+/// Replace every reference starting with `open` (`${steps[`, `${params.`)
+/// in `value` using `lookup`, which gets the text between `${` and `}`. A
+/// string that is a lone reference takes the looked-up value's type;
+/// references inside longer strings are interpolated as text.
+pub(crate) fn substitute(
+    value: &Value,
+    open: &str,
+    lookup: &dyn Fn(&str) -> Result<Value, String>,
+) -> Result<Value, String> {
+    if let Some(s) = value.as_str() {
+        return substitute_str(s, open, lookup);
+    }
+    if let Some(obj) = value.as_object() {
+        let mut resolved = json!({});
+        if let Some(out) = resolved.as_object_mut() {
+            for (key, item) in obj.iter() {
+                out.insert(key.clone(), substitute(item, open, lookup)?);
+            }
+        }
+        return Ok(resolved);
+    }
+    if let Some(items) = value.as_array() {
+        let items = items.iter().map(|item| substitute(item, open, lookup)).collect::<Result<Vec<_>, _>>()?;
+        return Ok(Value::from(items));
+    }
+    Ok(value.clone())
+}
+
+fn substitute_str(s: &str, open: &str, lookup: &dyn Fn(&str) -> Result<Value, String>) -> Result<Value, String> {
+    if s.starts_with(open) {
+        if let Some(inner) = s.strip_prefix(REFERENCE_OPEN).and_then(|r| r.strip_suffix('}')) {
+            if !inner.contains('}') {
+                return lookup(inner);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find(open) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + REFERENCE_OPEN.len()..];
+        let end = after.find('}').ok_or_else(|| format!("Unterminated reference in '{}'", s))?;
+        let value = lookup(&after[..end])?;
+        match value.as_str() {
+            Some(text) => out.push_str(text),
+            None => out.push_str(&simd_json::to_string(&value).unwrap_or_default()),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(json!(out))
+}
+
+/// Look up `steps[N].result.path[0].to.field`
+fn lookup(reference: &str, steps: &[StepResult]) -> Result<Value, String> {
+    let invalid = || format!("Invalid reference '${{{}}}' (expected steps[N].result...)", reference);
+
+    let rest = reference.trim().strip_prefix("steps[").ok_or_else(invalid)?;
+    let close = rest.find(']').ok_or_else(invalid)?;
+    let index: usize = rest[..close].parse().map_err(|_| invalid())?;
+    let path = rest[close + 1..].strip_prefix(".result").ok_or_else(invalid)?;
+
+    let step = steps
+        .get(index)
+        .ok_or_else(|| format!("Reference to step {} which has not run yet", index))?;
+    let mut current = step
+        .result
+        .as_ref()
+        .ok_or_else(|| format!("Step {} ({}) has no result", index, step.tool_name))?;
+
+    let mut path = path;
+    while !path.is_empty() {
+        if let Some(after) = path.strip_prefix('[') {
+            let close = after.find(']').ok_or_else(invalid)?;
+            let i: usize = after[..close].parse().map_err(|_| invalid())?;
+            current = current
+                .as_array()
+                .and_then(|items| items.get(i))
+                .ok_or_else(|| format!("'${{{}}}': no index {}", reference, i))?;
+            path = &after[close + 1..];
+        } else if let Some(after) = path.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let key = &after[..end];
+            current = current
+                .get(key)
+                .ok_or_else(|| format!("'${{{}}}': no field '{}'", reference, key))?;
+            path = &after[end..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(current.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn succeeded(index: usize, result: Value) -> StepResult {
+        StepResult {
+            status: StepStatus::Succeeded,
+            result: Some(result),
+            ..StepResult::skipped(index, "ovs_create_bridge")
+        }
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let steps = vec![succeeded(0, json!({ "bridge": "br0", "ports": [{ "name": "eth0" }], "mtu": 1500 }))];
+
+        let arguments = json!({
+            "bridge": "${steps[0].result.bridge}",
+            "port": "${steps[0].result.ports[0].name}",
+            "mtu": "${steps[0].result.mtu}",
+            "label": "on ${steps[0].result.bridge} (mtu ${steps[0].result.mtu})"
+        });
+        let resolved = resolve(&arguments, &steps).unwrap();
+        assert_eq!(
+            resolved,
+            json!({ "bridge": "br0", "port": "eth0", "mtu": 1500, "label": "on br0 (mtu 1500)" })
+        );
+
+        assert!(resolve(&json!("${steps[1].result}"), &steps).is_err());
+        assert!(resolve(&json!("${steps[0].result.missing}"), &steps).is_err());
+        assert!(resolve(&json!("${steps[0].output}"), &steps).is_err());
+    }
+
+    #[test]
+    fn test_other_placeholders_pass_through() {
+        let steps = vec![succeeded(0, json!({ "bridge": "br0" }))];
+
+        let command = json!({ "command": "echo ${HOME} ${steps[0].result.bridge}", "literal": "${PATH}" });
+        assert!(!has_reference(&json!("${HOME}")));
+        assert_eq!(
+            resolve(&command, &steps).unwrap(),
+            json!({ "command": "echo ${HOME} br0", "literal": "${PATH}" })
+        );
+    }
+}
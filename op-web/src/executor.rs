@@ -0,0 +1,337 @@
+//! Tool Executor - the single audited path for running tools
+//!
+//! REST (`/api/tool`, `/api/tools/:name/execute`), background jobs, the MCP
+//! surfaces (full, compact, agents) and the chat orchestrator all execute
+//! through here. Each execution is tracked as a job (`/api/jobs`, state
+//! store) and produces an audit record (`/api/audit`) naming the caller.
+//!
+//! Paths that wrap the run itself (MCP cancellation and progress) use
+//! [`ToolExecutor::begin`] and [`Execution::complete`]; the rest call
+//! [`ToolExecutor::execute`], which also answers cacheable tools from the
+//! result cache (see [`crate::tool_cache`]). Either way the tool runs under
+//! its timeout, concurrency and rate limits (see [`crate::tool_limits`]),
+//! only if the caller's access zone may use it (see [`crate::access_policy`]),
+//! and `file_*`/`shell_*` tools only within the caller's scope policy (see
+//! [`crate::scope_policy`]).
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use futures::future::{AbortHandle, Abortable};
+use op_tools::registry::ToolDefinition;
+use op_tools::ToolRegistry;
+use simd_json::OwnedValue as Value;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::access_policy::AccessPolicy;
+use crate::audit::{self, AuditLog, AuditRecord, Caller};
+use crate::jobs::{JobInfo, JobManager};
+use crate::scope_policy::ScopePolicies;
+use crate::tool_cache::{self, ToolCache};
+use crate::tool_limits::{ResolvedLimits, ToolLimiter};
+
+lazy_static::lazy_static! {
+    /// For paths without `AppState` (the agents MCP server)
+    static ref GLOBAL_EXECUTOR: RwLock<Option<Arc<ToolExecutor>>> = RwLock::new(None);
+}
+
+/// Make `executor` available through [`global`]
+pub fn install(executor: Arc<ToolExecutor>) {
+    *GLOBAL_EXECUTOR.write().unwrap() = Some(executor);
+}
+
+/// The executor installed by `AppState`, if any
+pub fn global() -> Option<Arc<ToolExecutor>> {
+    GLOBAL_EXECUTOR.read().unwrap().clone()
+}
+
+pub struct ToolExecutor {
+    registry: Arc<ToolRegistry>,
+    jobs: Arc<JobManager>,
+    audit: Arc<AuditLog>,
+    cache: Arc<ToolCache>,
+    limits: Arc<ToolLimiter>,
+    scope: Arc<ScopePolicies>,
+    access: Arc<AccessPolicy>,
+}
+
+/// What an execution does to the result cache when it completes
+enum CacheEffect {
+    /// Store a successful result
+    Store { key: String, domain: String, ttl: Duration },
+    /// A mutating tool: drop the domain's entries
+    Invalidate { domain: String },
+    None,
+}
+
+/// A tool execution in progress; finish it with [`Execution::complete`]
+pub struct Execution {
+    executor: Arc<ToolExecutor>,
+    pub id: Uuid,
+    tool_name: String,
+    caller: Caller,
+    arguments: Value,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    cache_effect: CacheEffect,
+    limits: ResolvedLimits,
+    /// Access or scope policy violation; the tool is not run
+    violation: Option<String>,
+}
+
+impl Execution {
+    /// Run the tool's future under its timeout, concurrency and rate limits,
+    /// unless the caller's access or scope policy denies it
+    pub async fn run<F>(&self, fut: F) -> anyhow::Result<Value>
+    where
+        F: Future<Output = anyhow::Result<Value>>,
+    {
+        if let Some(violation) = &self.violation {
+            return Err(anyhow!(violation.clone()));
+        }
+        self.executor
+            .limits
+            .run(&self.tool_name, &self.caller, &self.limits, fut)
+            .await
+    }
+
+    /// Record the outcome in the job store, the audit log and the result cache
+    pub async fn complete(self, outcome: Result<Value, String>) {
+        let cache = &self.executor.cache;
+        match (self.cache_effect, &outcome) {
+            (CacheEffect::Store { key, domain, ttl }, Ok(value)) => cache.put(key, domain, value.clone(), ttl),
+            (CacheEffect::Invalidate { domain }, _) => cache.invalidate_domain(&domain),
+            _ => {}
+        }
+
+        self.executor.jobs.finish(self.id, &outcome).await;
+        let record = AuditRecord::new(
+            self.id,
+            &self.tool_name,
+            self.caller,
+            self.arguments,
+            self.started_at,
+            self.started.elapsed(),
+            &outcome,
+        );
+        self.executor.audit.record(record).await;
+    }
+}
+
+impl ToolExecutor {
+    pub fn new(
+        registry: Arc<ToolRegistry>,
+        jobs: Arc<JobManager>,
+        audit: Arc<AuditLog>,
+        cache: Arc<ToolCache>,
+        limits: Arc<ToolLimiter>,
+        scope: Arc<ScopePolicies>,
+        access: Arc<AccessPolicy>,
+    ) -> Self {
+        Self { registry, jobs, audit, cache, limits, scope, access }
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub fn cache(&self) -> &ToolCache {
+        &self.cache
+    }
+
+    pub fn limits(&self) -> &ToolLimiter {
+        &self.limits
+    }
+
+    /// Cached result of an identical earlier call, for cacheable tools the
+    /// caller may run outside any scope policy; a hit is audited
+    pub async fn cached(&self, source: &str, tool_name: &str, arguments: &Value) -> Option<Value> {
+        let definition = self.registry.get_definition(tool_name).await?;
+        let caller = Caller::current(source);
+        if !self.may_use_cache(&caller, &definition) {
+            return None;
+        }
+        self.cache.ttl(&definition)?;
+        let value = self.cache.get(&tool_cache::cache_key(tool_name, arguments))?;
+        self.record_cached(caller, tool_name, arguments, &Ok(value.clone())).await;
+        Some(value)
+    }
+
+    /// Audit a call answered from the cache (or by another caller's flight)
+    async fn record_cached(&self, caller: Caller, tool_name: &str, arguments: &Value, outcome: &Result<Value, String>) {
+        let mut record = AuditRecord::new(
+            Uuid::new_v4(),
+            tool_name,
+            caller,
+            audit::redact(arguments),
+            Utc::now(),
+            Duration::ZERO,
+            outcome,
+        );
+        record.cached = true;
+        self.audit.record(record).await;
+    }
+
+    /// Whether a cached result may answer `caller`: policy checks only run
+    /// on real executions
+    fn may_use_cache(&self, caller: &Caller, definition: &ToolDefinition) -> bool {
+        !self.scope.governs(&definition.name, caller) && self.access.authorize_tool(caller, definition).is_ok()
+    }
+
+    /// Check the current caller's access and scope policies for a call,
+    /// without running or recording anything (completions)
+    pub async fn authorize(&self, source: &str, tool_name: &str, arguments: &mut Value) -> Result<(), String> {
+        let caller = Caller::current(source);
+        let definition = self.registry.get_definition(tool_name).await;
+        if let Some(definition) = &definition {
+            self.access.authorize_tool(&caller, definition)?;
+        }
+        let schema = definition.as_ref().map(|d| &d.input_schema);
+        self.scope.enforce(tool_name, schema, &caller, arguments)
+    }
+
+    /// Start tracking an execution by the current caller on `source`.
+    /// Checks the caller's access policy, and `arguments` against its scope
+    /// policy, which may rewrite them (environment sanitization).
+    pub async fn begin(self: &Arc<Self>, source: &str, tool_name: &str, arguments: &mut Value) -> Execution {
+        let caller = Caller::current(source);
+        let definition = self.registry.get_definition(tool_name).await;
+        let violation = match &definition {
+            Some(definition) => self.access.authorize_tool(&caller, definition).err(),
+            None => None,
+        }
+        .or_else(|| {
+            let schema = definition.as_ref().map(|d| &d.input_schema);
+            self.scope.enforce(tool_name, schema, &caller, arguments).err()
+        });
+        let limits = definition
+            .as_ref()
+            .map(|definition| self.limits.resolve(definition))
+            .unwrap_or_else(|| self.limits.resolve_name(tool_name));
+        let cache_effect = match definition {
+            _ if violation.is_some() => CacheEffect::None,
+            Some(definition) => match self.cache.ttl(&definition) {
+                Some(ttl) => CacheEffect::Store {
+                    key: tool_cache::cache_key(tool_name, arguments),
+                    domain: tool_cache::domain_of(&definition),
+                    ttl,
+                },
+                None if !tool_cache::is_read_only(tool_name) => CacheEffect::Invalidate {
+                    domain: tool_cache::domain_of(&definition),
+                },
+                None => CacheEffect::None,
+            },
+            None => CacheEffect::None,
+        };
+
+        let arguments = audit::redact(arguments);
+        let id = self.jobs.start(tool_name, &arguments).await;
+        Execution {
+            executor: self.clone(),
+            id,
+            tool_name: tool_name.to_string(),
+            caller,
+            arguments,
+            started_at: Utc::now(),
+            started: Instant::now(),
+            cache_effect,
+            limits,
+            violation,
+        }
+    }
+
+    /// Run a registry tool to completion, answering from the result cache
+    /// when the tool is cacheable
+    pub async fn execute(self: &Arc<Self>, source: &str, tool_name: &str, arguments: Value) -> anyhow::Result<Value> {
+        self.execute_with(source, tool_name, arguments, true).await
+    }
+
+    /// [`Self::execute`], with `use_cache: false` for `Cache-Control: no-cache`
+    /// (the fresh result still refreshes the cache)
+    pub async fn execute_with(
+        self: &Arc<Self>,
+        source: &str,
+        tool_name: &str,
+        arguments: Value,
+        use_cache: bool,
+    ) -> anyhow::Result<Value> {
+        let cacheable = match self.registry.get_definition(tool_name).await {
+            Some(definition) => {
+                self.cache.ttl(&definition).is_some() && self.may_use_cache(&Caller::current(source), &definition)
+            }
+            None => return Err(anyhow!("Tool not found: {}", tool_name)),
+        };
+        if !(cacheable && use_cache) {
+            return self.run(source, tool_name, arguments).await;
+        }
+
+        let key = tool_cache::cache_key(tool_name, &arguments);
+        if let Some(value) = self.cache.get(&key) {
+            debug!("Tool cache hit: {}", tool_name);
+            self.record_cached(Caller::current(source), tool_name, &arguments, &Ok(value.clone())).await;
+            return Ok(value);
+        }
+        // Only the caller leading the flight runs (and audits) the tool
+        let led = AtomicBool::new(false);
+        let redacted = audit::redact(&arguments);
+        let result = self
+            .cache
+            .single_flight(&key, async {
+                led.store(true, Ordering::Relaxed);
+                self.run(source, tool_name, arguments).await.map_err(|e| e.to_string())
+            })
+            .await;
+        if !led.load(Ordering::Relaxed) {
+            self.record_cached(Caller::current(source), tool_name, &redacted, &result).await;
+        }
+        result.map_err(|e| anyhow!(e))
+    }
+
+    async fn run(self: &Arc<Self>, source: &str, tool_name: &str, mut arguments: Value) -> anyhow::Result<Value> {
+        let tool = self
+            .registry
+            .get(tool_name)
+            .await
+            .ok_or_else(|| anyhow!("Tool not found: {}", tool_name))?;
+
+        let execution = self.begin(source, tool_name, &mut arguments).await;
+        let result = execution.run(tool.execute(arguments)).await;
+        execution
+            .complete(result.as_ref().map(Clone::clone).map_err(|e| e.to_string()))
+            .await;
+        result
+    }
+
+    /// Start a registry tool on its own task and return its job immediately
+    pub async fn submit(self: &Arc<Self>, tool_name: &str, mut arguments: Value) -> Result<JobInfo, String> {
+        let tool = self
+            .registry
+            .get(tool_name)
+            .await
+            .ok_or_else(|| format!("Tool '{}' not found", tool_name))?;
+
+        let execution = self.begin("job", tool_name, &mut arguments).await;
+        let id = execution.id;
+        let (handle, registration) = AbortHandle::new_pair();
+        self.jobs.attach(id, handle);
+        info!("Job {} submitted: {}", id, tool_name);
+
+        // The job runs as its submitter, for anything the tool executes in turn
+        audit::spawn_with_identity(async move {
+            let outcome = match Abortable::new(execution.run(tool.execute(arguments)), registration).await {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                // The job itself was already marked cancelled
+                Err(_) => Err("Cancelled by request".to_string()),
+            };
+            execution.complete(outcome).await;
+        });
+
+        self.jobs
+            .get(id)
+            .ok_or_else(|| format!("Job {} was not recorded", id))
+    }
+}
@@ -212,7 +212,7 @@ async fn get_profile(
                 "profile": name,
                 "groups": groups,
                 "preset": config.preset,
-                "mcp_endpoint": format!("/mcp/groups/{}", name)
+                "mcp_endpoint": format!("/mcp/p/{}", name)
             }))
         }
         None => Json(json!({
@@ -246,7 +246,7 @@ async fn save_profile(
         "success": true,
         "profile": name,
         "group_count": count,
-        "mcp_endpoint": format!("/mcp/groups/{}", name)
+        "mcp_endpoint": format!("/mcp/p/{}", name)
     }))
 }
 
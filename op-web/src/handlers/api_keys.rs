@@ -0,0 +1,69 @@
+//! API Key Admin Handlers
+
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use simd_json::json;
+use std::sync::Arc;
+
+use crate::api_keys::{ApiKeyError, CreateApiKey};
+use crate::state::AppState;
+
+/// GET /admin/api-keys - List keys (never their secrets)
+pub async fn list_api_keys_handler(Extension(state): Extension<Arc<AppState>>) -> Response {
+    let keys = state.api_keys.list().await;
+    Json(json!({
+        "count": keys.len(),
+        "keys": keys
+    }))
+    .into_response()
+}
+
+/// POST /admin/api-keys - Create a key; the response is the only place the
+/// key itself appears
+pub async fn create_api_key_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<CreateApiKey>,
+) -> Response {
+    match state.api_keys.create(request).await {
+        Ok((key, secret)) => (
+            StatusCode::CREATED,
+            Json(json!({ "success": true, "key": key, "secret": secret })),
+        )
+            .into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// POST /admin/api-keys/:id/rotate - Issue a new secret for a key
+pub async fn rotate_api_key_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.api_keys.rotate(&id).await {
+        Ok((key, secret)) => Json(json!({ "success": true, "key": key, "secret": secret })).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// DELETE /admin/api-keys/:id - Revoke a key
+pub async fn revoke_api_key_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.api_keys.revoke(&id).await {
+        Ok(key) => Json(json!({ "success": true, "key": key })).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+fn error_response(error: ApiKeyError) -> Response {
+    let status = match error {
+        ApiKeyError::Invalid(_) => StatusCode::BAD_REQUEST,
+        ApiKeyError::NotFound => StatusCode::NOT_FOUND,
+        ApiKeyError::Conflict(_) => StatusCode::CONFLICT,
+    };
+    (status, Json(json!({ "success": false, "error": error.to_string() }))).into_response()
+}
@@ -0,0 +1,24 @@
+//! Audit API Handlers
+
+use axum::{
+    extract::{Extension, Query},
+    response::Json,
+};
+use simd_json::{json, OwnedValue as Value};
+use std::sync::Arc;
+
+use crate::audit::AuditQuery;
+use crate::state::AppState;
+
+/// GET /api/audit - Tool executions, newest first
+/// (`?tool=&source=&ip=&zone=&session=&success=&since=&limit=`)
+pub async fn audit_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Json<Value> {
+    let records = state.audit.query(&query);
+    Json(json!({
+        "count": records.len(),
+        "records": records
+    }))
+}
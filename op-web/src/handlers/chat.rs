@@ -14,6 +14,7 @@ use std::time::Duration;
 use tokio::sync::mpsc;
 use tracing::{info, error};
 
+use crate::audit;
 use crate::state::AppState;
 use crate::orchestrator::OrchestratorEvent;
 use op_llm::provider::ChatMessage;
@@ -116,7 +117,8 @@ pub async fn chat_stream_handler(
         history.push(ChatMessage::user(request.message.clone()));
     }
 
-    tokio::spawn(async move {
+    // Tools the orchestrator runs are authorized for this request's caller
+    audit::spawn_with_identity(async move {
         let result = state_clone
             .orchestrator
             .process(&session_clone, &message, Some(tx.clone()))
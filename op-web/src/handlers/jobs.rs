@@ -0,0 +1,90 @@
+//! Job API Handlers
+
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use simd_json::json;
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::handlers::tools::DirectToolRequest;
+use crate::jobs::{CancelError, JobFilter};
+use crate::state::AppState;
+
+/// POST /api/jobs - Run a tool in the background, returning the job at once
+pub async fn submit_job_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<DirectToolRequest>,
+) -> Response {
+    info!("Job submission: {}", request.tool_name);
+    submit(&state, &request.tool_name, request.arguments).await
+}
+
+/// Submit a job and answer `202 Accepted` with its location
+pub(crate) async fn submit(state: &AppState, tool_name: &str, arguments: simd_json::OwnedValue) -> Response {
+    match state.executor.submit(tool_name, arguments).await {
+        Ok(job) => {
+            let location = format!("/api/jobs/{}", job.id);
+            (
+                StatusCode::ACCEPTED,
+                [("location", location)],
+                Json(json!({ "job": job })),
+            )
+                .into_response()
+        }
+        Err(e) => (StatusCode::NOT_FOUND, Json(json!({ "error": e }))).into_response(),
+    }
+}
+
+/// GET /api/jobs - List jobs (`?tool=&status=&since=&limit=`)
+pub async fn list_jobs_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Query(filter): Query<JobFilter>,
+) -> Json<simd_json::OwnedValue> {
+    let jobs = state.jobs.list(&filter);
+    Json(json!({
+        "count": jobs.len(),
+        "jobs": jobs
+    }))
+}
+
+/// GET /api/jobs/:id - Job status and, once finished, its result
+pub async fn get_job_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    match state.jobs.get(id) {
+        Some(job) => Json(json!({ "job": job })).into_response(),
+        None => not_found(id),
+    }
+}
+
+/// DELETE /api/jobs/:id - Cancel a running job
+pub async fn cancel_job_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    match state.jobs.cancel(id).await {
+        Ok(job) => Json(json!({ "job": job })).into_response(),
+        Err(CancelError::NotFound) => not_found(id),
+        Err(CancelError::AlreadyFinished(job)) => (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": format!("Job {} already finished", id),
+                "job": job
+            })),
+        )
+            .into_response(),
+    }
+}
+
+fn not_found(id: Uuid) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({ "error": format!("Job {} not found", id) })),
+    )
+        .into_response()
+}
@@ -0,0 +1,108 @@
+//! Macro API Handlers
+
+use axum::{
+    extract::{Extension, Path},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use simd_json::json;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::macros::{MacroDefinition, MacroError};
+use crate::state::AppState;
+
+/// GET /api/macros - List macros
+pub async fn list_macros_handler(Extension(state): Extension<Arc<AppState>>) -> Response {
+    let macros = state.macros.list().await;
+    Json(json!({
+        "count": macros.len(),
+        "macros": macros
+    }))
+    .into_response()
+}
+
+/// GET /api/macros/:name - Macro definition
+pub async fn get_macro_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response {
+    match state.macros.get(&name).await {
+        Some(stored) => Json(json!({ "macro": stored })).into_response(),
+        None => error_response(MacroError::NotFound),
+    }
+}
+
+/// POST /api/macros - Create or replace a macro (JSON, or TOML with
+/// `Content-Type: application/toml`)
+pub async fn save_macro_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let definition = match parse_definition(&headers, body) {
+        Ok(definition) => definition,
+        Err(e) => return error_response(MacroError::Invalid(e)),
+    };
+    save(&state, definition).await
+}
+
+/// PUT /api/macros/:name - Replace a macro
+pub async fn update_macro_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let definition = match parse_definition(&headers, body) {
+        Ok(definition) => definition,
+        Err(e) => return error_response(MacroError::Invalid(e)),
+    };
+    if definition.name != name {
+        return error_response(MacroError::Invalid(format!(
+            "Body names macro '{}' but the path names '{}'",
+            definition.name, name
+        )));
+    }
+    save(&state, definition).await
+}
+
+/// DELETE /api/macros/:name - Remove a macro and its tool
+pub async fn delete_macro_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response {
+    match state.macros.remove(&name).await {
+        Ok(()) => Json(json!({ "success": true, "name": name })).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn save(state: &AppState, definition: MacroDefinition) -> Response {
+    info!("Saving macro {}", definition.name);
+    match state.macros.upsert(definition).await {
+        Ok(stored) => Json(json!({ "success": true, "macro": stored })).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+fn parse_definition(headers: &HeaderMap, mut body: String) -> Result<MacroDefinition, String> {
+    let is_toml = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("toml"));
+    if is_toml {
+        toml::from_str(&body).map_err(|e| format!("Invalid TOML macro: {}", e))
+    } else {
+        unsafe { simd_json::from_str(&mut body) }.map_err(|e| format!("Invalid JSON macro: {}", e))
+    }
+}
+
+fn error_response(error: MacroError) -> Response {
+    let status = match error {
+        MacroError::Invalid(_) => StatusCode::BAD_REQUEST,
+        MacroError::Conflict(_) => StatusCode::CONFLICT,
+        MacroError::NotFound => StatusCode::NOT_FOUND,
+    };
+    (status, Json(json!({ "success": false, "error": error.to_string() }))).into_response()
+}
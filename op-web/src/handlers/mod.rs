@@ -1,9 +1,13 @@
 //! HTTP Request Handlers
 
 pub mod agents;
+pub mod api_keys;
+pub mod audit;
 pub mod chat;
 pub mod health;
+pub mod jobs;
 pub mod llm;
+pub mod macros;
 pub mod privacy;
 pub mod status;
 pub mod tools;
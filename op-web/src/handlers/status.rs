@@ -3,10 +3,12 @@
 use axum::{extract::Extension, response::Json};
 use serde::Serialize;
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use sysinfo::System;
 
+use crate::mcp_upstream::UpstreamStatus;
 use crate::state::AppState;
+use crate::tool_catalog;
 
 #[derive(Serialize)]
 pub struct StatusResponse {
@@ -16,6 +18,7 @@ pub struct StatusResponse {
     pub agents: AgentsInfo,
     pub services: Vec<ServiceStatus>,
     pub network: NetworkInfo,
+    pub upstreams: Vec<UpstreamStatus>,
 }
 
 #[derive(Serialize)]
@@ -34,7 +37,7 @@ pub struct SystemInfo {
 #[derive(Serialize)]
 pub struct ToolsInfo {
     pub total: usize,
-    pub by_category: HashMap<String, usize>,
+    pub by_category: BTreeMap<String, usize>,
 }
 
 #[derive(Serialize)]
@@ -104,25 +107,7 @@ pub async fn status_handler(
 
     // Get tools info
     let tools_list = state.tool_registry.list().await;
-    let mut by_category: HashMap<String, usize> = HashMap::new();
-    for tool in &tools_list {
-        let category = if tool.name.starts_with("ovs_") {
-            "ovs"
-        } else if tool.name.starts_with("systemd_") {
-            "systemd"
-        } else if tool.name.starts_with("nm_") {
-            "networkmanager"
-        } else if tool.name.starts_with("file_") {
-            "file"
-        } else if tool.name.starts_with("system_") {
-            "system"
-        } else if tool.name.starts_with("plugin_") {
-            "plugin"
-        } else {
-            "other"
-        };
-        *by_category.entry(category.to_string()).or_insert(0) += 1;
-    }
+    let by_category = tool_catalog::count_by_category(&tools_list);
 
     let tools = ToolsInfo {
         total: tools_list.len(),
@@ -159,6 +144,7 @@ pub async fn status_handler(
         agents,
         services,
         network,
+        upstreams: state.upstreams.status(),
     })
 }
 
@@ -1,70 +1,81 @@
 //! Tool API Handlers
 
 use axum::{
-    extract::{Path, Extension},
-    response::Json,
+    extract::{Path, Extension, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
 };
+use futures::stream::Stream;
 use serde::{Deserialize, Serialize};
 use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::convert::Infallible;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
 use tracing::info;
 
+use crate::audit;
+use crate::batch::{self, BatchRequest};
 use crate::state::AppState;
+use crate::tool_catalog::{self, ToolQuery};
+use crate::tool_output::{self, OutputSink};
 
-/// GET /api/tools - List all available tools
+/// GET /api/tools - List tools (`?category=&tag=&namespace=&q=&offset=&limit=`)
 pub async fn list_tools_handler(
     Extension(state): Extension<Arc<AppState>>,
+    Query(query): Query<ToolQuery>,
 ) -> Json<Value> {
     let tools = state.tool_registry.list().await;
+    let page = tool_catalog::search(&tools, &query);
 
-    let tool_list: Vec<Value> = tools
-        .iter()
-        .map(|t| json!({
-            "name": t.name,
-            "description": t.description,
-            "category": categorize_tool(&t.name),
-        }))
-        .collect();
+    let tool_list: Vec<Value> = page.tools.iter().map(|t| tool_catalog::tool_summary(t)).collect();
 
     Json(json!({
         "tools": tool_list,
-        "count": tool_list.len()
+        "count": tool_list.len(),
+        "total": page.total,
+        "offset": query.offset,
+        "limit": query.limit,
+        "facets": page.facets
     }))
 }
 
-fn categorize_tool(name: &str) -> &'static str {
-    if name.starts_with("ovs_") { "ovs" }
-    else if name.starts_with("systemd_") { "systemd" }
-    else if name.starts_with("nm_") { "networkmanager" }
-    else if name.starts_with("file_") { "file" }
-    else if name.starts_with("system_") { "system" }
-    else if name.starts_with("plugin_") { "plugin" }
-    else { "other" }
-}
-
 /// GET /api/tools/:name - Get tool details
 pub async fn get_tool_handler(
     Extension(state): Extension<Arc<AppState>>,
     Path(name): Path<String>,
 ) -> Json<Value> {
-    let tools = state.tool_registry.list().await;
-    
-    if let Some(tool) = tools.iter().find(|t| t.name == name) {
-        Json(json!({
-            "found": true,
-            "tool": {
-                "name": tool.name,
-                "description": tool.description,
-                "category": categorize_tool(&tool.name),
-                "input_schema": tool.input_schema
+    match state.tool_registry.get_definition(&name).await {
+        Some(tool) => {
+            let mut summary = tool_catalog::tool_summary(&tool);
+            if let Some(obj) = summary.as_object_mut() {
+                obj.insert("input_schema".into(), tool.input_schema.clone());
             }
-        }))
-    } else {
-        Json(json!({
+            Json(json!({
+                "found": true,
+                "tool": summary
+            }))
+        }
+        None => Json(json!({
             "found": false,
             "error": format!("Tool '{}' not found", name)
-        }))
+        })),
+    }
+}
+
+/// POST /api/tools/batch - Execute an ordered list of tools
+pub async fn batch_tools_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<BatchRequest>,
+) -> Response {
+    if let Err(e) = batch::validate(&request) {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))).into_response();
     }
+    Json(batch::run(&state.executor, &state.tool_registry, request).await).into_response()
 }
 
 #[derive(Debug, Deserialize)]
@@ -86,43 +97,119 @@ pub struct DirectToolResponse {
 /// POST /api/tool - Execute a tool directly
 pub async fn execute_tool_handler(
     Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
     Json(request): Json<DirectToolRequest>,
 ) -> Json<DirectToolResponse> {
     info!("Direct tool execution: {}", request.tool_name);
-    execute_tool_internal(state, &request.tool_name, request.arguments).await
+    execute_tool_internal(state, &request.tool_name, request.arguments, use_cache(&headers)).await
 }
 
-/// POST /api/tools/:name/execute - Execute a named tool
+/// POST /api/tools/:name/execute - Execute a named tool (`?async=true` runs it as a job)
 pub async fn execute_named_tool_handler(
     Extension(state): Extension<Arc<AppState>>,
     Path(name): Path<String>,
+    Query(query): Query<ExecuteQuery>,
+    headers: HeaderMap,
     Json(arguments): Json<Value>,
-) -> Json<DirectToolResponse> {
+) -> Response {
     info!("Named tool execution: {}", name);
-    execute_tool_internal(state, &name, arguments).await
+    if query.run_async {
+        return crate::handlers::jobs::submit(&state, &name, arguments).await;
+    }
+    execute_tool_internal(state, &name, arguments, use_cache(&headers)).await.into_response()
+}
+
+/// POST /api/tools/:name/stream - Execute a named tool, streaming its output (SSE)
+///
+/// Sends `output` events (`{seq, stream, data}`) while the tool runs, then one
+/// `result` event with the same body `/api/tools/:name/execute` returns.
+pub async fn stream_tool_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(arguments): Json<Value>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    info!("Streaming tool execution: {}", name);
+    let (tx, mut rx) = mpsc::unbounded_channel::<Event>();
+    let use_cache = use_cache(&headers);
+    let output_tx = tx.clone();
+    let sink = OutputSink::new(move |chunk| {
+        let event = Event::default()
+            .event("output")
+            .data(simd_json::to_string(&chunk).unwrap_or_default());
+        let _ = output_tx.send(event);
+    });
+
+    audit::spawn_with_identity(async move {
+        let Json(response) =
+            tool_output::with_sink(Some(sink), execute_tool_internal(state, &name, arguments, use_cache)).await;
+        let _ = tx.send(
+            Event::default()
+                .event("result")
+                .data(simd_json::to_string(&response).unwrap_or_default()),
+        );
+    });
+
+    let stream = async_stream::stream! {
+        while let Some(event) = rx.recv().await {
+            yield Ok(event);
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)).text("ping"))
+}
+
+/// `Cache-Control: no-cache` skips the result cache
+fn use_cache(headers: &HeaderMap) -> bool {
+    !headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().contains("no-cache"))
+}
+
+/// GET /api/cache - Tool result cache statistics
+pub async fn cache_stats_handler(Extension(state): Extension<Arc<AppState>>) -> Json<Value> {
+    Json(state.executor.cache().stats())
+}
+
+/// GET /api/limits - Tool timeouts, concurrency and rate limits, with violations
+pub async fn limits_handler(Extension(state): Extension<Arc<AppState>>) -> Json<Value> {
+    Json(state.executor.limits().stats())
+}
+
+/// DELETE /api/cache - Drop every cached tool result
+pub async fn clear_cache_handler(Extension(state): Extension<Arc<AppState>>) -> Json<Value> {
+    state.executor.cache().clear();
+    info!("Tool result cache cleared");
+    Json(json!({ "cleared": true }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExecuteQuery {
+    /// Run as a background job and answer `202` with its id
+    #[serde(rename = "async", default)]
+    pub run_async: bool,
 }
 
 async fn execute_tool_internal(
     state: Arc<AppState>,
     tool_name: &str,
     arguments: Value,
+    use_cache: bool,
 ) -> Json<DirectToolResponse> {
     let start = std::time::Instant::now();
 
-    let tool = match state.tool_registry.get(tool_name).await {
-        Some(t) => t,
-        None => {
-            return Json(DirectToolResponse {
-                success: false,
-                tool_name: tool_name.to_string(),
-                result: None,
-                error: Some("Tool not found".to_string()),
-                execution_time_ms: start.elapsed().as_millis() as u64,
-            });
-        }
-    };
+    if state.tool_registry.get(tool_name).await.is_none() {
+        return Json(DirectToolResponse {
+            success: false,
+            tool_name: tool_name.to_string(),
+            result: None,
+            error: Some("Tool not found".to_string()),
+            execution_time_ms: start.elapsed().as_millis() as u64,
+        });
+    }
 
-    match tool.execute(arguments).await {
+    match state.executor.execute_with("api", tool_name, arguments, use_cache).await {
         Ok(result) => Json(DirectToolResponse {
             success: true,
             tool_name: tool_name.to_string(),
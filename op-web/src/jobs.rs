@@ -0,0 +1,323 @@
+//! Tool Jobs - Asynchronous tool execution over the ExecutionJob store
+//!
+//! Every tracked execution is written to `state_store` as an `ExecutionJob`
+//! (Running → Completed/Failed) and indexed here so it can be read back
+//! through `/api/jobs`. Jobs submitted asynchronously (see
+//! [`crate::executor::ToolExecutor::submit`]) can be cancelled and announce
+//! completion on `/api/events` as a `job` event; synchronous executions are
+//! answered directly and only show up here. The index keeps recent jobs; the
+//! state store keeps the full history.
+
+use chrono::{DateTime, Utc};
+use futures::future::AbortHandle;
+use op_state_store::execution_job::{ExecutionJob, ExecutionResult, ExecutionStatus};
+use op_state_store::StateStore;
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::sse::SseEventBroadcaster;
+
+/// Finished jobs kept in the index (oldest are dropped first)
+const MAX_FINISHED_JOBS: usize = 1000;
+
+/// Default page size for `GET /api/jobs`
+const DEFAULT_LIST_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        self != JobStatus::Running
+    }
+}
+
+/// A tool execution as reported by `/api/jobs`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: Uuid,
+    pub tool_name: String,
+    pub arguments: Value,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl JobInfo {
+    fn to_execution_job(&self) -> ExecutionJob {
+        let status = match self.status {
+            JobStatus::Running => ExecutionStatus::Running,
+            JobStatus::Completed => ExecutionStatus::Completed,
+            // The store has no cancelled state; the error says why
+            JobStatus::Failed | JobStatus::Cancelled => ExecutionStatus::Failed,
+        };
+        let result = self.status.is_finished().then(|| ExecutionResult {
+            success: self.status == JobStatus::Completed,
+            output: self.result.clone(),
+            error: self.error.clone(),
+        });
+
+        ExecutionJob {
+            id: self.id,
+            tool_name: self.tool_name.clone(),
+            arguments: self.arguments.clone(),
+            status,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            result,
+        }
+    }
+}
+
+/// Query for `GET /api/jobs`
+#[derive(Debug, Default, Deserialize)]
+pub struct JobFilter {
+    pub tool: Option<String>,
+    pub status: Option<JobStatus>,
+    /// Only jobs created at or after this time (RFC 3339)
+    pub since: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl JobFilter {
+    fn matches(&self, job: &JobInfo) -> bool {
+        self.tool.as_deref().is_none_or(|tool| job.tool_name == tool)
+            && self.status.is_none_or(|status| job.status == status)
+            && self.since.is_none_or(|since| job.created_at >= since)
+    }
+}
+
+/// Why a job could not be cancelled
+#[derive(Debug)]
+pub enum CancelError {
+    NotFound,
+    AlreadyFinished(JobInfo),
+}
+
+struct JobEntry {
+    info: JobInfo,
+    abort: Option<AbortHandle>,
+    /// Run in the background; its completion is broadcast
+    submitted: bool,
+}
+
+/// Tracks tool executions
+pub struct JobManager {
+    store: Arc<dyn StateStore>,
+    events: Arc<SseEventBroadcaster>,
+    jobs: RwLock<HashMap<Uuid, JobEntry>>,
+}
+
+impl JobManager {
+    pub fn new(store: Arc<dyn StateStore>, events: Arc<SseEventBroadcaster>) -> Self {
+        Self {
+            store,
+            events,
+            jobs: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Record the start of an execution run by the caller
+    pub async fn start(&self, tool_name: &str, arguments: &Value) -> Uuid {
+        let now = Utc::now();
+        let info = JobInfo {
+            id: Uuid::new_v4(),
+            tool_name: tool_name.to_string(),
+            arguments: arguments.clone(),
+            status: JobStatus::Running,
+            created_at: now,
+            updated_at: now,
+            result: None,
+            error: None,
+        };
+        let id = info.id;
+
+        if let Err(e) = self.store.save_job(&info.to_execution_job()).await {
+            error!("Failed to save execution job start to state store: {}", e);
+        }
+        self.jobs
+            .write()
+            .unwrap()
+            .insert(id, JobEntry { info, abort: None, submitted: false });
+        id
+    }
+
+    /// Record how an execution ended. A job cancelled meanwhile stays cancelled.
+    pub async fn finish(&self, id: Uuid, outcome: &Result<Value, String>) -> Option<JobInfo> {
+        let (info, submitted) = {
+            let mut jobs = self.jobs.write().unwrap();
+            let entry = jobs.get_mut(&id)?;
+            if entry.info.status.is_finished() {
+                return Some(entry.info.clone());
+            }
+            match outcome {
+                Ok(output) => {
+                    entry.info.status = JobStatus::Completed;
+                    entry.info.result = Some(output.clone());
+                }
+                Err(e) => {
+                    entry.info.status = JobStatus::Failed;
+                    entry.info.error = Some(e.clone());
+                }
+            }
+            entry.info.updated_at = Utc::now();
+            entry.abort = None;
+            (entry.info.clone(), entry.submitted)
+        };
+
+        self.record_finished(&info, submitted).await;
+        Some(info)
+    }
+
+    /// Make a running job cancellable through `handle`, as a submitted job
+    pub fn attach(&self, id: Uuid, handle: AbortHandle) {
+        if let Some(entry) = self.jobs.write().unwrap().get_mut(&id) {
+            entry.submitted = true;
+            if entry.info.status.is_finished() {
+                handle.abort();
+            } else {
+                entry.abort = Some(handle);
+            }
+        }
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<JobInfo> {
+        self.jobs.read().unwrap().get(&id).map(|entry| entry.info.clone())
+    }
+
+    /// Jobs matching `filter`, newest first
+    pub fn list(&self, filter: &JobFilter) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self
+            .jobs
+            .read()
+            .unwrap()
+            .values()
+            .filter(|entry| filter.matches(&entry.info))
+            .map(|entry| entry.info.clone())
+            .collect();
+        jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        jobs.truncate(filter.limit.unwrap_or(DEFAULT_LIST_LIMIT));
+        jobs
+    }
+
+    /// Abort a running job
+    pub async fn cancel(&self, id: Uuid) -> Result<JobInfo, CancelError> {
+        let (info, submitted) = {
+            let mut jobs = self.jobs.write().unwrap();
+            let entry = jobs.get_mut(&id).ok_or(CancelError::NotFound)?;
+            if entry.info.status.is_finished() {
+                return Err(CancelError::AlreadyFinished(entry.info.clone()));
+            }
+            if let Some(handle) = entry.abort.take() {
+                handle.abort();
+            }
+            entry.info.status = JobStatus::Cancelled;
+            entry.info.error = Some("Cancelled by request".to_string());
+            entry.info.updated_at = Utc::now();
+            (entry.info.clone(), entry.submitted)
+        };
+
+        info!("Job {} cancelled ({})", id, info.tool_name);
+        self.record_finished(&info, submitted).await;
+        Ok(info)
+    }
+
+    async fn record_finished(&self, info: &JobInfo, submitted: bool) {
+        if let Err(e) = self.store.update_job(&info.to_execution_job()).await {
+            error!("Failed to update execution job {}: {}", info.id, e);
+        }
+
+        // Other executions are answered to their caller and stay off the
+        // public event stream
+        if submitted {
+            let event = json!({
+                "id": info.id.to_string(),
+                "tool_name": info.tool_name.clone(),
+                "status": info.status,
+                "error": info.error.clone()
+            });
+            if let Ok(data) = simd_json::to_string(&event) {
+                self.events.broadcast("job", &data);
+            }
+        }
+
+        self.prune();
+    }
+
+    /// Drop the oldest finished jobs beyond the retention limit
+    fn prune(&self) {
+        let mut jobs = self.jobs.write().unwrap();
+        let mut finished: Vec<(DateTime<Utc>, Uuid)> = jobs
+            .values()
+            .filter(|entry| entry.info.status.is_finished())
+            .map(|entry| (entry.info.updated_at, entry.info.id))
+            .collect();
+        if finished.len() <= MAX_FINISHED_JOBS {
+            return;
+        }
+        finished.sort();
+        for (_, id) in finished.iter().take(finished.len() - MAX_FINISHED_JOBS) {
+            jobs.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(tool: &str, status: JobStatus, created_at: DateTime<Utc>) -> JobInfo {
+        JobInfo {
+            id: Uuid::new_v4(),
+            tool_name: tool.to_string(),
+            arguments: json!({}),
+            status,
+            created_at,
+            updated_at: created_at,
+            result: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_matches_tool_status_and_since() {
+        let now = Utc::now();
+        let filter = JobFilter {
+            tool: Some("ovs_list_bridges".to_string()),
+            status: Some(JobStatus::Completed),
+            since: Some(now - chrono::Duration::minutes(5)),
+            limit: None,
+        };
+
+        assert!(filter.matches(&job("ovs_list_bridges", JobStatus::Completed, now)));
+        assert!(!filter.matches(&job("ovs_list_bridges", JobStatus::Failed, now)));
+        assert!(!filter.matches(&job("file_read", JobStatus::Completed, now)));
+        assert!(!filter.matches(&job("ovs_list_bridges", JobStatus::Completed, now - chrono::Duration::hours(1))));
+    }
+
+    #[test]
+    fn test_cancelled_jobs_are_stored_as_failed() {
+        let mut info = job("file_read", JobStatus::Cancelled, Utc::now());
+        info.error = Some("Cancelled by request".to_string());
+
+        let stored = info.to_execution_job();
+        assert!(matches!(stored.status, ExecutionStatus::Failed));
+        let result = stored.result.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.error.as_deref(), Some("Cancelled by request"));
+    }
+}
@@ -15,29 +15,59 @@
 //! ├─────────────────────────────────────────────────────────────────┤
 //! │  /api/health     - Health check                                 │
 //! │  /api/status     - System status                                │
+//! │  /api/openapi.json - OpenAPI document (REST API + every tool)   │
 //! │  /api/tools      - Tool registry                                │
+//! │  /api/jobs       - Background tool jobs                         │
+//! │  /api/macros     - Composite tool macros                        │
+//! │  /api/audit      - Tool execution audit trail                   │
+//! │  /api/cache      - Tool result cache stats / clear              │
+//! │  /api/limits     - Tool limits and violations                   │
 //! │  /api/agents     - Agent management                             │
 //! │  /api/chat       - Chat API                                     │
 //! │  /api/events     - SSE event stream                             │
-//! │  /mcp            - MCP JSON-RPC endpoint                        │
+//! │  /mcp            - MCP entry point (mode negotiated per client) │
+//! │  /mcp/p/{name}   - MCP scoped to a tool groups profile          │
 //! │  /ws             - WebSocket chat                               │
 //! │  /               - Static files (WASM frontend)                 │
 //! └─────────────────────────────────────────────────────────────────┘
 //! ```
 
+pub mod access_policy;
+pub mod api_keys;
+pub mod audit;
+pub mod batch;
 pub mod email;
 pub mod embedded_ui;
+pub mod executor;
 pub mod handlers;
+pub mod jobs;
+pub mod macros;
 pub mod middleware;
 pub mod mcp;
 pub mod mcp_compact;
 pub mod mcp_agents;
+pub mod mcp_completion;
 pub mod mcp_discovery;
+pub mod mcp_elicitation;
+pub mod mcp_logging;
+pub mod mcp_sampling;
+pub mod mcp_session;
+pub mod mcp_smart_router;
+pub mod mcp_stdio;
+pub mod mcp_upstream;
+pub mod openapi;
 pub mod groups_admin;
 pub mod orchestrator;
+pub mod registry_refresh;
 pub mod routes;
+pub mod scope_policy;
 pub mod sse;
 pub mod state;
+pub mod tool_cache;
+pub mod tool_catalog;
+pub mod tool_limits;
+pub mod tool_output;
+pub mod tool_profiles;
 pub mod users;
 pub mod websocket;
 pub mod wireguard;
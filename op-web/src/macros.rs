@@ -0,0 +1,696 @@
+//! Tool Macros - named composite tools built from existing tool calls
+//!
+//! A macro is a parameterized list of steps registered in the
+//! [`ToolRegistry`] as an ordinary tool, with an input schema generated from
+//! its parameters. Step arguments take `${params.<name>}` for the caller's
+//! arguments and `${steps[N].result...}` for earlier outputs; steps run
+//! through the batch runner (see [`crate::batch`]), so a failing step rolls
+//! back the ones before it that have an inverse.
+//!
+//! ```toml
+//! [[macros]]
+//! name = "create_internal_bridge"
+//! description = "Create an OVS bridge with an internal port and assign it an address"
+//!
+//! [[macros.parameters]]
+//! name = "name"
+//! description = "Bridge (and interface) name"
+//!
+//! [[macros.steps]]
+//! tool_name = "ovs_create_bridge"
+//! arguments = { name = "${params.name}" }
+//! ```
+//!
+//! Sources, later ones replacing earlier ones of the same name: built-in
+//! macros, `/etc/op-dbus/macros.toml` (hand-written, read-only through the
+//! API), and `/etc/op-dbus/macros.json`, which `/api/macros` maintains.
+//! A macro whose step tools are not registered yet (a D-Bus service that
+//! has not started) is kept as pending and registered once the registry
+//! announces a change.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use op_tools::tool::Tool;
+use op_tools::ToolRegistry;
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Weak};
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
+
+use crate::batch::{self, BatchMode, BatchRequest, BatchStep};
+use crate::mcp_session::MCP_SESSIONS;
+use crate::sse::SseEventBroadcaster;
+
+const MACROS_PATH: &str = "/etc/op-dbus/macros.json";
+const MACROS_TOML_PATH: &str = "/etc/op-dbus/macros.toml";
+
+/// Registry category (and namespace) of macro tools
+pub const MACRO_CATEGORY: &str = "macro";
+
+const PARAM_PREFIX: &str = "params.";
+const PARAM_REFERENCE: &str = "${params.";
+
+fn default_param_type() -> String {
+    "string".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroParameter {
+    pub name: String,
+    /// JSON schema type
+    #[serde(rename = "type", default = "default_param_type")]
+    pub param_type: String,
+    #[serde(default)]
+    pub description: String,
+    /// Used when the caller omits the parameter; makes it optional
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroStep {
+    pub tool_name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub parameters: Vec<MacroParameter>,
+    pub steps: Vec<MacroStep>,
+    /// Undo completed steps when a later one fails
+    #[serde(default = "default_true")]
+    pub rollback: bool,
+}
+
+impl MacroDefinition {
+    /// Input schema generated from the parameters
+    pub fn input_schema(&self) -> Value {
+        let mut properties = json!({});
+        if let Some(obj) = properties.as_object_mut() {
+            for param in &self.parameters {
+                let mut property = json!({
+                    "type": param.param_type.as_str(),
+                    "description": param.description.as_str()
+                });
+                if let (Some(default), Some(p)) = (&param.default, property.as_object_mut()) {
+                    p.insert("default".into(), default.clone());
+                }
+                obj.insert(param.name.clone(), property);
+            }
+        }
+        let required: Vec<Value> = self
+            .parameters
+            .iter()
+            .filter(|p| p.default.is_none())
+            .map(|p| json!(p.name.as_str()))
+            .collect();
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": Value::from(required)
+        })
+    }
+
+    /// Steps with `${params.*}` filled in from `input`
+    fn expand(&self, input: &Value) -> Result<Vec<BatchStep>, String> {
+        let mut values = BTreeMap::new();
+        for param in &self.parameters {
+            let value = input
+                .get(param.name.as_str())
+                .cloned()
+                .or_else(|| param.default.clone())
+                .ok_or_else(|| format!("Missing required parameter: {}", param.name))?;
+            values.insert(param.name.as_str(), value);
+        }
+
+        // Step references are left for the batch runner
+        let lookup = |reference: &str| {
+            let name = reference.strip_prefix(PARAM_PREFIX).unwrap_or(reference);
+            values
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Unknown parameter '{}'", name))
+        };
+        self.steps
+            .iter()
+            .map(|step| {
+                Ok(BatchStep {
+                    tool_name: step.tool_name.clone(),
+                    arguments: batch::substitute(&step.arguments, PARAM_REFERENCE, &lookup)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Where a macro was defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MacroSource {
+    Builtin,
+    /// `/etc/op-dbus/macros.toml`
+    File,
+    /// `/api/macros`
+    Api,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredMacro {
+    #[serde(flatten)]
+    pub definition: MacroDefinition,
+    pub source: MacroSource,
+    /// Why the macro is not registered as a tool yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum MacroError {
+    Invalid(String),
+    /// Name taken by a regular tool or a read-only macro
+    Conflict(String),
+    NotFound,
+}
+
+impl std::fmt::Display for MacroError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) | Self::Conflict(e) => f.write_str(e),
+            Self::NotFound => f.write_str("Macro not found"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MacroFile {
+    #[serde(default)]
+    macros: Vec<MacroDefinition>,
+}
+
+/// The macros shipped with op-web
+fn builtin_macros() -> Vec<MacroDefinition> {
+    vec![MacroDefinition {
+        name: "create_internal_bridge".to_string(),
+        description: "Create an OVS bridge with an internal port of the same name and assign it an address. \
+                      A bridge alone has no Linux interface to address."
+            .to_string(),
+        parameters: vec![
+            MacroParameter {
+                name: "name".to_string(),
+                param_type: default_param_type(),
+                description: "Bridge and internal port name (e.g. br0)".to_string(),
+                default: None,
+            },
+            MacroParameter {
+                name: "cidr".to_string(),
+                param_type: default_param_type(),
+                description: "Address for the bridge interface (e.g. 10.0.0.1/24)".to_string(),
+                default: None,
+            },
+        ],
+        steps: vec![
+            MacroStep {
+                tool_name: "ovs_create_bridge".to_string(),
+                arguments: json!({ "name": "${params.name}" }),
+            },
+            MacroStep {
+                tool_name: "ovs_add_port".to_string(),
+                arguments: json!({ "bridge": "${params.name}", "port": "${params.name}", "type": "internal" }),
+            },
+            MacroStep {
+                tool_name: "rtnetlink_add_address".to_string(),
+                arguments: json!({ "interface": "${params.name}", "address": "${params.cidr}" }),
+            },
+        ],
+        rollback: true,
+    }]
+}
+
+/// Registry tool running a macro's steps
+struct MacroTool {
+    definition: MacroDefinition,
+    input_schema: Value,
+    registry: Weak<ToolRegistry>,
+}
+
+#[async_trait]
+impl Tool for MacroTool {
+    fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    fn description(&self) -> &str {
+        &self.definition.description
+    }
+
+    fn input_schema(&self) -> Value {
+        self.input_schema.clone()
+    }
+
+    fn category(&self) -> &str {
+        MACRO_CATEGORY
+    }
+
+    fn namespace(&self) -> &str {
+        MACRO_CATEGORY
+    }
+
+    async fn execute(&self, input: Value) -> Result<Value> {
+        let registry = self.registry.upgrade().ok_or_else(|| anyhow!("Tool registry is gone"))?;
+        let executor = crate::executor::global().ok_or_else(|| anyhow!("Tool executor is not running"))?;
+        let steps = self.definition.expand(&input).map_err(|e| anyhow!(e))?;
+
+        let request = BatchRequest {
+            steps,
+            mode: BatchMode::Sequential,
+            stop_on_error: true,
+            rollback: self.definition.rollback,
+        };
+        let response = batch::run(&executor, &registry, request).await;
+        let body = simd_json::serde::to_owned_value(&response)?;
+        if response.success {
+            return Ok(body);
+        }
+
+        let failed = response
+            .steps
+            .iter()
+            .find_map(|s| s.error.as_ref().map(|e| format!("step {} ({}): {}", s.index, s.tool_name, e)))
+            .unwrap_or_default();
+        Err(anyhow!(
+            "Macro {} failed at {}{}",
+            self.definition.name,
+            failed,
+            if response.rolled_back { "; completed steps were rolled back" } else { "" }
+        ))
+    }
+}
+
+pub struct MacroManager {
+    registry: Arc<ToolRegistry>,
+    events: Arc<SseEventBroadcaster>,
+    macros: RwLock<BTreeMap<String, StoredMacro>>,
+    /// Whether API macros are read from and saved to `MACROS_PATH`
+    persistent: bool,
+}
+
+impl MacroManager {
+    /// Register the built-in, file and stored macros
+    pub async fn load(registry: Arc<ToolRegistry>, events: Arc<SseEventBroadcaster>) -> Self {
+        Self::open(registry, events, true).await
+    }
+
+    /// Register only the built-in macros; API macros are kept in memory
+    pub async fn builtin(registry: Arc<ToolRegistry>, events: Arc<SseEventBroadcaster>) -> Self {
+        Self::open(registry, events, false).await
+    }
+
+    async fn open(registry: Arc<ToolRegistry>, events: Arc<SseEventBroadcaster>, persistent: bool) -> Self {
+        let manager = Self {
+            registry,
+            events,
+            macros: RwLock::new(BTreeMap::new()),
+            persistent,
+        };
+
+        let mut sources: Vec<(MacroDefinition, MacroSource)> =
+            builtin_macros().into_iter().map(|m| (m, MacroSource::Builtin)).collect();
+        if persistent {
+            if let Ok(content) = tokio::fs::read_to_string(MACROS_TOML_PATH).await {
+                match toml::from_str::<MacroFile>(&content) {
+                    Ok(file) => sources.extend(file.macros.into_iter().map(|m| (m, MacroSource::File))),
+                    Err(e) => warn!("Invalid macro file {}: {}", MACROS_TOML_PATH, e),
+                }
+            }
+            if let Ok(mut content) = tokio::fs::read_to_string(MACROS_PATH).await {
+                match unsafe { simd_json::from_str::<Vec<MacroDefinition>>(&mut content) } {
+                    Ok(stored) => sources.extend(stored.into_iter().map(|m| (m, MacroSource::Api))),
+                    Err(e) => warn!("Invalid macro store {}: {}", MACROS_PATH, e),
+                }
+            }
+        }
+
+        let mut loaded = 0;
+        for (definition, source) in sources {
+            let name = definition.name.clone();
+            match manager.install(definition.clone(), source).await {
+                Ok(()) => loaded += 1,
+                Err(e) => {
+                    // Built-ins depend on optional tools (OVS, rtnetlink)
+                    if source == MacroSource::Builtin {
+                        debug!("Built-in macro {} pending: {}", name, e);
+                    } else {
+                        warn!("Macro {} pending: {}", name, e);
+                    }
+                    manager.hold(definition, source, e.to_string()).await;
+                }
+            }
+        }
+        info!("Registered {} tool macros", loaded);
+        manager
+    }
+
+    /// Register pending macros whenever another part of the server changes
+    /// the registry
+    pub fn watch(self: &Arc<Self>) {
+        let manager = Arc::downgrade(self);
+        let mut events = self.events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.event_type == "tools_changed" && !event.data.contains(r#""source":"macros""#) => {}
+                    Ok(_) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+                let Some(manager) = manager.upgrade() else { break };
+                manager.register_pending().await;
+            }
+        });
+    }
+
+    /// Try to register every pending macro; returns how many were
+    pub async fn register_pending(&self) -> usize {
+        let pending: Vec<(MacroDefinition, MacroSource)> = self
+            .macros
+            .read()
+            .await
+            .values()
+            .filter(|m| m.pending.is_some())
+            .map(|m| (m.definition.clone(), m.source))
+            .collect();
+
+        let mut registered = 0;
+        for (definition, source) in pending {
+            let name = definition.name.clone();
+            match self.install(definition, source).await {
+                Ok(()) => {
+                    info!("Pending macro {} registered", name);
+                    registered += 1;
+                }
+                Err(e) => {
+                    if let Some(stored) = self.macros.write().await.get_mut(&name) {
+                        if stored.pending.is_some() {
+                            stored.pending = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        if registered > 0 {
+            self.announce();
+        }
+        registered
+    }
+
+    /// Keep a macro that cannot be registered yet, so it is listed and saved
+    async fn hold(&self, definition: MacroDefinition, source: MacroSource, reason: String) {
+        let name = definition.name.clone();
+        let mut macros = self.macros.write().await;
+        match macros.get(&name) {
+            // The file macro stays; the stored one is dropped from the store
+            Some(existing) if existing.source == MacroSource::File && source == MacroSource::Api => return,
+            // A later source replaces the earlier macro even when it is not usable yet
+            Some(existing) if existing.pending.is_none() => self.registry.unregister_tool(&name).await,
+            _ => {}
+        }
+        macros.insert(name, StoredMacro { definition, source, pending: Some(reason) });
+    }
+
+    pub async fn list(&self) -> Vec<StoredMacro> {
+        self.macros.read().await.values().cloned().collect()
+    }
+
+    pub async fn get(&self, name: &str) -> Option<StoredMacro> {
+        self.macros.read().await.get(name).cloned()
+    }
+
+    /// Create or replace a macro through the API and persist it
+    pub async fn upsert(&self, definition: MacroDefinition) -> Result<StoredMacro, MacroError> {
+        let name = definition.name.clone();
+        self.install(definition, MacroSource::Api).await?;
+        self.save().await;
+        self.announce();
+        info!("Macro {} saved", name);
+        self.get(&name).await.ok_or(MacroError::NotFound)
+    }
+
+    /// Remove an API-defined macro
+    pub async fn remove(&self, name: &str) -> Result<(), MacroError> {
+        let mut macros = self.macros.write().await;
+        match macros.get(name).map(|m| m.source) {
+            None => return Err(MacroError::NotFound),
+            Some(MacroSource::Api) => {}
+            Some(_) => {
+                return Err(MacroError::Conflict(format!(
+                    "Macro {} is not managed through the API",
+                    name
+                )))
+            }
+        }
+        let registered = macros.remove(name).is_some_and(|m| m.pending.is_none());
+        drop(macros);
+
+        if registered {
+            self.registry.unregister_tool(name).await;
+        }
+        self.save().await;
+        self.announce();
+        info!("Macro {} removed", name);
+        Ok(())
+    }
+
+    async fn install(&self, definition: MacroDefinition, source: MacroSource) -> Result<(), MacroError> {
+        self.validate(&definition).await?;
+
+        let name = definition.name.clone();
+        // Checked under the lock, so a concurrent load cannot slip in between
+        let mut macros = self.macros.write().await;
+        let replaced = macros.get(&name).cloned();
+        match &replaced {
+            Some(existing) if existing.source == MacroSource::File && source == MacroSource::Api => {
+                return Err(MacroError::Conflict(format!(
+                    "Macro {} is defined in {} and cannot be changed through the API",
+                    name, MACROS_TOML_PATH
+                )));
+            }
+            Some(existing) if existing.pending.is_none() => self.registry.unregister_tool(&name).await,
+            // Nothing or a pending macro under this name
+            _ if self.registry.get_definition(&name).await.is_some() => {
+                return Err(MacroError::Conflict(format!("A tool named {} already exists", name)));
+            }
+            _ => {}
+        }
+
+        if let Err(e) = self.registry.register_tool(self.tool_for(definition.clone())).await {
+            // Put the replaced macro back rather than leave the name unregistered
+            if let Some(existing) = replaced.filter(|m| m.pending.is_none()) {
+                if let Err(e) = self.registry.register_tool(self.tool_for(existing.definition)).await {
+                    error!("Failed to restore macro {}: {}", name, e);
+                    macros.remove(&name);
+                }
+            }
+            return Err(MacroError::Invalid(e.to_string()));
+        }
+        macros.insert(name, StoredMacro { definition, source, pending: None });
+        Ok(())
+    }
+
+    fn tool_for(&self, definition: MacroDefinition) -> Arc<MacroTool> {
+        Arc::new(MacroTool {
+            input_schema: definition.input_schema(),
+            definition,
+            registry: Arc::downgrade(&self.registry),
+        })
+    }
+
+    async fn validate(&self, definition: &MacroDefinition) -> Result<(), MacroError> {
+        let invalid = |e: String| Err(MacroError::Invalid(e));
+        if definition.name.is_empty()
+            || !definition
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return invalid(format!("Invalid macro name '{}' (use a-z, 0-9 and _)", definition.name));
+        }
+        if definition.steps.is_empty() {
+            return invalid("Macro has no steps".to_string());
+        }
+
+        let declared: HashSet<&str> = definition.parameters.iter().map(|p| p.name.as_str()).collect();
+        for (index, step) in definition.steps.iter().enumerate() {
+            match self.registry.get_definition(&step.tool_name).await {
+                None => return invalid(format!("Step {}: unknown tool {}", index, step.tool_name)),
+                // Macros calling macros could recurse
+                Some(tool) if tool.category == MACRO_CATEGORY => {
+                    return invalid(format!("Step {}: {} is a macro", index, step.tool_name))
+                }
+                Some(_) => {}
+            }
+            for param in param_references(&step.arguments)? {
+                if !declared.contains(param.as_str()) {
+                    return invalid(format!("Step {}: undeclared parameter '{}'", index, param));
+                }
+            }
+        }
+
+        let request = BatchRequest {
+            steps: definition
+                .steps
+                .iter()
+                .map(|s| BatchStep { tool_name: s.tool_name.clone(), arguments: s.arguments.clone() })
+                .collect(),
+            mode: BatchMode::Sequential,
+            stop_on_error: true,
+            rollback: definition.rollback,
+        };
+        batch::validate(&request).map_err(MacroError::Invalid)
+    }
+
+    /// Write the API macros, pending ones included, to `MACROS_PATH`
+    async fn save(&self) {
+        if !self.persistent {
+            return;
+        }
+        let stored: Vec<MacroDefinition> = self
+            .macros
+            .read()
+            .await
+            .values()
+            .filter(|m| m.source == MacroSource::Api)
+            .map(|m| m.definition.clone())
+            .collect();
+        match simd_json::to_string_pretty(&stored) {
+            Ok(json) => {
+                if let Some(dir) = std::path::Path::new(MACROS_PATH).parent() {
+                    tokio::fs::create_dir_all(dir).await.ok();
+                }
+                if let Err(e) = tokio::fs::write(MACROS_PATH, json).await {
+                    error!("Failed to save macros to {}: {}", MACROS_PATH, e);
+                }
+            }
+            Err(e) => error!("Failed to serialize macros: {}", e),
+        }
+    }
+
+    fn announce(&self) {
+        MCP_SESSIONS.notify_all("notifications/tools/list_changed", json!({}));
+        self.events.broadcast("tools_changed", r#"{"source":"macros"}"#);
+    }
+}
+
+/// Parameters referenced as `${params.<name>}` in `arguments`
+fn param_references(arguments: &Value) -> Result<Vec<String>, MacroError> {
+    let found = RefCell::new(Vec::new());
+    batch::substitute(arguments, PARAM_REFERENCE, &|reference| {
+        if let Some(name) = reference.strip_prefix(PARAM_PREFIX) {
+            found.borrow_mut().push(name.to_string());
+        }
+        Ok(json!(null))
+    })
+    .map_err(MacroError::Invalid)?;
+    Ok(found.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_and_expansion() {
+        let definition = builtin_macros().remove(0);
+        let schema = definition.input_schema();
+        assert_eq!(schema.get("required"), Some(&json!(["name", "cidr"])));
+
+        let steps = definition.expand(&json!({ "name": "br1", "cidr": "10.1.0.1/24" })).unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[1].arguments, json!({ "bridge": "br1", "port": "br1", "type": "internal" }));
+        assert_eq!(steps[2].arguments, json!({ "interface": "br1", "address": "10.1.0.1/24" }));
+
+        assert!(definition.expand(&json!({ "name": "br1" })).is_err());
+    }
+
+    struct StubTool(&'static str);
+
+    #[async_trait]
+    impl Tool for StubTool {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn description(&self) -> &str {
+            "Stub"
+        }
+
+        fn input_schema(&self) -> Value {
+            json!({ "type": "object", "properties": {} })
+        }
+
+        fn category(&self) -> &str {
+            "test"
+        }
+
+        fn namespace(&self) -> &str {
+            "test"
+        }
+
+        async fn execute(&self, _input: Value) -> Result<Value> {
+            Ok(json!({}))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pending_macro_registers_when_its_tools_appear() {
+        let registry = Arc::new(ToolRegistry::new());
+        let manager = MacroManager::builtin(registry.clone(), Arc::new(SseEventBroadcaster::new())).await;
+
+        // No OVS or rtnetlink tools yet: kept, but not a tool
+        let stored = manager.get("create_internal_bridge").await.expect("kept while pending");
+        assert!(stored.pending.is_some());
+        assert!(registry.get_definition("create_internal_bridge").await.is_none());
+
+        for name in ["ovs_create_bridge", "ovs_add_port", "rtnetlink_add_address"] {
+            registry.register_tool(Arc::new(StubTool(name))).await.unwrap();
+        }
+        assert_eq!(manager.register_pending().await, 1);
+        assert!(manager.get("create_internal_bridge").await.unwrap().pending.is_none());
+        assert!(registry.get_definition("create_internal_bridge").await.is_some());
+    }
+
+    #[test]
+    fn test_step_references_survive_expansion() {
+        let definition = MacroDefinition {
+            name: "port_on_new_bridge".to_string(),
+            description: String::new(),
+            parameters: vec![MacroParameter {
+                name: "port".to_string(),
+                param_type: default_param_type(),
+                description: String::new(),
+                default: Some(json!("eth1")),
+            }],
+            steps: vec![MacroStep {
+                tool_name: "ovs_add_port".to_string(),
+                arguments: json!({ "bridge": "${steps[0].result.name}", "port": "${params.port}" }),
+            }],
+            rollback: true,
+        };
+        let steps = definition.expand(&json!({})).unwrap();
+        assert_eq!(steps[0].arguments, json!({ "bridge": "${steps[0].result.name}", "port": "eth1" }));
+        assert_eq!(param_references(&definition.steps[0].arguments).unwrap(), vec!["port".to_string()]);
+    }
+}
@@ -1,33 +1,61 @@
 //! op-web: Main Entry Point
 //!
 //! Unified web server for op-dbus-v2.
+//! `--mcp-stdio` serves MCP over stdin/stdout instead (see `mcp_stdio`).
 
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::info;
-use tracing_subscriber::{FmtSubscriber, EnvFilter};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+use op_web::mcp_logging::{self, McpLogLayer};
+use op_web::mcp_stdio::{self, StdioOptions};
 use op_web::routes;
+use op_web::tool_output::{self, ToolOutputLayer};
 use op_web::AppState;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let stdio = StdioOptions::from_args(std::env::args().skip(1))?;
+
     // Initialize logging with environment filter (RUST_LOG)
     // Default to info if not set
     let filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("info"));
 
-    let _ = FmtSubscriber::builder()
-        .with_env_filter(filter)
-        .with_target(false)
-        .with_thread_ids(false)
-        .compact()
+    // In stdio mode stdout carries the protocol, so logs go to stderr
+    let writer = if stdio.is_some() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+
+    // The env filter applies to the console only; MCP clients pick their
+    // own level via logging/setLevel, and each layer only sees its events
+    let _ = tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_writer(writer)
+                .with_target(false)
+                .with_thread_ids(false)
+                .compact()
+                .with_filter(filter),
+        )
+        .with(McpLogLayer.with_filter(filter_fn(mcp_logging::forwards)))
+        .with(ToolOutputLayer.with_filter(filter_fn(tool_output::is_output)))
         .try_init();
 
     info!("Starting op-web server...");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
 
     let state = Arc::new(AppState::new().await?);
+
+    if let Some(options) = stdio {
+        return mcp_stdio::serve(state, options).await;
+    }
+
     let app = routes::create_router(state);
 
     let port = std::env::var("PORT")
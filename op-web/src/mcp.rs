@@ -3,15 +3,23 @@
 //! Implements the Model Context Protocol (MCP) server endpoints.
 //! - Standard Mode: Exposes all tools via `tools/list`
 //! - Compact Mode: Exposes meta-tools via `mcp_compact` module
-//! - SSE Support: For server-initiated events
+//! - Profile Mode: `/mcp/p/{profile}` scopes both modes to a tool groups profile
+//! - Negotiated: `/mcp` picks the mode per client via `mcp_smart_router`
+//! - SSE Support: For server-initiated events (progress, per-session responses)
 
 use axum::{
-    extract::{Extension, Json},
-    response::sse::{Event, Sse},
+    body::Bytes,
+    extract::{ConnectInfo, Extension, Json, Path, Query},
+    http::StatusCode,
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
     routing::{get, post},
     Router,
 };
 use futures::stream::{self, Stream};
+use op_core::security::AccessZone;
 use serde::{Deserialize, Serialize};
 use simd_json::{json, OwnedValue as Value};
 use simd_json::prelude::*;
@@ -22,7 +30,15 @@ use tokio_stream::wrappers::BroadcastStream;
 use tokio_stream::StreamExt;
 use tracing::{info, debug, error};
 
+use crate::mcp_completion;
+use crate::mcp_discovery::{self, RemoteEndpoint};
+use crate::mcp_elicitation;
+use crate::mcp_logging;
+use crate::mcp_session::{self, McpSession, SessionQuery, MCP_SESSIONS};
+use crate::mcp_smart_router::McpMode;
+use crate::mcp_stdio::StdioOptions;
 use crate::state::AppState;
+use crate::tool_profiles::{self, ToolProfile};
 
 /// SSE broadcaster for MCP responses
 #[derive(Clone)]
@@ -109,21 +125,40 @@ pub fn create_mcp_router() -> Router {
         .route("/compact", get(crate::mcp_compact::mcp_compact_sse_handler).post(crate::mcp_compact::mcp_compact_message_handler))
         .route("/compact/message", post(crate::mcp_compact::mcp_compact_message_handler))
         
+        // Negotiated entry point (full, compact or agents per client)
+        .merge(crate::mcp_smart_router::create_smart_mcp_router())
+
         // Standard endpoints (all tools)
-        .route("/", post(mcp_handler))
         .route("/sse", get(mcp_sse_handler))
         .route("/message", post(mcp_message_handler))
+
+        // Profile-scoped endpoints (tools limited to a groups_admin profile)
+        .route("/p/:profile", post(profile_mcp_handler))
+        .route("/p/:profile/compact", get(crate::mcp_compact::mcp_compact_profile_sse_handler).post(crate::mcp_compact::mcp_compact_profile_message_handler))
+        .route("/p/:profile/compact/message", post(crate::mcp_compact::mcp_compact_profile_message_handler))
         
         // Configuration
         .route("/_config", get(config_handler))
 }
 
-/// Standard Handler (all tools)
-async fn mcp_handler(
+/// Profile-scoped handler (tools limited to the profile's groups)
+async fn profile_mcp_handler(
     Extension(state): Extension<Arc<AppState>>,
+    Path(profile): Path<String>,
     Json(request): Json<McpRequest>,
 ) -> Json<McpResponse> {
-    let response = process_request(&state, request).await;
+    let profile = match ToolProfile::load(&profile).await {
+        Some(p) => p,
+        None => {
+            return Json(McpResponse::error(
+                request.id,
+                -32602,
+                format!("Unknown tool profile: {}", profile),
+            ))
+        }
+    };
+
+    let response = process_request(&state, request, Some(&profile), None).await;
     Json(response)
 }
 
@@ -132,7 +167,7 @@ pub async fn jsonrpc_handler(
     Extension(state): Extension<Arc<AppState>>,
     Json(request): Json<McpRequest>,
 ) -> Json<McpResponse> {
-    let response = process_request(&state, request).await;
+    let response = process_request(&state, request, None, None).await;
     Json(response)
 }
 
@@ -141,20 +176,11 @@ async fn mcp_sse_handler(
     headers: axum::http::HeaderMap,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     let rx = GLOBAL_BROADCASTER.subscribe();
+    let (session, session_stream) = MCP_SESSIONS.open();
 
-    info!("SSE client connected");
-
-    let host = headers
-        .get("host")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("localhost");
-
-    let scheme = headers
-        .get("x-forwarded-proto")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("http");
+    info!("SSE client connected (session: {})", session.id);
 
-    let post_url = format!("{}://{}/mcp/message", scheme, host);
+    let post_url = mcp_session::endpoint_url(&headers, "/mcp/message", &session);
     
     // Initial endpoint event
     let endpoint_event = Event::default()
@@ -168,9 +194,9 @@ async fn mcp_sse_handler(
         }
     });
     
-    // Combine initial event with broadcast stream
+    // Combine initial event with session messages and legacy broadcasts
     let combined_stream = stream::once(async move { Ok(endpoint_event) })
-        .chain(stream);
+        .chain(stream::select(session_stream, stream));
 
     Sse::new(combined_stream).keep_alive(
         axum::response::sse::KeepAlive::new()
@@ -182,22 +208,51 @@ async fn mcp_sse_handler(
 /// Message handler for MCP requests (with SSE broadcasting)
 async fn mcp_message_handler(
     Extension(state): Extension<Arc<AppState>>,
-    Json(request): Json<McpRequest>,
-) -> Json<McpResponse> {
+    Query(query): Query<SessionQuery>,
+    body: Bytes,
+) -> Response {
+    let session = MCP_SESSIONS.from_query(&query);
+    let request: McpRequest = match mcp_session::parse_post(session.as_ref(), &body) {
+        Ok(Some(request)) => request,
+        // A reply to a server-initiated request, already routed
+        Ok(None) => return StatusCode::ACCEPTED.into_response(),
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(McpResponse::error(None, -32700, format!("Parse error: {}", e))),
+            )
+                .into_response()
+        }
+    };
     info!("MCP message received (method: {})", request.method);
 
-    let response = process_request(&state, request).await;
-
-    // Broadcast to SSE clients
-    if let Ok(json) = simd_json::to_string(&response) {
-        GLOBAL_BROADCASTER.broadcast(&json);
+    let response = mcp_logging::with_session(
+        session.as_ref(),
+        process_request(&state, request, None, session.as_ref()),
+    )
+    .await;
+
+    // Reply on the caller's own stream; sessionless clients get the legacy broadcast
+    match &session {
+        Some(_) if response.id.is_none() => {}
+        Some(s) => s.send(&response),
+        None => {
+            if let Ok(json) = simd_json::to_string(&response) {
+                GLOBAL_BROADCASTER.broadcast(&json);
+            }
+        }
     }
 
-    Json(response)
+    Json(response).into_response()
 }
 
-/// Process a single MCP request
-async fn process_request(state: &AppState, request: McpRequest) -> McpResponse {
+/// Process a single MCP request, optionally scoped to a tool profile
+pub(crate) async fn process_request(
+    state: &AppState,
+    request: McpRequest,
+    profile: Option<&ToolProfile>,
+    session: Option<&Arc<McpSession>>,
+) -> McpResponse {
     debug!("MCP request: {} (id: {:?})", request.method, request.id);
 
     // Validate JSON-RPC version
@@ -210,14 +265,40 @@ async fn process_request(state: &AppState, request: McpRequest) -> McpResponse {
     }
 
     match request.method.as_str() {
-        "initialize" => handle_initialize(request.id).await,
+        "initialize" => {
+            mcp_session::record_initialize(session, request.params.as_ref().unwrap_or(&json!({})));
+            handle_initialize(request.id).await
+        }
         "initialized" => handle_initialized(request.id).await,
-        "tools/list" => handle_tools_list(state, request.id, request.params).await,
-        "tools/call" => handle_tools_call(state, request.id, request.params).await,
+        "tools/list" => handle_tools_list(state, request.id, request.params, profile).await,
+        "tools/call" => {
+            tool_profiles::with_profile(
+                profile,
+                handle_tools_call(state, request.id, request.params, profile, session),
+            )
+            .await
+        }
         "resources/list" => handle_resources_list(request.id).await,
         "resources/read" => handle_resources_read(request.id, request.params).await,
         "prompts/list" => handle_prompts_list(request.id).await,
+        "completion/complete" => {
+            let params = request.params.unwrap_or_else(|| json!({}));
+            match mcp_completion::complete(&state.executor, &state.tool_registry, profile, "mcp", &params).await {
+                Ok(result) => McpResponse::success(request.id, result),
+                Err(e) => McpResponse::error(request.id, -32602, e),
+            }
+        }
         "ping" => handle_ping(request.id).await,
+        "notifications/cancelled" => {
+            mcp_session::handle_cancelled(session, request.params.as_ref().unwrap_or(&json!({})));
+            McpResponse::success(request.id, json!({}))
+        }
+        "logging/setLevel" => {
+            match mcp_logging::handle_set_level(session, request.params.as_ref().unwrap_or(&json!({}))) {
+                Ok(result) => McpResponse::success(request.id, result),
+                Err(e) => McpResponse::error(request.id, -32602, e),
+            }
+        }
         _ => McpResponse::error(
             request.id,
             -32601,
@@ -239,7 +320,9 @@ async fn handle_initialize(id: Option<Value>) -> McpResponse {
             },
             "prompts": {
                 "listChanged": false
-            }
+            },
+            "logging": {},
+            "completions": {}
         },
         "serverInfo": {
             "name": "op-dbus-mcp",
@@ -256,8 +339,9 @@ async fn handle_tools_list(
     state: &AppState, 
     id: Option<Value>, 
     _params: Option<Value>,
+    profile: Option<&ToolProfile>,
 ) -> McpResponse {
-    let tools = state.tool_registry.list().await;
+    let tools = tool_profiles::list_scoped(&state.tool_registry, profile).await;
     
     let tool_list: Vec<Value> = tools.iter().map(|t| {
         json!({
@@ -276,6 +360,8 @@ async fn handle_tools_call(
     state: &AppState,
     id: Option<Value>,
     params: Option<Value>,
+    profile: Option<&ToolProfile>,
+    session: Option<&Arc<McpSession>>,
 ) -> McpResponse {
     let params = match params {
         Some(p) => p,
@@ -291,13 +377,53 @@ async fn handle_tools_call(
 
     info!("MCP tool call: {} with args: {:?}", tool_name, arguments);
 
+    if let Err(e) = tool_profiles::check_allowed(&state.tool_registry, profile, tool_name).await {
+        return McpResponse::error(id, -32602, e);
+    }
+
     // Get and execute tool
     let tool = match state.tool_registry.get(tool_name).await {
         Some(t) => t,
         None => return McpResponse::error(id, -32602, format!("Tool not found: {}", tool_name)),
     };
 
-    match tool.execute(arguments).await {
+    // Ask the user for missing inputs or confirmation (clients with elicitation)
+    let mut arguments = match state.tool_registry.get_definition(tool_name).await {
+        Some(definition) => {
+            match mcp_elicitation::prepare_arguments(session, tool_name, &definition.input_schema, arguments).await {
+                Ok(arguments) => arguments,
+                Err(e) => {
+                    return McpResponse::success(id, json!({
+                        "content": [{ "type": "text", "text": e }],
+                        "isError": true
+                    }))
+                }
+            }
+        }
+        None => arguments,
+    };
+
+    let result = if let Some(cached) = state.executor.cached("mcp", tool_name, &arguments).await {
+        Ok(cached)
+    } else {
+        let request_id = id.clone().unwrap_or_default();
+        let execution = state.executor.begin("mcp", tool_name, &mut arguments).await;
+        let outcome = mcp_session::run_request(session, &request_id, &params, execution.run(tool.execute(arguments))).await;
+
+        let result = match outcome {
+            Ok(result) => result,
+            Err(cancelled) => {
+                execution.complete(Err(cancelled.to_string())).await;
+                return McpResponse::error(id, mcp_session::REQUEST_CANCELLED, cancelled.to_string());
+            }
+        };
+        execution
+            .complete(result.as_ref().map(Clone::clone).map_err(|e| e.to_string()))
+            .await;
+        result
+    };
+
+    match result {
         Ok(result) => McpResponse::success(id, json!({
             "content": [{
                 "type": "text",
@@ -336,16 +462,78 @@ async fn handle_ping(id: Option<Value>) -> McpResponse {
     McpResponse::success(id, json!({}))
 }
 
+/// Query for `/mcp/_config`
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigQuery {
+    pub mode: Option<String>,
+    pub profile: Option<String>,
+    /// Render for a specific client: `claude`, `cursor` or `vscode`
+    pub client: Option<String>,
+}
+
 /// GET /mcp/_config - Generate config for clients
+///
+/// `op-dbus` launches this binary with `--mcp-stdio` (by path only for
+/// local callers); `op-dbus-remote` points at the HTTP endpoint. `?mode=`
+/// and `?profile=` carry over to both, and `?client=` returns the snippet in
+/// that client's settings format.
 pub async fn config_handler(
-    Extension(_state): Extension<Arc<AppState>>,
-) -> Json<Value> {
+    connect_info: Option<ConnectInfo<std::net::SocketAddr>>,
+    headers: axum::http::HeaderMap,
+    zone: Option<Extension<AccessZone>>,
+    Query(query): Query<ConfigQuery>,
+) -> Response {
+    let options = StdioOptions {
+        mode: query.mode.as_deref().and_then(McpMode::parse),
+        profile: query.profile.clone(),
+    };
+
+    let path = match (&options.profile, options.mode) {
+        (Some(profile), Some(McpMode::Compact)) => format!("/mcp/p/{}/compact", profile),
+        (Some(profile), _) => format!("/mcp/p/{}", profile),
+        (None, _) => "/mcp".to_string(),
+    };
+    let mut remote = RemoteEndpoint {
+        url: format!("{}{}", mcp_discovery::base_url(&headers, connect_info.map(|ci| ci.0).as_ref()), path),
+        headers: Vec::new(),
+    };
+    if let (None, Some(mode)) = (&options.profile, options.mode) {
+        remote.headers.push(("x-mcp-mode".to_string(), mode.as_str().to_string()));
+    }
+    let zone = zone.map(|Extension(zone)| zone);
+    let command = mcp_discovery::stdio_command_for(zone.as_ref());
+    if mcp_discovery::auth_required(zone.as_ref()) {
+        remote.headers.push(("x-api-key".to_string(), "<your-api-key>".to_string()));
+    }
+
+    if let Some(client) = query.client.as_deref() {
+        return match mcp_discovery::client_config(client, &command, &options.to_args(), &remote) {
+            Ok(config) => Json(config).into_response(),
+            Err(e) => (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))).into_response(),
+        };
+    }
+
+    let mut remote_server = json!({ "url": remote.url });
+    if !remote.headers.is_empty() {
+        let mut remote_headers = json!({});
+        if let Some(obj) = remote_headers.as_object_mut() {
+            for (name, value) in remote.headers {
+                obj.insert(name.into(), json!(value));
+            }
+        }
+        if let Some(obj) = remote_server.as_object_mut() {
+            obj.insert("headers".into(), remote_headers);
+        }
+    }
+
     Json(json!({
         "mcpServers": {
             "op-dbus": {
-                "command": "op-mcp-server",
-                "args": []
-            }
+                "command": command,
+                "args": options.to_args()
+            },
+            "op-dbus-remote": remote_server
         }
     }))
+    .into_response()
 }
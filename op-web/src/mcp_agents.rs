@@ -1,17 +1,30 @@
 //! MCP Agents Server - Critical Agents for Chat UI
 //!
 //! Provides MCP-compatible access to core orchestration agents plus
-//! additional requested agents.
+//! additional requested agents. Agent calls honour `_meta.progressToken`
+//! and `notifications/cancelled` for clients connected over SSE.
+//!
+//! The roster comes from `/etc/op-dbus/agents-roster.json` (any type from
+//! `op_agents::list_agent_types()`) and can be changed at runtime through
+//! `/admin/agents/roster`, which notifies clients with `tools/list_changed`.
+//! Tool input schemas are the agents' own (`operation_schema`), and a roster
+//! entry may override them per operation.
+//!
+//! Reasoning agents (`sequential_thinking`, `prompt_engineer`, `debugger`)
+//! append a model-written analysis when the client supports MCP sampling,
+//! using the client's model. Other callers opt in with `"assist": true`,
+//! which uses the server's LLM provider.
 
 use axum::{routing::{get, post},
-    extract::{Json, Extension},
+    body::Bytes,
+    extract::{Json, Extension, Path, Query},
     http::{HeaderMap, StatusCode},
     response::{
         sse::{Event, Sse},
         IntoResponse, Response,
     },
 };
-use futures::stream::{self, Stream};
+use futures::stream::{self, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use simd_json::{json, OwnedValue as Value};
 use simd_json::prelude::*;
@@ -22,21 +35,10 @@ use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 use op_agents::agents::base::{AgentTrait as Agent, AgentTask};
-use op_agents::agents::orchestration::context_manager::ContextManagerAgent;
-use op_agents::agents::orchestration::memory::MemoryAgent;
-use op_agents::agents::orchestration::sequential_thinking::SequentialThinkingAgent;
-use op_agents::agents::orchestration::mem0_wrapper::Mem0WrapperAgent;
-
-// Additional agents
-use op_agents::agents::seo::search_specialist::SearchSpecialistAgent;
-use op_agents::agents::infrastructure::deployment::DeploymentAgent;
-use op_agents::agents::infrastructure::network::NetworkEngineerAgent;
-use op_agents::agents::language::python_pro::PythonProAgent;
-use op_agents::agents::language::rust_pro::RustProAgent;
-use op_agents::agents::architecture::BackendArchitectAgent;
-use op_agents::agents::analysis::debugger::DebuggerAgent;
-use op_agents::agents::aiml::prompt_engineer::PromptEngineerAgent;
-use op_agents::agents::security::BackendSecurityCoderAgent;
+
+use crate::mcp_logging;
+use crate::mcp_sampling::{self, Completion, SamplingRequest};
+use crate::mcp_session::{self, McpSession, SessionQuery, MCP_SESSIONS};
 
 #[derive(Debug, Deserialize)]
 pub struct JsonRpcRequest {
@@ -90,330 +92,270 @@ impl JsonRpcResponse {
     }
 }
 
+const AGENTS_ROSTER_PATH: &str = "/etc/op-dbus/agents-roster.json";
+
+/// Agent types served when no roster config exists
+const DEFAULT_AGENT_TYPES: &[&str] = &[
+    // Core orchestration agents (memory, cognitive)
+    "memory",
+    "context_manager",
+    "sequential_thinking",
+    "mem0",
+    // Language agents
+    "rust_pro",
+    "python_pro",
+    // Architecture and security
+    "backend_architect",
+    "backend_security_coder",
+    // Network
+    "network_engineer",
+    // Frequently used utility agents
+    "search_specialist",
+    "deployment",
+    "debugger",
+    "prompt_engineer",
+];
+
+/// One agent exposed by the agents MCP endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RosterEntry {
+    /// Agent type from `op_agents::list_agent_types()`
+    #[serde(rename = "type")]
+    pub agent_type: String,
+    /// Tool name prefix, defaults to the type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Operation input schemas that override the ones the agent supplies
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub schemas: HashMap<String, Value>,
+}
+
+impl RosterEntry {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.agent_type)
+    }
+}
+
+/// Roster configuration (`/etc/op-dbus/agents-roster.json`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRoster {
+    pub agents: Vec<RosterEntry>,
+}
+
+impl Default for AgentRoster {
+    fn default() -> Self {
+        Self {
+            agents: DEFAULT_AGENT_TYPES
+                .iter()
+                .map(|t| RosterEntry {
+                    agent_type: t.to_string(),
+                    name: None,
+                    schemas: HashMap::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl AgentRoster {
+    pub fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(AGENTS_ROSTER_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<AgentRoster>(&mut raw) } {
+            Ok(roster) => {
+                info!("Loaded agent roster from {} ({} agents)", AGENTS_ROSTER_PATH, roster.agents.len());
+                roster
+            }
+            Err(e) => {
+                warn!("Invalid agent roster {}: {}, using defaults", AGENTS_ROSTER_PATH, e);
+                Self::default()
+            }
+        }
+    }
+
+    async fn save(&self) {
+        if let Ok(json) = simd_json::to_string_pretty(self) {
+            if let Err(e) = tokio::fs::write(AGENTS_ROSTER_PATH, json).await {
+                error!("Failed to save agent roster: {}", e);
+            }
+        }
+    }
+}
+
+struct RosterAgent {
+    entry: RosterEntry,
+    agent: Arc<dyn Agent + Send + Sync>,
+}
+
 pub struct CriticalAgentsState {
-    agents: Vec<Arc<dyn Agent + Send + Sync>>,
+    roster: Vec<RosterAgent>,
+    /// Tool name -> (roster index, operation)
+    tools: HashMap<String, (usize, String)>,
 }
 
 impl CriticalAgentsState {
     pub fn new() -> Self {
-        let agents: Vec<Arc<dyn Agent + Send + Sync>> = vec![
-            // Core orchestration agents (memory, cognitive)
-            Arc::new(MemoryAgent::new("memory".to_string())),
-            Arc::new(ContextManagerAgent::new("context_manager".to_string())),
-            Arc::new(SequentialThinkingAgent::new("sequential_thinking".to_string())),
-            Arc::new(Mem0WrapperAgent::new("mem0".to_string())),
-            // Language agents (rust_pro, python)
-            Arc::new(RustProAgent::new("rust_pro".to_string())),
-            Arc::new(PythonProAgent::new("python_pro".to_string())),
-            // Architecture agents (backend_architect)
-            Arc::new(BackendArchitectAgent::new("backend_architect".to_string())),
-            // Security agents (backend_security_coder)
-            Arc::new(BackendSecurityCoderAgent::new("backend_security_coder".to_string())),
-            // Network agents
-            Arc::new(NetworkEngineerAgent::new("network_engineer".to_string())),
-            // Frequently used utility agents
-            Arc::new(SearchSpecialistAgent::new("search_specialist".to_string())),
-            Arc::new(DeploymentAgent::new("deployment".to_string())),
-            Arc::new(DebuggerAgent::new("debugger".to_string())),
-            Arc::new(PromptEngineerAgent::new("prompt_engineer".to_string())),
-        ];
-
-        info!("Initialized Critical Agents MCP with {} agents", agents.len());
-        Self { agents }
+        Self::from_roster(AgentRoster::load())
+    }
+
+    pub fn from_roster(roster: AgentRoster) -> Self {
+        let mut state = Self {
+            roster: Vec::new(),
+            tools: HashMap::new(),
+        };
+        for entry in roster.agents {
+            if let Err(e) = state.insert(entry) {
+                warn!("Skipping agent: {}", e);
+            }
+        }
+
+        info!("Initialized Critical Agents MCP with {} agents", state.roster.len());
+        state
+    }
+
+    fn instantiate(entry: &RosterEntry) -> Result<Arc<dyn Agent + Send + Sync>, String> {
+        let known = op_agents::list_agent_types()
+            .iter()
+            .any(|t| t.to_string() == entry.agent_type);
+        if !known {
+            return Err(format!("Unknown agent type: {}", entry.agent_type));
+        }
+
+        op_agents::create_agent(&entry.agent_type, entry.name().to_string())
+            .map(Arc::from)
+            .map_err(|e| format!("Failed to create agent {}: {}", entry.agent_type, e))
+    }
+
+    fn insert(&mut self, entry: RosterEntry) -> Result<(), String> {
+        if self.roster.iter().any(|a| a.entry.name() == entry.name()) {
+            return Err(format!("Agent already in roster: {}", entry.name()));
+        }
+        let agent = Self::instantiate(&entry)?;
+        self.roster.push(RosterAgent { entry, agent });
+        self.reindex();
+        Ok(())
+    }
+
+    fn reindex(&mut self) {
+        self.tools.clear();
+        for (index, roster_agent) in self.roster.iter().enumerate() {
+            for op in roster_agent.agent.operations() {
+                let tool_name = format!("{}_{}", roster_agent.entry.name(), op);
+                if self.tools.contains_key(&tool_name) {
+                    warn!("Duplicate agent tool name {}, keeping the first", tool_name);
+                    continue;
+                }
+                self.tools.insert(tool_name, (index, op.clone()));
+            }
+        }
+    }
+
+    /// Current roster configuration
+    pub fn roster(&self) -> AgentRoster {
+        AgentRoster {
+            agents: self.roster.iter().map(|a| a.entry.clone()).collect(),
+        }
+    }
+
+    /// Add an agent to the roster
+    pub fn add(&mut self, entry: RosterEntry) -> Result<(), String> {
+        self.insert(entry)
+    }
+
+    /// Remove an agent by name; returns false if it was not in the roster
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.roster.len();
+        self.roster.retain(|a| a.entry.name() != name);
+        let removed = self.roster.len() != before;
+        if removed {
+            self.reindex();
+        }
+        removed
+    }
+
+    /// Agents with their tool names, for discovery
+    pub fn summary(&self) -> Vec<Value> {
+        self.roster
+            .iter()
+            .map(|roster_agent| {
+                let name = roster_agent.entry.name();
+                let tools: Vec<String> = roster_agent
+                    .agent
+                    .operations()
+                    .iter()
+                    .map(|op| format!("{}_{}", name, op))
+                    .collect();
+                json!({
+                    "name": name,
+                    "type": roster_agent.entry.agent_type.clone(),
+                    "description": roster_agent.agent.description(),
+                    "tools": tools
+                })
+            })
+            .collect()
     }
 
     pub fn get_tools(&self) -> Vec<Value> {
         let mut tools = Vec::new();
-        for agent in &self.agents {
-            let agent_name = agent.name();
-            let agent_description = agent.description();
-            for op in agent.operations() {
+        for roster_agent in &self.roster {
+            let agent_name = roster_agent.entry.name();
+            let agent_description = roster_agent.agent.description();
+            for op in roster_agent.agent.operations() {
                 let tool_name = format!("{}_{}", agent_name, op);
                 let description = format!("{} - {}", agent_description, op);
                 tools.push(json!({
                     "name": tool_name,
                     "description": description,
-                    "inputSchema": self.get_operation_schema(agent_name, &op)
+                    "inputSchema": operation_schema(&roster_agent.entry, roster_agent.agent.as_ref(), &op)
                 }));
             }
         }
         tools
     }
 
-    fn get_operation_schema(&self, agent_name: &str, operation: &str) -> Value {
-        match (agent_name, operation) {
-            // Memory agent
-            ("memory", "remember") => json!({
-                "type": "object",
-                "properties": {
-                    "key": {"type": "string", "description": "Memory key"},
-                    "value": {"type": "string", "description": "Value to remember"}
-                },
-                "required": ["key", "value"]
-            }),
-            ("memory", "recall") => json!({
-                "type": "object",
-                "properties": {
-                    "key": {"type": "string", "description": "Memory key to recall"}
-                },
-                "required": ["key"]
-            }),
-            ("memory", "forget") => json!({
-                "type": "object",
-                "properties": {
-                    "key": {"type": "string", "description": "Memory key to forget"}
-                },
-                "required": ["key"]
-            }),
-            // Context manager
-            ("context_manager", "save") => json!({
-                "type": "object",
-                "properties": {
-                    "name": {"type": "string", "description": "Context name"},
-                    "content": {"type": "string", "description": "Context content"}
-                },
-                "required": ["name", "content"]
-            }),
-            ("context_manager", "load") => json!({
-                "type": "object",
-                "properties": {
-                    "name": {"type": "string", "description": "Context name to load"}
-                },
-                "required": ["name"]
-            }),
-            ("context_manager", "list") => json!({
-                "type": "object",
-                "properties": {},
-                "required": []
-            }),
-            ("context_manager", "delete") => json!({
-                "type": "object",
-                "properties": {
-                    "name": {"type": "string", "description": "Context name to delete"}
-                },
-                "required": ["name"]
-            }),
-            // Sequential thinking
-            ("sequential_thinking", "think") => json!({
-                "type": "object",
-                "properties": {
-                    "problem": {"type": "string", "description": "Problem to think through"},
-                    "steps": {"type": "integer", "description": "Number of thinking steps", "default": 5}
-                },
-                "required": ["problem"]
-            }),
-            // Mem0 - semantic memory
-            ("mem0", "add") => json!({
-                "type": "object",
-                "properties": {
-                    "text": {"type": "string", "description": "Text to add to semantic memory"},
-                    "user_id": {"type": "string", "description": "User ID for memory isolation", "default": "default"}
-                },
-                "required": ["text"]
-            }),
-            ("mem0", "search") => json!({
-                "type": "object",
-                "properties": {
-                    "query": {"type": "string", "description": "Search query"},
-                    "user_id": {"type": "string", "description": "User ID", "default": "default"},
-                    "limit": {"type": "integer", "description": "Max results", "default": 10}
-                },
-                "required": ["query"]
-            }),
-            ("mem0", "get_all") => json!({
-                "type": "object",
-                "properties": {
-                    "user_id": {"type": "string", "description": "User ID", "default": "default"}
-                },
-                "required": []
-            }),
-            ("mem0", "delete") => json!({
-                "type": "object",
-                "properties": {
-                    "memory_id": {"type": "string", "description": "Memory ID to delete"}
-                },
-                "required": ["memory_id"]
-            }),
-            ("mem0", "update") => json!({
-                "type": "object",
-                "properties": {
-                    "memory_id": {"type": "string", "description": "Memory ID to update"},
-                    "text": {"type": "string", "description": "New text content"}
-                },
-                "required": ["memory_id", "text"]
-            }),
-            // Search specialist
-            ("search_specialist", "search") => json!({
-                "type": "object",
-                "properties": {
-                    "query": {"type": "string", "description": "Search query"},
-                    "scope": {"type": "string", "description": "Search scope (code, docs, web)", "default": "code"}
-                },
-                "required": ["query"]
-            }),
-            ("search_specialist", "optimize") => json!({
-                "type": "object",
-                "properties": {
-                    "query": {"type": "string", "description": "Query to optimize"},
-                    "target": {"type": "string", "description": "Optimization target"}
-                },
-                "required": ["query"]
-            }),
-            // Deployment
-            ("deployment", "deploy") => json!({
-                "type": "object",
-                "properties": {
-                    "service": {"type": "string", "description": "Service to deploy"},
-                    "environment": {"type": "string", "description": "Target environment", "default": "staging"}
-                },
-                "required": ["service"]
-            }),
-            ("deployment", "rollback") => json!({
-                "type": "object",
-                "properties": {
-                    "service": {"type": "string", "description": "Service to rollback"},
-                    "version": {"type": "string", "description": "Version to rollback to"}
-                },
-                "required": ["service"]
-            }),
-            ("deployment", "status") => json!({
-                "type": "object",
-                "properties": {
-                    "service": {"type": "string", "description": "Service to check"}
-                },
-                "required": []
-            }),
-            // Python Pro
-            ("python_pro", "analyze") => json!({
-                "type": "object",
-                "properties": {
-                    "code": {"type": "string", "description": "Python code to analyze"},
-                    "path": {"type": "string", "description": "File path to analyze"}
-                },
-                "required": []
-            }),
-            ("python_pro", "refactor") => json!({
-                "type": "object",
-                "properties": {
-                    "code": {"type": "string", "description": "Python code to refactor"},
-                    "pattern": {"type": "string", "description": "Refactoring pattern"}
-                },
-                "required": ["code"]
-            }),
-            // Debugger
-            ("debugger", "analyze") => json!({
-                "type": "object",
-                "properties": {
-                    "error": {"type": "string", "description": "Error message or stack trace"},
-                    "context": {"type": "string", "description": "Additional context"}
-                },
-                "required": ["error"]
-            }),
-            ("debugger", "trace") => json!({
-                "type": "object",
-                "properties": {
-                    "function": {"type": "string", "description": "Function to trace"},
-                    "depth": {"type": "integer", "description": "Trace depth", "default": 3}
-                },
-                "required": ["function"]
-            }),
-            // Prompt Engineer
-            ("prompt_engineer", "generate") => json!({
-                "type": "object",
-                "properties": {
-                    "task": {"type": "string", "description": "Task to generate prompt for"},
-                    "style": {"type": "string", "description": "Prompt style", "default": "detailed"}
-                },
-                "required": ["task"]
-            }),
-            ("prompt_engineer", "optimize") => json!({
-                "type": "object",
-                "properties": {
-                    "prompt": {"type": "string", "description": "Prompt to optimize"},
-                    "goal": {"type": "string", "description": "Optimization goal"}
-                },
-                "required": ["prompt"]
-            }),
-            // Backend Security Coder
-            ("backend_security_coder", "secure_endpoint") => json!({
-                "type": "object",
-                "properties": {
-                    "code": {"type": "string", "description": "Endpoint code to secure"},
-                    "framework": {"type": "string", "description": "Web framework (express, axum, etc.)", "default": "axum"}
-                },
-                "required": ["code"]
-            }),
-            ("backend_security_coder", "audit_code") => json!({
-                "type": "object",
-                "properties": {
-                    "code": {"type": "string", "description": "Code to audit for security issues"},
-                    "scope": {"type": "string", "description": "Audit scope (full, auth, input)", "default": "full"}
-                },
-                "required": ["code"]
-            }),
-            ("backend_security_coder", "fix_vulnerability") => json!({
-                "type": "object",
-                "properties": {
-                    "vulnerability": {"type": "string", "description": "Vulnerability type (sql_injection, xss, etc.)"},
-                    "code": {"type": "string", "description": "Code containing the vulnerability"}
-                },
-                "required": ["vulnerability", "code"]
-            }),
-            ("backend_security_coder", "analyze") => json!({
-                "type": "object",
-                "properties": {
-                    "input": {"type": "string", "description": "Security concern to analyze"}
-                },
-                "required": ["input"]
-            }),
-            // Rust Pro additional ops
-            ("rust_pro", "analyze") => json!({
-                "type": "object",
-                "properties": {
-                    "code": {"type": "string", "description": "Rust code to analyze"},
-                    "path": {"type": "string", "description": "File path to analyze"}
-                },
-                "required": []
-            }),
-            ("rust_pro", "refactor") => json!({
-                "type": "object",
-                "properties": {
-                    "code": {"type": "string", "description": "Rust code to refactor"},
-                    "pattern": {"type": "string", "description": "Refactoring pattern"}
-                },
-                "required": ["code"]
-            }),
-            ("rust_pro", "implement") => json!({
-                "type": "object",
-                "properties": {
-                    "spec": {"type": "string", "description": "Implementation specification"},
-                    "trait": {"type": "string", "description": "Trait to implement"}
-                },
-                "required": ["spec"]
-            }),
-            // Default
-            _ => json!({
+    /// Resolve a tool name to its agent, agent type and operation
+    pub fn find_agent(&self, tool_name: &str) -> Option<(Arc<dyn Agent + Send + Sync>, String, String)> {
+        let (index, op) = self.tools.get(tool_name)?;
+        let roster_agent = &self.roster[*index];
+        Some((
+            roster_agent.agent.clone(),
+            roster_agent.entry.agent_type.clone(),
+            op.clone(),
+        ))
+    }
+}
+
+/// Schema for an agent operation: roster override, then the agent's own, then generic
+fn operation_schema(entry: &RosterEntry, agent: &(dyn Agent + Send + Sync), operation: &str) -> Value {
+    let mut schema = entry
+        .schemas
+        .get(operation)
+        .cloned()
+        .or_else(|| agent.operation_schema(operation))
+        .unwrap_or_else(|| {
+            json!({
                 "type": "object",
                 "properties": {
                     "args": {"type": "object", "description": "Operation arguments"}
                 },
                 "required": []
             })
+        });
+    if is_model_assisted(&entry.agent_type) {
+        if let Some(properties) = schema.get_mut("properties").and_then(|p| p.as_object_mut()) {
+            properties.insert(
+                ASSIST_ARGUMENT.into(),
+                json!({
+                    "type": "boolean",
+                    "description": "Add a model-written analysis using the server's LLM provider (automatic when the client supports sampling)",
+                    "default": false
+                }),
+            );
         }
     }
-
-    pub fn find_agent(&self, tool_name: &str) -> Option<(&Arc<dyn Agent + Send + Sync>, String)> {
-        for agent in &self.agents {
-            let agent_name = agent.name();
-            for op in agent.operations() {
-                let expected_tool = format!("{}_{}", agent_name, op);
-                if expected_tool == tool_name {
-                    return Some((agent, op.clone()));
-                }
-            }
-        }
-        None
-    }
+    schema
 }
 
 impl Default for CriticalAgentsState {
@@ -453,6 +395,54 @@ lazy_static::lazy_static! {
     static ref GLOBAL_AGENTS_STATE: Arc<AgentsMcpState> = Arc::new(AgentsMcpState::new());
 }
 
+/// Shared agents state used by the stateless and negotiated endpoints
+pub(crate) fn global_state() -> Arc<AgentsMcpState> {
+    GLOBAL_AGENTS_STATE.clone()
+}
+
+/// GET /admin/agents/roster - Current agent roster
+pub async fn roster_list_handler() -> Json<Value> {
+    let agents = GLOBAL_AGENTS_STATE.agents.read().await;
+    let roster = agents.roster();
+    Json(json!({
+        "agents": roster.agents,
+        "available_types": op_agents::list_agent_types()
+    }))
+}
+
+/// POST /admin/agents/roster - Add an agent to the roster
+pub async fn roster_add_handler(Json(entry): Json<RosterEntry>) -> Response {
+    let name = entry.name().to_string();
+    let mut agents = GLOBAL_AGENTS_STATE.agents.write().await;
+    if let Err(e) = agents.add(entry) {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "success": false, "error": e }))).into_response();
+    }
+    agents.roster().save().await;
+    drop(agents);
+
+    info!("Added agent {} to the agents MCP roster", name);
+    MCP_SESSIONS.notify_all("notifications/tools/list_changed", json!({}));
+    Json(json!({ "success": true, "name": name })).into_response()
+}
+
+/// DELETE /admin/agents/roster/:name - Remove an agent from the roster
+pub async fn roster_remove_handler(Path(name): Path<String>) -> Response {
+    let mut agents = GLOBAL_AGENTS_STATE.agents.write().await;
+    if !agents.remove(&name) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "success": false, "error": format!("Agent not in roster: {}", name) })),
+        )
+            .into_response();
+    }
+    agents.roster().save().await;
+    drop(agents);
+
+    info!("Removed agent {} from the agents MCP roster", name);
+    MCP_SESSIONS.notify_all("notifications/tools/list_changed", json!({}));
+    Json(json!({ "success": true, "name": name })).into_response()
+}
+
 /// Stateless SSE handler that uses global state
 /// Used when nesting under the main MCP router without its own state
 pub async fn mcp_agents_sse_handler_stateless(
@@ -464,10 +454,11 @@ pub async fn mcp_agents_sse_handler_stateless(
 /// Stateless message handler that uses global state
 /// Used when nesting under the main MCP router
 pub async fn mcp_agents_message_handler_stateless(
-    Json(request): Json<JsonRpcRequest>,
+    query: Query<SessionQuery>,
+    body: Bytes,
 ) -> Response {
     let state = GLOBAL_AGENTS_STATE.clone();
-    mcp_agents_message_handler(Extension(state), Json(request)).await
+    mcp_agents_message_handler(Extension(state), query, body).await
 }
 
 pub async fn mcp_agents_sse_handler(
@@ -475,24 +466,15 @@ pub async fn mcp_agents_sse_handler(
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     info!("MCP Agents SSE client connected");
 
-    let host = headers
-        .get("host")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("localhost");
-
-    let scheme = headers
-        .get("x-forwarded-proto")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("http");
-
-    let post_url = format!("{}://{}/mcp/agents/message", scheme, host);
+    let (session, session_stream) = MCP_SESSIONS.open();
+    let post_url = mcp_session::endpoint_url(&headers, "/mcp/agents/message", &session);
     info!("MCP Agents POST endpoint: {}", post_url);
 
     let endpoint_event = Event::default()
         .event("endpoint")
         .data(&post_url);
 
-    let stream = stream::once(async move { Ok(endpoint_event) });
+    let stream = stream::once(async move { Ok(endpoint_event) }).chain(session_stream);
 
     Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
@@ -503,26 +485,20 @@ pub async fn mcp_agents_sse_handler(
 
 pub async fn mcp_agents_message_handler(
     Extension(state): Extension<Arc<AgentsMcpState>>,
-    Json(request): Json<JsonRpcRequest>,
+    Query(query): Query<SessionQuery>,
+    body: Bytes,
 ) -> Response {
-    debug!("MCP Agents request: method={} id={}", request.method, request.id);
-
-    let response = match request.method.as_str() {
-        "initialize" => handle_initialize(&request),
-        "initialized" => JsonRpcResponse::success(request.id.clone(), json!({})),
-        "tools/list" => handle_tools_list(&state, &request).await,
-        "tools/call" => handle_tools_call(&state, &request).await,
-        "ping" => JsonRpcResponse::success(request.id.clone(), json!({})),
-        "notifications/initialized" => JsonRpcResponse::success(request.id.clone(), json!({})),
-        _ => {
-            warn!("Unknown MCP method: {}", request.method);
-            JsonRpcResponse::error(
-                request.id.clone(),
-                -32601,
-                format!("Method not found: {}", request.method),
-            )
+    let session = MCP_SESSIONS.from_query(&query);
+    let request: JsonRpcRequest = match mcp_session::parse_post(session.as_ref(), &body) {
+        Ok(Some(request)) => request,
+        // A reply to a server-initiated request, already routed
+        Ok(None) => return StatusCode::ACCEPTED.into_response(),
+        Err(e) => {
+            let response = JsonRpcResponse::error(json!(null), -32700, format!("Parse error: {}", e));
+            return Json(response).into_response();
         }
     };
+    let response = dispatch(&state, &request, session.as_ref()).await;
 
     let json_body = simd_json::to_string(&response).unwrap_or_else(|e| {
         error!("Failed to serialize response: {}", e);
@@ -538,6 +514,49 @@ pub async fn mcp_agents_message_handler(
         })
 }
 
+/// Dispatch a single agents MCP request
+pub(crate) async fn dispatch(
+    state: &Arc<AgentsMcpState>,
+    request: &JsonRpcRequest,
+    session: Option<&Arc<McpSession>>,
+) -> JsonRpcResponse {
+    debug!("MCP Agents request: method={} id={}", request.method, request.id);
+
+    if request.jsonrpc != "2.0" {
+        return JsonRpcResponse::error(request.id.clone(), -32600, "Invalid JSON-RPC version".to_string());
+    }
+
+    match request.method.as_str() {
+        "initialize" => {
+            mcp_session::record_initialize(session, &request.params);
+            handle_initialize(request)
+        }
+        "initialized" => JsonRpcResponse::success(request.id.clone(), json!({})),
+        "tools/list" => handle_tools_list(state, request).await,
+        "tools/call" => {
+            mcp_logging::with_session(session, handle_tools_call(state, request, session)).await
+        }
+        "ping" => JsonRpcResponse::success(request.id.clone(), json!({})),
+        "notifications/initialized" => JsonRpcResponse::success(request.id.clone(), json!({})),
+        "notifications/cancelled" => {
+            mcp_session::handle_cancelled(session, &request.params);
+            JsonRpcResponse::success(request.id.clone(), json!({}))
+        }
+        "logging/setLevel" => match mcp_logging::handle_set_level(session, &request.params) {
+            Ok(result) => JsonRpcResponse::success(request.id.clone(), result),
+            Err(e) => JsonRpcResponse::error(request.id.clone(), -32602, e),
+        },
+        _ => {
+            warn!("Unknown MCP method: {}", request.method);
+            JsonRpcResponse::error(
+                request.id.clone(),
+                -32601,
+                format!("Method not found: {}", request.method),
+            )
+        }
+    }
+}
+
 fn handle_initialize(request: &JsonRpcRequest) -> JsonRpcResponse {
     info!("MCP Agents initialize request");
     JsonRpcResponse::success(
@@ -546,8 +565,9 @@ fn handle_initialize(request: &JsonRpcRequest) -> JsonRpcResponse {
             "protocolVersion": "2024-11-05",
             "capabilities": {
                 "tools": {
-                    "listChanged": false
-                }
+                    "listChanged": true
+                },
+                "logging": {}
             },
             "serverInfo": {
                 "name": "op-dbus-agents",
@@ -574,6 +594,7 @@ async fn handle_tools_list(state: &Arc<AgentsMcpState>, request: &JsonRpcRequest
 async fn handle_tools_call(
     state: &Arc<AgentsMcpState>,
     request: &JsonRpcRequest,
+    session: Option<&Arc<McpSession>>,
 ) -> JsonRpcResponse {
     let params = &request.params;
     
@@ -589,7 +610,7 @@ async fn handle_tools_call(
         }
     };
     
-    let arguments = params
+    let mut arguments = params
         .get("arguments")
         .cloned()
         .unwrap_or(json!({}));
@@ -598,8 +619,8 @@ async fn handle_tools_call(
 
     let agents = state.agents.read().await;
     
-    let (agent, operation) = match agents.find_agent(tool_name) {
-        Some((a, op)) => (a.clone(), op),
+    let (agent, agent_type, operation) = match agents.find_agent(tool_name) {
+        Some(found) => found,
         None => {
             return JsonRpcResponse::success(
                 request.id.clone(),
@@ -615,11 +636,14 @@ async fn handle_tools_call(
     };
     
     drop(agents);
-    
-    let agent_type = tool_name.split('_').next().unwrap_or(tool_name);
-    
+
+    let execution = match crate::executor::global() {
+        Some(executor) => Some(executor.begin("mcp-agents", tool_name, &mut arguments).await),
+        None => None,
+    };
+    let assisted_type = agent_type.clone();
     let task = AgentTask {
-        task_type: agent_type.to_string(),
+        task_type: agent_type,
         operation: operation.clone(),
         path: arguments.get("path").and_then(|p| p.as_str()).map(String::from),
         args: Some(simd_json::to_string(&arguments).unwrap_or_else(|_| "{}".to_string())),
@@ -632,19 +656,61 @@ async fn handle_tools_call(
             .unwrap_or_default(),
     };
     
-    match agent.execute(task).await {
-        Ok(result) => {
-            let text = simd_json::to_string_pretty(&result)
-                .unwrap_or_else(|_| format!("{:?}", result));
-            JsonRpcResponse::success(
+    let run = async {
+        let call = async {
+            agent
+                .execute(task)
+                .await
+                .map(|output| {
+                    Value::from(simd_json::to_string_pretty(&output).unwrap_or_else(|_| format!("{:?}", output)))
+                })
+                .map_err(|e| anyhow::anyhow!(e.to_string()))
+        };
+        // The execution applies the caller's access and scope policies and
+        // the tool's limits; without an executor the agent runs directly
+        let result = match &execution {
+            Some(execution) => execution.run(call).await,
+            None => call.await,
+        }
+        .map(|value| value.as_str().map(String::from).unwrap_or_default());
+        let assist = match &result {
+            Ok(text) => model_assist(&assisted_type, &operation, &arguments, text).await,
+            Err(_) => None,
+        };
+        (result, assist)
+    };
+    let outcome = mcp_session::run_request(session, &request.id, params, run).await;
+    let (result, assist) = match outcome {
+        Ok(result) => result,
+        Err(cancelled) => {
+            if let Some(execution) = execution {
+                execution.complete(Err(cancelled.to_string())).await;
+            }
+            return JsonRpcResponse::error(
                 request.id.clone(),
-                json!({
-                    "content": [{
-                        "type": "text",
-                        "text": text
-                    }]
-                }),
-            )
+                mcp_session::REQUEST_CANCELLED,
+                cancelled.to_string(),
+            );
+        }
+    };
+    if let Some(execution) = execution {
+        let outcome = match &result {
+            Ok(text) => Ok(Value::from(text.clone())),
+            Err(e) => Err(e.to_string()),
+        };
+        execution.complete(outcome).await;
+    }
+
+    match result {
+        Ok(text) => {
+            let mut content = vec![json!({ "type": "text", "text": text })];
+            if let Some(completion) = assist {
+                content.push(json!({
+                    "type": "text",
+                    "text": format!("Analysis ({}):\n{}", completion.model, completion.text)
+                }));
+            }
+            JsonRpcResponse::success(request.id.clone(), json!({ "content": content }))
         }
         Err(e) => {
             error!("Agent execution error: {}", e);
@@ -661,3 +727,60 @@ async fn handle_tools_call(
         }
     }
 }
+
+/// Agents whose structured output is handed to a model for a written
+/// answer, with the system prompt used for it
+const MODEL_ASSISTED_AGENTS: &[(&str, &str)] = &[
+    (
+        "sequential_thinking",
+        "Work through the structured thinking steps below and give a concise, well-justified conclusion.",
+    ),
+    (
+        "prompt_engineer",
+        "Using the prompt analysis below, write the improved prompt and briefly explain what changed.",
+    ),
+    (
+        "debugger",
+        "Using the debugging findings below, name the most likely root cause and the fix.",
+    ),
+];
+
+/// Argument that asks for a server-side analysis when the client cannot sample
+const ASSIST_ARGUMENT: &str = "assist";
+
+fn is_model_assisted(agent_type: &str) -> bool {
+    MODEL_ASSISTED_AGENTS.iter().any(|(t, _)| *t == agent_type)
+}
+
+/// Ask a model to turn a model-assisted agent's output into an answer: the
+/// client's via sampling, or the server's when the caller passed
+/// `"assist": true`. `None` when the agent is not model-assisted or no
+/// model was asked.
+async fn model_assist(agent_type: &str, operation: &str, arguments: &Value, output: &str) -> Option<Completion> {
+    let (_, system_prompt) = MODEL_ASSISTED_AGENTS.iter().find(|(t, _)| *t == agent_type)?;
+    let client = mcp_sampling::client_can_sample();
+    let requested = arguments.get(ASSIST_ARGUMENT).and_then(|a| a.as_bool()).unwrap_or(false);
+    if !client && !requested {
+        debug!("Skipping model assist for {} agent", agent_type);
+        return None;
+    }
+
+    let arguments = simd_json::to_string_pretty(arguments).unwrap_or_default();
+    let prompt = format!(
+        "Operation: {}\nArguments:\n{}\n\nAgent output:\n{}",
+        operation, arguments, output
+    );
+    let request = SamplingRequest::new(*system_prompt, prompt);
+    let completion = if client {
+        mcp_sampling::sample_client(&request).await
+    } else {
+        mcp_sampling::create_message(request).await
+    };
+    match completion {
+        Ok(completion) => Some(completion),
+        Err(e) => {
+            warn!("Model assist for {} agent failed: {}", agent_type, e);
+            None
+        }
+    }
+}
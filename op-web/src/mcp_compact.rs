@@ -7,16 +7,21 @@
 //! - execute_tool: Execute any tool by name
 //!
 //! This allows LLMs to work with 750+ tools without exceeding context limits.
+//! Under `/mcp/p/{profile}/compact` the meta-tools only see the profile's tools.
+//! `execute_tool` honours `_meta.progressToken` and `notifications/cancelled`
+//! for clients connected through the SSE session, and asks clients that
+//! support elicitation for missing arguments or destructive-call confirmation.
 
 use axum::{
-    extract::{Json, Extension},
+    body::Bytes,
+    extract::{Extension, Path, Query},
     http::{HeaderMap, StatusCode},
     response::{
         sse::{Event, Sse},
         IntoResponse, Response,
     },
 };
-use futures::stream::{self, Stream};
+use futures::stream::{self, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use simd_json::{json, OwnedValue as Value};
 use simd_json::prelude::*;
@@ -24,9 +29,12 @@ use std::convert::Infallible;
 use std::sync::Arc;
 use tracing::{debug, error, info, warn};
 
-use op_state_store::execution_job::{ExecutionJob, ExecutionStatus, ExecutionResult};
-use uuid::Uuid;
-
+use crate::mcp_completion;
+use crate::mcp_elicitation;
+use crate::mcp_logging;
+use crate::mcp_session::{self, McpSession, RequestCancelled, SessionQuery, MCP_SESSIONS};
+use crate::tool_catalog;
+use crate::tool_profiles::{self, ToolProfile};
 use crate::AppState;
 
 /// JSON-RPC request structure
@@ -170,20 +178,26 @@ pub async fn mcp_compact_sse_handler(
     headers: HeaderMap,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     info!("MCP Compact SSE client connected");
+    compact_sse_stream(&headers, "/mcp/compact/message")
+}
 
-    // Get host from headers
-    let host = headers
-        .get("host")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("localhost");
+/// SSE endpoint for a profile-scoped compact MCP server
+pub async fn mcp_compact_profile_sse_handler(
+    Path(profile): Path<String>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    info!("MCP Compact SSE client connected (profile: {})", profile);
+    compact_sse_stream(&headers, &format!("/mcp/p/{}/compact/message", profile))
+}
 
-    // Determine scheme from forwarded headers
-    let scheme = headers
-        .get("x-forwarded-proto")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("http");
+fn compact_sse_stream(
+    headers: &HeaderMap,
+    message_path: &str,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Open a session so progress notifications reach this client
+    let (session, session_stream) = MCP_SESSIONS.open();
 
-    let post_url = format!("{}://{}/mcp/compact/message", scheme, host);
+    let post_url = mcp_session::endpoint_url(headers, message_path, &session);
     info!("MCP Compact POST endpoint: {}", post_url);
 
     // Create initial endpoint event (required by MCP SSE transport spec)
@@ -191,8 +205,8 @@ pub async fn mcp_compact_sse_handler(
         .event("endpoint")
         .data(&post_url);
 
-    // Stream the endpoint event
-    let stream = stream::once(async move { Ok(endpoint_event) });
+    // Stream the endpoint event, then server-initiated messages
+    let stream = stream::once(async move { Ok(endpoint_event) }).chain(session_stream);
 
     Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
@@ -205,20 +219,90 @@ pub async fn mcp_compact_sse_handler(
 /// Returns proper JSON-RPC responses, never HTML
 pub async fn mcp_compact_message_handler(
     Extension(state): Extension<Arc<AppState>>,
-    Json(request): Json<JsonRpcRequest>,
+    Query(query): Query<SessionQuery>,
+    body: Bytes,
 ) -> Response {
+    let session = MCP_SESSIONS.from_query(&query);
+    let request = match parse_message(session.as_ref(), &body) {
+        Ok(request) => request,
+        Err(reply) => return reply,
+    };
+    let response = mcp_logging::with_session(
+        session.as_ref(),
+        dispatch(&state, &request, None, session.as_ref()),
+    )
+    .await;
+    json_response(&response)
+}
+
+/// POST endpoint for profile-scoped compact MCP JSON-RPC messages
+pub async fn mcp_compact_profile_message_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(profile): Path<String>,
+    Query(query): Query<SessionQuery>,
+    body: Bytes,
+) -> Response {
+    let session = MCP_SESSIONS.from_query(&query);
+    let request = match parse_message(session.as_ref(), &body) {
+        Ok(request) => request,
+        Err(reply) => return reply,
+    };
+    let response = match ToolProfile::load(&profile).await {
+        Some(p) => {
+            mcp_logging::with_session(
+                session.as_ref(),
+                dispatch(&state, &request, Some(&p), session.as_ref()),
+            )
+            .await
+        }
+        None => JsonRpcResponse::error(
+            request.id.clone(),
+            -32602,
+            format!("Unknown tool profile: {}", profile),
+        ),
+    };
+    json_response(&response)
+}
+
+pub(crate) async fn dispatch(
+    state: &Arc<AppState>,
+    request: &JsonRpcRequest,
+    profile: Option<&ToolProfile>,
+    session: Option<&Arc<McpSession>>,
+) -> JsonRpcResponse {
     debug!("MCP Compact request: method={} id={}", request.method, request.id);
 
-    let response = match request.method.as_str() {
-        "initialize" => handle_initialize(&request),
+    if request.jsonrpc != "2.0" {
+        return JsonRpcResponse::error(request.id.clone(), -32600, "Invalid JSON-RPC version".to_string());
+    }
+
+    match request.method.as_str() {
+        "initialize" => {
+            mcp_session::record_initialize(session, &request.params);
+            handle_initialize(request)
+        }
         "initialized" => JsonRpcResponse::success(request.id.clone(), json!({})),
-        "tools/list" => handle_tools_list(&request),
-        "tools/call" => handle_tools_call(&state, &request).await,
+        "tools/list" => handle_tools_list(request),
+        "tools/call" => tool_profiles::with_profile(profile, handle_tools_call(state, request, profile, session)).await,
+        "completion/complete" => {
+            match mcp_completion::complete(&state.executor, &state.tool_registry, profile, "mcp-compact", &request.params).await {
+                Ok(result) => JsonRpcResponse::success(request.id.clone(), result),
+                Err(e) => JsonRpcResponse::error(request.id.clone(), -32602, e),
+            }
+        }
         "ping" => JsonRpcResponse::success(request.id.clone(), json!({})),
         "notifications/initialized" => {
             // This is a notification, no response needed but we'll acknowledge
             JsonRpcResponse::success(request.id.clone(), json!({}))
         }
+        "notifications/cancelled" => {
+            mcp_session::handle_cancelled(session, &request.params);
+            JsonRpcResponse::success(request.id.clone(), json!({}))
+        }
+        "logging/setLevel" => match mcp_logging::handle_set_level(session, &request.params) {
+            Ok(result) => JsonRpcResponse::success(request.id.clone(), result),
+            Err(e) => JsonRpcResponse::error(request.id.clone(), -32602, e),
+        },
         _ => {
             warn!("Unknown MCP method: {}", request.method);
             JsonRpcResponse::error(
@@ -227,10 +311,25 @@ pub async fn mcp_compact_message_handler(
                 format!("Method not found: {}", request.method),
             )
         }
-    };
+    }
+}
 
-    // Always return JSON with correct content type
-    let json_body = simd_json::to_string(&response).unwrap_or_else(|e| {
+/// Parse a POSTed message; `Err` is the reply for client responses and bad JSON
+fn parse_message(session: Option<&Arc<McpSession>>, body: &Bytes) -> Result<JsonRpcRequest, Response> {
+    match mcp_session::parse_post(session, body) {
+        Ok(Some(request)) => Ok(request),
+        // A reply to a server-initiated request, already routed
+        Ok(None) => Err(StatusCode::ACCEPTED.into_response()),
+        Err(e) => Err(json_response(&JsonRpcResponse::error(
+            json!(null),
+            -32700,
+            format!("Parse error: {}", e),
+        ))),
+    }
+}
+
+fn json_response(response: &JsonRpcResponse) -> Response {
+    let json_body = simd_json::to_string(response).unwrap_or_else(|e| {
         error!("Failed to serialize response: {}", e);
         r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32603,"message":"Internal error"}}"#.to_string()
     });
@@ -254,7 +353,9 @@ fn handle_initialize(request: &JsonRpcRequest) -> JsonRpcResponse {
             "capabilities": {
                 "tools": {
                     "listChanged": false
-                }
+                },
+                "logging": {},
+                "completions": {}
             },
             "serverInfo": {
                 "name": "op-dbus-compact",
@@ -280,6 +381,8 @@ fn handle_tools_list(request: &JsonRpcRequest) -> JsonRpcResponse {
 async fn handle_tools_call(
     state: &Arc<AppState>,
     request: &JsonRpcRequest,
+    profile: Option<&ToolProfile>,
+    session: Option<&Arc<McpSession>>,
 ) -> JsonRpcResponse {
     let params = &request.params;
     
@@ -306,10 +409,15 @@ async fn handle_tools_call(
 
     // Execute the meta-tool (no security needed for meta-tools themselves)
     let result = match tool_name {
-        "list_tools" => execute_list_tools(&state.tool_registry, &arguments).await,
-        "search_tools" => execute_search_tools(&state.tool_registry, &arguments).await,
-        "get_tool_schema" => execute_get_tool_schema(&state.tool_registry, &arguments).await,
-        "execute_tool" => execute_execute_tool(state, &arguments).await,
+        "list_tools" => execute_list_tools(&state.tool_registry, &arguments, profile).await,
+        "search_tools" => execute_search_tools(&state.tool_registry, &arguments, profile).await,
+        "get_tool_schema" => execute_get_tool_schema(&state.tool_registry, &arguments, profile).await,
+        "execute_tool" => match execute_execute_tool(state, &arguments, profile, session, request).await {
+            Err(ExecuteError::Cancelled(cancelled)) => {
+                return JsonRpcResponse::error(request.id.clone(), mcp_session::REQUEST_CANCELLED, cancelled.to_string())
+            }
+            other => other.map_err(|e| e.to_string()),
+        },
         _ => Err(format!("Unknown compact tool: {}. Available: list_tools, search_tools, get_tool_schema, execute_tool", tool_name)),
     };
 
@@ -347,6 +455,7 @@ async fn handle_tools_call(
 async fn execute_list_tools(
     registry: &Arc<op_tools::ToolRegistry>,
     args: &Value,
+    profile: Option<&ToolProfile>,
 ) -> Result<Value, String> {
     let category = args.get("category").and_then(|v| v.as_str());
     let offset = args.get("offset").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
@@ -356,14 +465,14 @@ async fn execute_list_tools(
         .unwrap_or(50)
         .min(100) as usize;
 
-    let all_tools = registry.list().await;
+    let all_tools = tool_profiles::list_scoped(registry, profile).await;
 
     // Filter by category if specified
     let filtered: Vec<_> = if let Some(cat) = category {
         let cat_lower = cat.to_lowercase();
         all_tools
             .iter()
-            .filter(|t| t.category.to_lowercase().contains(&cat_lower))
+            .filter(|t| tool_catalog::category_of(t).contains(&cat_lower))
             .collect()
     } else {
         all_tools.iter().collect()
@@ -378,7 +487,7 @@ async fn execute_list_tools(
             json!({
                 "name": t.name,
                 "description": t.description,
-                "category": t.category
+                "category": tool_catalog::category_of(t)
             })
         })
         .collect();
@@ -398,6 +507,7 @@ async fn execute_list_tools(
 async fn execute_search_tools(
     registry: &Arc<op_tools::ToolRegistry>,
     args: &Value,
+    profile: Option<&ToolProfile>,
 ) -> Result<Value, String> {
     let query = args
         .get("query")
@@ -409,7 +519,7 @@ async fn execute_search_tools(
         .unwrap_or(20) as usize;
 
     let query_lower = query.to_lowercase();
-    let all_tools = registry.list().await;
+    let all_tools = tool_profiles::list_scoped(registry, profile).await;
 
     let matches: Vec<Value> = all_tools
         .iter()
@@ -422,7 +532,7 @@ async fn execute_search_tools(
             json!({
                 "name": t.name,
                 "description": t.description,
-                "category": t.category
+                "category": tool_catalog::category_of(t)
             })
         })
         .collect();
@@ -438,12 +548,15 @@ async fn execute_search_tools(
 async fn execute_get_tool_schema(
     registry: &Arc<op_tools::ToolRegistry>,
     args: &Value,
+    profile: Option<&ToolProfile>,
 ) -> Result<Value, String> {
     let tool_name = args
         .get("tool_name")
         .and_then(|v| v.as_str())
         .ok_or("Missing required parameter: tool_name")?;
 
+    tool_profiles::check_allowed(registry, profile, tool_name).await?;
+
     let all_tools = registry.list().await;
     let tool = all_tools
         .iter()
@@ -453,16 +566,47 @@ async fn execute_get_tool_schema(
     Ok(json!({
         "name": tool.name,
         "description": tool.description,
-        "category": tool.category,
+        "category": tool_catalog::category_of(tool),
         "inputSchema": tool.input_schema
     }))
 }
 
+/// Why execute_tool produced no result
+enum ExecuteError {
+    Invalid(String),
+    /// The client cancelled the call; answered with a JSON-RPC error
+    Cancelled(RequestCancelled),
+}
+
+impl From<String> for ExecuteError {
+    fn from(e: String) -> Self {
+        Self::Invalid(e)
+    }
+}
+
+impl From<&str> for ExecuteError {
+    fn from(e: &str) -> Self {
+        Self::Invalid(e.to_string())
+    }
+}
+
+impl std::fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => f.write_str(e),
+            Self::Cancelled(cancelled) => write!(f, "{}", cancelled),
+        }
+    }
+}
+
 /// Execute execute_tool meta-tool - runs any underlying tool
 async fn execute_execute_tool(
     state: &Arc<AppState>,
     args: &Value,
-) -> Result<Value, String> {
+    profile: Option<&ToolProfile>,
+    session: Option<&Arc<McpSession>>,
+    request: &JsonRpcRequest,
+) -> Result<Value, ExecuteError> {
     let registry = &state.tool_registry;
     let tool_name = args
         .get("tool_name")
@@ -475,65 +619,51 @@ async fn execute_execute_tool(
 
     info!("Executing underlying tool: {} with args: {}", tool_name, arguments);
 
-    // Create ExecutionJob for tracking
-    let job_id = Uuid::new_v4();
-    let now = chrono::Utc::now();
-    
-    let mut job = ExecutionJob {
-         id: job_id,
-         tool_name: tool_name.to_string(),
-         arguments: arguments.clone(),
-         status: ExecutionStatus::Running,
-         created_at: now,
-         updated_at: now,
-         result: None,
+    // Reject calls outside the profile before anything is recorded
+    tool_profiles::check_allowed(registry, profile, tool_name).await?;
+
+    // Ask the user for missing inputs or confirmation (clients with elicitation)
+    let mut arguments = match registry.get_definition(tool_name).await {
+        Some(definition) => {
+            mcp_elicitation::prepare_arguments(session, tool_name, &definition.input_schema, arguments).await?
+        }
+        None => arguments,
     };
-    
-    // Save initial state to state store (audit log)
-    if let Err(e) = state.state_store.save_job(&job).await {
-         error!("Failed to save execution job start to state store: {}", e);
-         // Continue execution even if logging fails? 
-         // For high security, we might want to fail, but for now we log and proceed.
-    }
 
-    // Find and execute the tool
-    let tool_result = match registry.get(tool_name).await {
-        Some(tool) => tool.execute(arguments).await,
-        None => Err(anyhow::anyhow!("Tool not found: {}. Use list_tools or search_tools to find available tools.", tool_name)),
+    // Find and execute the tool (cached, audited, cancellable, with progress when requested)
+    let Some(tool) = registry.get(tool_name).await else {
+        return Ok(json!({
+            "tool": tool_name,
+            "success": false,
+            "error": format!("Tool not found: {}. Use list_tools or search_tools to find available tools.", tool_name)
+        }));
+    };
+    let tool_result = if let Some(cached) = state.executor.cached("mcp-compact", tool_name, &arguments).await {
+        Ok(cached)
+    } else {
+        let execution = state.executor.begin("mcp-compact", tool_name, &mut arguments).await;
+        let outcome =
+            mcp_session::run_request(session, &request.id, &request.params, execution.run(tool.execute(arguments))).await;
+        let tool_result = match outcome {
+            Ok(result) => result,
+            Err(cancelled) => {
+                execution.complete(Err(cancelled.to_string())).await;
+                return Err(ExecuteError::Cancelled(cancelled));
+            }
+        };
+        execution
+            .complete(tool_result.as_ref().map(Clone::clone).map_err(|e| e.to_string()))
+            .await;
+        tool_result
     };
 
-    // Update Job with result
-    job.updated_at = chrono::Utc::now();
-    
     match tool_result {
-        Ok(res) => {
-            job.status = ExecutionStatus::Completed;
-            job.result = Some(ExecutionResult {
-                success: true,
-                output: Some(res.clone()),
-                error: None,
-            });
-             if let Err(e) = state.state_store.update_job(&job).await {
-                 error!("Failed to update execution job success: {}", e);
-             }
-             
-             Ok(json!({
-                "tool": tool_name,
-                "success": true,
-                "result": res
-            }))
-        },
+        Ok(res) => Ok(json!({
+            "tool": tool_name,
+            "success": true,
+            "result": res
+        })),
         Err(e) => {
-             job.status = ExecutionStatus::Failed;
-             job.result = Some(ExecutionResult {
-                 success: false,
-                 output: None,
-                 error: Some(e.to_string()),
-             });
-             if let Err(log_err) = state.state_store.update_job(&job).await {
-                 error!("Failed to update execution job failure: {}", log_err);
-             }
-             
             error!("Tool {} execution failed: {}", tool_name, e);
             Ok(json!({
                 "tool": tool_name,
@@ -0,0 +1,288 @@
+//! MCP Completion - `completion/complete` backed by live system data
+//!
+//! Clients ask for argument suggestions with a reference (`ref/tool`,
+//! `ref/prompt` or `ref/resource`) and the partial argument value. For tool
+//! arguments the schema's `enum` wins; otherwise a resolver keyed by the
+//! property name asks the system, usually by running the matching list tool
+//! from the registry (`dbus_systemd_list_units`, `ovs_list_bridges`, ...).
+//!
+//! Completions answer only what the caller could use: the referenced tool
+//! must pass the profile and access policy, list tools run through
+//! [`ToolExecutor`] (audited, limited, policy-checked), and directories are
+//! listed only where the caller's scope policy lets `file_list` read.
+
+use op_tools::ToolRegistry;
+use std::sync::Arc;
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::path::Path;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::executor::ToolExecutor;
+use crate::tool_profiles::{self, ToolProfile};
+
+/// Maximum values returned per completion (MCP limit)
+const MAX_COMPLETIONS: usize = 100;
+
+/// Resolver tool calls must not hold up a keystroke for long
+const RESOLVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tool whose scope policy governs directory listings
+const PATH_LIST_TOOL: &str = "file_list";
+
+/// Fields that identify an item in list tool output
+const NAME_FIELDS: &[&str] = &["name", "unit", "bridge", "port", "interface", "ifname", "path", "id"];
+
+/// Handle `completion/complete` for the current caller on `source`
+pub async fn complete(
+    executor: &Arc<ToolExecutor>,
+    registry: &ToolRegistry,
+    profile: Option<&ToolProfile>,
+    source: &str,
+    params: &Value,
+) -> Result<Value, String> {
+    let resolver = Resolver { executor, registry, profile, source };
+    let reference = params.get("ref").ok_or("Missing required parameter: ref")?;
+    let argument = params.get("argument").ok_or("Missing required parameter: argument")?;
+    let arg_name = argument
+        .get("name")
+        .and_then(|n| n.as_str())
+        .ok_or("Missing argument name")?;
+    let arg_value = argument.get("value").and_then(|v| v.as_str()).unwrap_or("");
+    let context = params
+        .get("context")
+        .and_then(|c| c.get("arguments"))
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+
+    let candidates = match reference.get("type").and_then(|t| t.as_str()) {
+        Some("ref/tool") => {
+            let tool_name = reference
+                .get("name")
+                .and_then(|n| n.as_str())
+                .ok_or("Missing tool name in ref")?;
+            resolver.tool_candidates(tool_name, arg_name, arg_value, &context).await?
+        }
+        // No prompts or resources take arguments yet
+        Some("ref/prompt") | Some("ref/resource") => Vec::new(),
+        Some(other) => return Err(format!("Unsupported completion ref type: {}", other)),
+        None => return Err("Missing ref type".to_string()),
+    };
+
+    let (values, total) = rank(candidates, arg_value);
+    debug!("Completion for {}={:?}: {} of {} values", arg_name, arg_value, values.len(), total);
+
+    Ok(json!({
+        "completion": {
+            "values": values,
+            "total": total,
+            "hasMore": total > MAX_COMPLETIONS
+        }
+    }))
+}
+
+/// Looks up completion values on behalf of one caller
+struct Resolver<'a> {
+    executor: &'a Arc<ToolExecutor>,
+    registry: &'a ToolRegistry,
+    profile: Option<&'a ToolProfile>,
+    source: &'a str,
+}
+
+impl Resolver<'_> {
+    /// Candidates for an argument of a registry tool
+    async fn tool_candidates(
+        &self,
+        tool_name: &str,
+        arg_name: &str,
+        arg_value: &str,
+        context: &Value,
+    ) -> Result<Vec<String>, String> {
+        let Some(definition) = self.registry.get_definition(tool_name).await else {
+            // Compact mode meta-tools are not in the registry but take tool names
+            if arg_name == "tool_name" {
+                return Ok(self.resolve(arg_name, arg_value, context).await);
+            }
+            return Err(format!("Tool not found: {}", tool_name));
+        };
+        tool_profiles::check_allowed(self.registry, self.profile, tool_name).await?;
+        self.executor.authorize(self.source, tool_name, &mut json!({})).await?;
+
+        let enum_values: Vec<String> = definition
+            .input_schema
+            .get("properties")
+            .and_then(|p| p.get(arg_name))
+            .and_then(|s| s.get("enum"))
+            .and_then(|e| e.as_array())
+            .map(|values| values.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+        if !enum_values.is_empty() {
+            return Ok(enum_values);
+        }
+
+        Ok(self.resolve(arg_name, arg_value, context).await)
+    }
+
+    /// Resolve live values for a schema property name
+    async fn resolve(&self, arg_name: &str, arg_value: &str, context: &Value) -> Vec<String> {
+        match arg_name {
+            "unit" | "unit_name" | "service" => self.from_tool("dbus_systemd_list_units", json!({})).await,
+            "bridge" | "bridge_name" => self.from_tool("ovs_list_bridges", json!({})).await,
+            "port" | "port_name" => match context.get("bridge").and_then(|b| b.as_str()) {
+                Some(bridge) => self.from_tool("ovs_list_ports", json!({ "bridge": bridge })).await,
+                // Ports being added are usually existing kernel interfaces
+                None => self.from_tool("rtnetlink_list_links", json!({})).await,
+            },
+            "interface" | "iface" | "ifname" | "link" | "dev" => self.from_tool("rtnetlink_list_links", json!({})).await,
+            "path" | "file" | "dir" | "directory" => self.complete_path(arg_value).await,
+            // Compact mode meta-tools (execute_tool, get_tool_schema)
+            "tool_name" => tool_profiles::list_scoped(self.registry, self.profile)
+                .await
+                .into_iter()
+                .map(|t| t.name)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Run a list tool through the executor and collect the item names from
+    /// its output
+    async fn from_tool(&self, tool_name: &str, args: Value) -> Vec<String> {
+        let run = self.executor.execute(self.source, tool_name, args);
+        match tokio::time::timeout(RESOLVER_TIMEOUT, run).await {
+            Ok(Ok(output)) => {
+                let mut names = Vec::new();
+                collect_names(&output, &mut names);
+                names
+            }
+            Ok(Err(e)) => {
+                debug!("Completion source {} failed: {}", tool_name, e);
+                Vec::new()
+            }
+            Err(_) => {
+                debug!("Completion source {} timed out", tool_name);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Complete a filesystem path from its directory listing, if the scope
+    /// policy lets the caller read the directory
+    async fn complete_path(&self, partial: &str) -> Vec<String> {
+        let (dir, prefix) = match partial.rfind('/') {
+            Some(idx) => (&partial[..=idx], &partial[idx + 1..]),
+            None => ("", partial),
+        };
+        let read_from = if dir.is_empty() { "." } else { dir };
+
+        let mut probe = json!({ "path": read_from });
+        if let Err(e) = self.executor.authorize(self.source, PATH_LIST_TOOL, &mut probe).await {
+            debug!("Path completion for {} refused: {}", read_from, e);
+            return Vec::new();
+        }
+
+        let Ok(mut entries) = tokio::fs::read_dir(Path::new(read_from)).await else {
+            return Vec::new();
+        };
+
+        let mut paths = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                continue;
+            }
+            let is_dir = entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false);
+            paths.push(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }));
+        }
+        paths
+    }
+}
+
+/// Extract item names from list tool output (arrays of strings or objects,
+/// possibly wrapped in an object like `{"units": [...]}`)
+fn collect_names(value: &Value, out: &mut Vec<String>) {
+    if let Some(items) = value.as_array() {
+        for item in items {
+            if let Some(s) = item.as_str() {
+                out.push(s.to_string());
+            } else if item.is_object() {
+                if let Some(name) = NAME_FIELDS
+                    .iter()
+                    .find_map(|f| item.get(*f).and_then(|v| v.as_str()))
+                {
+                    out.push(name.to_string());
+                }
+            }
+        }
+    } else if let Some(obj) = value.as_object() {
+        for nested in obj.values() {
+            if nested.is_array() || nested.is_object() {
+                collect_names(nested, out);
+            }
+        }
+    }
+}
+
+/// Filter candidates by the typed value: prefix matches first, then
+/// substring matches (so `nginx` offers `nginx.service`). Returns the
+/// capped values and the total number of matches.
+fn rank(candidates: Vec<String>, value: &str) -> (Vec<String>, usize) {
+    let needle = value.to_lowercase();
+    let mut prefix = Vec::new();
+    let mut contains = Vec::new();
+
+    for candidate in candidates {
+        if prefix.contains(&candidate) || contains.contains(&candidate) {
+            continue;
+        }
+        let lower = candidate.to_lowercase();
+        if lower.starts_with(&needle) {
+            prefix.push(candidate);
+        } else if lower.contains(&needle) {
+            contains.push(candidate);
+        }
+    }
+
+    prefix.sort();
+    contains.sort();
+    prefix.extend(contains);
+
+    let total = prefix.len();
+    prefix.truncate(MAX_COMPLETIONS);
+    (prefix, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_prefers_prefix_matches() {
+        let candidates = vec![
+            "sshd.service".to_string(),
+            "nginx.service".to_string(),
+            "php-nginx.socket".to_string(),
+            "nginx.service".to_string(),
+        ];
+        let (values, total) = rank(candidates, "nginx");
+        assert_eq!(values, vec!["nginx.service", "php-nginx.socket"]);
+        assert_eq!(total, 2);
+    }
+
+    #[test]
+    fn test_collect_names_from_wrapped_objects() {
+        let output = json!({
+            "count": 2,
+            "units": [
+                { "name": "nginx.service", "active_state": "active" },
+                { "unit": "sshd.service" }
+            ],
+            "bridges": ["br0"]
+        });
+        let mut names = Vec::new();
+        collect_names(&output, &mut names);
+        names.sort();
+        assert_eq!(names, vec!["br0", "nginx.service", "sshd.service"]);
+    }
+}
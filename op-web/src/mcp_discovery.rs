@@ -1,94 +1,313 @@
 //! MCP Discovery - Well-Known Endpoint for Auto-Discovery
 //!
 //! Provides `/.well-known/mcp.json` for clients to auto-discover MCP servers
-//! without manual configuration. This is the standard MCP discovery mechanism.
+//! without manual configuration. The document is generated per request from
+//! the mounted MCP surfaces, the live agent roster, the registry's tool count,
+//! the saved group profiles and the caller's authentication requirements.
+//! It only lists what the caller's zone (and API key) may use under the
+//! access policy: surfaces and profiles it may reach, the tools it may run,
+//! and the stdio command only for `Localhost`.
+//!
+//! [`client_config`] renders the ready-to-paste snippets served by
+//! `/mcp/_config?client=claude|cursor|vscode`.
 
 use axum::{
-    http::{header, HeaderMap, StatusCode},
+    extract::{ConnectInfo, Extension},
+    http::{header, HeaderMap, Method, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use op_core::security::AccessZone;
+use op_tools::registry::ToolDefinition;
 use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use tracing::info;
 
+use crate::access_policy::{self, AccessPolicy};
+use crate::audit::{Caller, ClientIdentity};
+use crate::groups_admin::GROUPS_CONFIG;
+use crate::middleware::security;
+use crate::mcp_smart_router::{self, McpMode};
+use crate::state::AppState;
+use crate::tool_profiles::{self, ToolProfile};
+
+/// An MCP surface served by this process
+struct Surface {
+    key: &'static str,
+    name: &'static str,
+    /// SSE stream (GET) or, for `/mcp`, the combined endpoint
+    path: &'static str,
+    /// Where JSON-RPC messages are POSTed
+    message_path: &'static str,
+    mode: Option<McpMode>,
+    description: &'static str,
+    features: &'static [&'static str],
+}
+
+/// Surfaces mounted by `mcp::create_mcp_router` and `routes::create_router`
+const SURFACES: &[Surface] = &[
+    Surface {
+        key: "op-dbus",
+        name: "OP-DBUS",
+        path: "/mcp",
+        message_path: "/mcp",
+        mode: None,
+        description: "Negotiated entry point - serves the full, compact or agents surface depending on x-mcp-mode, API key configuration or the initialize clientInfo",
+        features: &["streaming", "negotiation"],
+    },
+    Surface {
+        key: "op-dbus-full",
+        name: "OP-DBUS Full",
+        path: "/mcp/sse",
+        message_path: "/mcp/message",
+        mode: Some(McpMode::Full),
+        description: "Every registry tool exposed directly through tools/list",
+        features: &["streaming"],
+    },
+    Surface {
+        key: "op-dbus-compact",
+        name: "OP-DBUS Compact",
+        path: "/mcp/compact",
+        message_path: "/mcp/compact/message",
+        mode: Some(McpMode::Compact),
+        description: "4 meta-tools (list_tools, search_tools, get_tool_schema, execute_tool) in front of the whole registry",
+        features: &["streaming", "tool-discovery"],
+    },
+    Surface {
+        key: "op-dbus-agents",
+        name: "OP-DBUS Agents",
+        path: "/mcp/agents",
+        message_path: "/mcp/agents/message",
+        mode: Some(McpMode::Agents),
+        description: "Agents from the configured roster, one tool per agent operation",
+        features: &["streaming", "agents"],
+    },
+];
+
+/// Headers that carry an API key
+const AUTH_HEADERS: &[&str] = &["x-op-mcp-token", "x-api-key", "authorization"];
+
+/// Base URL of this server as seen by the caller
+///
+/// `X-Forwarded-Host` / `X-Forwarded-Proto` are only believed from a trusted
+/// proxy (`peer`); anyone else gets URLs built from its own `Host`.
+pub fn base_url(headers: &HeaderMap, peer: Option<&SocketAddr>) -> String {
+    base_url_from(headers, security::is_trusted_proxy(peer))
+}
+
+fn base_url_from(headers: &HeaderMap, forwarded: bool) -> String {
+    let forwarded_header = |name: &str| {
+        forwarded
+            .then(|| headers.get(name))
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+    };
+
+    let host = forwarded_header("x-forwarded-host")
+        .or_else(|| headers.get("host").and_then(|v| v.to_str().ok()))
+        .unwrap_or("localhost");
+
+    let scheme = forwarded_header("x-forwarded-proto").unwrap_or("http");
+
+    format!("{}://{}", scheme, host)
+}
+
+/// Binary name used when the path is unknown or not disclosed
+const STDIO_BINARY: &str = "op-web-server";
+
+/// Path of this binary, for stdio client configs
+pub fn stdio_command() -> String {
+    std::env::current_exe()
+        .ok()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| STDIO_BINARY.to_string())
+}
+
+/// Stdio command to show a caller in `zone`: the binary's path only on this host
+pub fn stdio_command_for(zone: Option<&AccessZone>) -> String {
+    match zone {
+        Some(AccessZone::Localhost) => stdio_command(),
+        _ => STDIO_BINARY.to_string(),
+    }
+}
+
+/// Whether a caller in `zone` must send an API key for full access
+pub fn auth_required(zone: Option<&AccessZone>) -> bool {
+    !matches!(zone, Some(AccessZone::TrustedMesh))
+}
+
+/// What the discovering caller may use under the access policy
+struct Visibility {
+    policy: Option<Arc<AccessPolicy>>,
+    zone: AccessZone,
+    caller: Caller,
+}
+
+impl Visibility {
+    fn new(zone: Option<&AccessZone>, identity: Option<ClientIdentity>) -> Self {
+        let zone = zone.cloned().unwrap_or(AccessZone::Public);
+        let caller = Caller {
+            source: "discovery".to_string(),
+            zone: Some(format!("{:?}", zone)),
+            api_key: identity.and_then(|i| i.api_key),
+            ..Default::default()
+        };
+        Self { policy: access_policy::global(), zone, caller }
+    }
+
+    async fn route(&self, method: Method, path: &str) -> bool {
+        match &self.policy {
+            Some(policy) => policy
+                .authorize_route(&self.zone, self.caller.api_key.as_deref(), &method, path)
+                .await
+                .is_ok(),
+            None => true,
+        }
+    }
+
+    /// Number of `tools` the caller may run
+    fn tool_count(&self, tools: &[ToolDefinition]) -> usize {
+        match &self.policy {
+            Some(policy) => tools.iter().filter(|t| policy.authorize_tool(&self.caller, t).is_ok()).count(),
+            None => tools.len(),
+        }
+    }
+}
+
 /// Well-known MCP discovery endpoint
-/// Returns all available MCP servers for automatic client configuration
-pub async fn mcp_discovery_handler(headers: HeaderMap) -> Response {
+/// Returns the MCP servers the caller may use, for automatic client configuration
+pub async fn mcp_discovery_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    zone: Option<Extension<AccessZone>>,
+    identity: Option<Extension<ClientIdentity>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response {
     info!("MCP discovery request from {:?}", headers.get("user-agent"));
-    
-    // Get the host from the request to build proper URLs
-    let host = headers
-        .get("host")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("op-dbus.ghostbridge.tech:3001");
-    
-    let scheme = headers
-        .get("x-forwarded-proto")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("http");
-    
-    let base_url = format!("{}://{}", scheme, host);
-    
-    let discovery = json!({
-        "mcpServers": {
-            "op-dbus-compact": {
-                "url": format!("{}/mcp/compact", base_url),
-                "transport": {
-                    "type": "sse"
-                },
-                "name": "OP-DBUS Compact",
-                "description": "Compact MCP - 4 meta-tools for LLM tool discovery (list_tools, search_tools, get_tool_schema, execute_tool). Exposes 148+ tools through minimal interface.",
-                "features": ["streaming", "tool-discovery"],
-                "recommended": true,
-                "meta_tools": [
-                    {"name": "list_tools", "description": "List available tools with pagination"},
-                    {"name": "search_tools", "description": "Search tools by keyword"},
-                    {"name": "get_tool_schema", "description": "Get full schema for a specific tool"},
-                    {"name": "execute_tool", "description": "Execute any discovered tool"}
-                ]
-            },
-            "op-dbus-agents": {
-                "url": format!("{}/mcp/agents", base_url),
-                "transport": {
-                    "type": "sse"
-                },
-                "name": "OP-DBUS Agents",
-                "description": "Critical Agents MCP - Live streaming agents for dynamic LLM use. Includes memory, cognitive, backend architect, backend security coder, rust pro, and frequently used agents.",
-                "features": ["streaming", "agents", "memory", "cognitive"],
-                "agents": {
-                    "memory": ["memory_remember", "memory_recall", "memory_forget"],
-                    "cognitive": ["context_manager_save", "context_manager_load", "sequential_thinking_think"],
-                    "semantic_memory": ["mem0_add", "mem0_search", "mem0_get_all", "mem0_delete"],
-                    "code": ["rust_pro_analyze", "rust_pro_refactor", "rust_pro_implement", "python_pro_analyze", "python_pro_refactor"],
-                    "architecture": ["backend_architect_design", "backend_architect_review"],
-                    "security": ["backend_security_coder_secure_endpoint", "backend_security_coder_audit_code", "backend_security_coder_fix_vulnerability", "backend_security_coder_analyze"],
-                    "utility": ["debugger_analyze", "debugger_trace", "prompt_engineer_generate", "prompt_engineer_optimize", "deployment_deploy", "deployment_status"]
+
+    let base_url = base_url(&headers, connect_info.map(|ci| ci.0).as_ref());
+    let zone = zone.map(|Extension(zone)| zone);
+    let visibility = Visibility::new(zone.as_ref(), identity.map(|Extension(identity)| identity));
+    let registry = &state.tool_registry;
+    let tool_count = visibility.tool_count(&tool_profiles::list_scoped(registry, None).await);
+    let agents = crate::mcp_agents::global_state().agents.read().await.summary();
+
+    let mut visible = Vec::new();
+    for surface in SURFACES {
+        if visibility.route(Method::GET, surface.path).await
+            && visibility.route(Method::POST, surface.message_path).await
+        {
+            visible.push(surface);
+        }
+    }
+
+    let mut servers = json!({});
+    for surface in &visible {
+        let mut server = json!({
+            "url": format!("{}{}", base_url, surface.path),
+            "message_url": format!("{}{}", base_url, surface.message_path),
+            "transport": { "type": if surface.mode.is_none() { "http" } else { "sse" } },
+            "name": surface.name,
+            "description": surface.description,
+            "features": surface.features,
+            "recommended": surface.mode.is_none()
+        });
+        if let Some(obj) = server.as_object_mut() {
+            match surface.mode {
+                None => {
+                    obj.insert("negotiation".into(), mcp_smart_router::negotiation_info());
+                }
+                Some(McpMode::Full) => {
+                    obj.insert("tool_count".into(), json!(tool_count));
+                }
+                Some(McpMode::Compact) => {
+                    obj.insert("tool_count".into(), json!(tool_count));
+                    obj.insert(
+                        "meta_tools".into(),
+                        json!(["list_tools", "search_tools", "get_tool_schema", "execute_tool"]),
+                    );
+                }
+                Some(McpMode::Agents) => {
+                    obj.insert("agents".into(), json!(agents.clone()));
                 }
             }
+        }
+        if let Some(obj) = servers.as_object_mut() {
+            obj.insert(surface.key.into(), server);
+        }
+    }
+
+    let mut profiles = Vec::new();
+    for name in GROUPS_CONFIG.list_profiles().await {
+        if !visibility.route(Method::POST, &format!("/mcp/p/{}", name)).await {
+            continue;
+        }
+        let Some(profile) = ToolProfile::load(&name).await else {
+            continue;
+        };
+        let count = visibility.tool_count(&tool_profiles::list_scoped(registry, Some(&profile)).await);
+        profiles.push(json!({
+            "name": name,
+            "url": format!("{}/mcp/p/{}", base_url, name),
+            "compact_url": format!("{}/mcp/p/{}/compact", base_url, name),
+            "tool_count": count
+        }));
+    }
+
+    let mut links = json!({
+        "self": format!("{}/.well-known/mcp.json", base_url),
+        "config": format!("{}/mcp/_config", base_url),
+        "documentation": "https://spec.modelcontextprotocol.io/"
+    });
+    if let Some(obj) = links.as_object_mut() {
+        for surface in &visible {
+            obj.insert(surface.key.into(), json!(format!("{}{}", base_url, surface.path)));
+        }
+    }
+
+    let mut discovery = json!({
+        "mcpServers": servers,
+        "profiles": profiles,
+        "authentication": {
+            "type": "api_key",
+            "headers": AUTH_HEADERS,
+            "required": auth_required(zone.as_ref()),
+            "caller_zone": zone.as_ref().map(|z| format!("{:?}", z)),
+            "description": "Trusted networks are admitted by address; other callers send an API key for full access"
         },
-        "_links": {
-            "self": format!("{}/.well-known/mcp.json", base_url),
-            "compact_sse": format!("{}/mcp/compact", base_url),
-            "compact_message": format!("{}/mcp/compact/message", base_url),
-            "agents_sse": format!("{}/mcp/agents", base_url),
-            "agents_message": format!("{}/mcp/agents/message", base_url),
-            "documentation": "https://spec.modelcontextprotocol.io/"
-        },
+        "_links": links,
         "protocol": {
             "version": "2024-11-05",
-            "transports": ["sse", "http+sse"]
+            "transports": ["sse", "http+sse", "http", "stdio"]
         },
         "server": {
             "name": "op-dbus",
             "version": env!("CARGO_PKG_VERSION"),
-            "description": "Operation D-Bus - Linux System Management via MCP"
+            "description": "Operation D-Bus - Linux System Management via MCP",
+            "tool_count": tool_count,
+            "agent_count": agents.len()
         }
     });
-    
+    // The binary's path is only useful (and only disclosed) on this host
+    if matches!(zone, Some(AccessZone::Localhost)) {
+        if let Some(obj) = discovery.as_object_mut() {
+            obj.insert(
+                "stdio".into(),
+                json!({
+                    "command": stdio_command(),
+                    "args": ["--mcp-stdio"]
+                }),
+            );
+        }
+    }
+
     Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/json")
-        .header(header::CACHE_CONTROL, "public, max-age=300") // Cache for 5 minutes
+        // Generated from live state (roster, profiles, tools)
+        .header(header::CACHE_CONTROL, "no-cache")
         .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
         .body(Json(discovery).into_response().into_body())
         .unwrap_or_else(|_| {
@@ -96,18 +315,145 @@ pub async fn mcp_discovery_handler(headers: HeaderMap) -> Response {
         })
 }
 
+/// A remote MCP endpoint for client configs
+pub struct RemoteEndpoint {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Render a config snippet in the format a client pastes into its settings:
+/// `claude` (claude_desktop_config.json), `cursor` (~/.cursor/mcp.json) or
+/// `vscode` (.vscode/mcp.json)
+pub fn client_config(
+    client: &str,
+    command: &str,
+    args: &[String],
+    remote: &RemoteEndpoint,
+) -> Result<Value, String> {
+    let headers = || {
+        let mut map = json!({});
+        if let Some(obj) = map.as_object_mut() {
+            for (name, value) in &remote.headers {
+                obj.insert(name.clone().into(), json!(value.clone()));
+            }
+        }
+        map
+    };
+
+    match client {
+        // Claude Desktop launches local processes; remote goes through mcp-remote
+        "claude" | "claude-desktop" => {
+            let mut remote_args = vec!["-y".to_string(), "mcp-remote".to_string(), remote.url.clone()];
+            for (name, value) in &remote.headers {
+                remote_args.push("--header".to_string());
+                remote_args.push(format!("{}:{}", name, value));
+            }
+            Ok(json!({
+                "mcpServers": {
+                    "op-dbus": { "command": command, "args": args },
+                    "op-dbus-remote": { "command": "npx", "args": remote_args }
+                }
+            }))
+        }
+        "cursor" => {
+            let mut remote_server = json!({ "url": remote.url.clone() });
+            if !remote.headers.is_empty() {
+                if let Some(obj) = remote_server.as_object_mut() {
+                    obj.insert("headers".into(), headers());
+                }
+            }
+            Ok(json!({
+                "mcpServers": {
+                    "op-dbus": { "command": command, "args": args },
+                    "op-dbus-remote": remote_server
+                }
+            }))
+        }
+        "vscode" | "vs-code" => {
+            let mut remote_server = json!({ "type": "http", "url": remote.url.clone() });
+            if !remote.headers.is_empty() {
+                if let Some(obj) = remote_server.as_object_mut() {
+                    obj.insert("headers".into(), headers());
+                }
+            }
+            Ok(json!({
+                "servers": {
+                    "op-dbus": { "type": "stdio", "command": command, "args": args },
+                    "op-dbus-remote": remote_server
+                }
+            }))
+        }
+        other => Err(format!("Unknown client '{}' (claude, cursor or vscode)", other)),
+    }
+}
+
 /// Simplified discovery for embedding in other responses
 pub fn get_mcp_servers_config(base_url: &str) -> Value {
-    json!({
-        "compact": {
-            "url": format!("{}/mcp/compact", base_url),
-            "transport": "sse",
-            "description": "4 meta-tools for tool discovery"
-        },
-        "agents": {
-            "url": format!("{}/mcp/agents", base_url),
-            "transport": "sse",
-            "description": "Streaming agents: memory, cognitive, security, rust_pro"
+    let mut config = json!({});
+    if let Some(obj) = config.as_object_mut() {
+        for surface in SURFACES {
+            let key = surface.mode.map(|m| m.as_str()).unwrap_or("auto");
+            obj.insert(
+                key.into(),
+                json!({
+                    "url": format!("{}{}", base_url, surface.path),
+                    "transport": if surface.mode.is_none() { "http" } else { "sse" },
+                    "description": surface.description
+                }),
+            );
         }
-    })
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_config_formats() {
+        let args = vec!["--mcp-stdio".to_string()];
+        let remote = RemoteEndpoint {
+            url: "http://host/mcp".to_string(),
+            headers: vec![("x-mcp-mode".to_string(), "compact".to_string())],
+        };
+
+        let vscode = client_config("vscode", "/usr/bin/op-web-server", &args, &remote).unwrap();
+        let servers = vscode.get("servers").unwrap();
+        assert_eq!(servers.get("op-dbus").and_then(|s| s.get("type")), Some(&json!("stdio")));
+        assert_eq!(
+            servers.get("op-dbus-remote").and_then(|s| s.get("headers")),
+            Some(&json!({ "x-mcp-mode": "compact" }))
+        );
+
+        let claude = client_config("claude", "/usr/bin/op-web-server", &args, &remote).unwrap();
+        let remote_args = claude
+            .get("mcpServers")
+            .and_then(|s| s.get("op-dbus-remote"))
+            .and_then(|s| s.get("args"))
+            .unwrap();
+        assert_eq!(remote_args, &json!(["-y", "mcp-remote", "http://host/mcp", "--header", "x-mcp-mode:compact"]));
+
+        assert!(client_config("emacs", "op-web-server", &args, &remote).is_err());
+    }
+
+    #[test]
+    fn test_forwarded_host_only_from_trusted_proxy() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", "10.0.0.5:8080".parse().unwrap());
+        headers.insert("x-forwarded-host", "evil.example".parse().unwrap());
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+
+        assert_eq!(base_url_from(&headers, true), "https://evil.example");
+        assert_eq!(base_url_from(&headers, false), "http://10.0.0.5:8080");
+        // No socket address means no proxy to vouch for the headers
+        assert_eq!(base_url(&headers, None), "http://10.0.0.5:8080");
+    }
+
+    #[test]
+    fn test_stdio_path_only_for_localhost() {
+        assert_eq!(stdio_command_for(Some(&AccessZone::Localhost)), stdio_command());
+        assert_eq!(stdio_command_for(Some(&AccessZone::PrivateNetwork)), STDIO_BINARY);
+        assert_eq!(stdio_command_for(None), STDIO_BINARY);
+    }
 }
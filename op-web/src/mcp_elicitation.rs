@@ -0,0 +1,211 @@
+//! MCP Elicitation - ask the user for missing or risky tool inputs
+//!
+//! Before a tool runs, required schema fields missing from its arguments are
+//! requested from the client with `elicitation/create`, and destructive
+//! tools (by name: `*_delete_*`, `*_stop_*`, ...) ask for confirmation. The
+//! call then resumes with the user's answers merged into the arguments. A
+//! missing field that cannot be asked for (object or array) fails the call.
+//!
+//! Both only happen when the client declared the `elicitation` capability;
+//! other clients keep the old behaviour, where the tool reports the missing
+//! field and the LLM has to recover.
+
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::mcp_session::McpSession;
+
+/// The user is filling in a form, so allow them time
+const ELICITATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Name segments that mark a tool as destructive
+const DESTRUCTIVE_VERBS: &[&str] = &[
+    "delete", "remove", "destroy", "drop", "purge", "wipe", "kill", "stop", "restart", "reboot",
+    "shutdown", "poweroff", "format", "reset", "disable", "uninstall",
+];
+
+/// Elicitation only supports flat primitive fields
+const PRIMITIVE_TYPES: &[&str] = &["string", "number", "integer", "boolean"];
+
+/// Schema keywords passed through to the client's form
+const FIELD_KEYWORDS: &[&str] = &[
+    "type", "title", "description", "enum", "enumNames", "format", "minimum", "maximum",
+    "minLength", "maxLength", "default",
+];
+
+/// Whether a tool name marks it as destructive (`ovs_delete_bridge`, `dbus_systemd_stop_unit`)
+pub fn is_destructive(tool_name: &str) -> bool {
+    tool_name
+        .split(|c: char| c == '_' || c == '-' || c == '.')
+        .any(|segment| DESTRUCTIVE_VERBS.contains(&segment))
+}
+
+/// Complete a tool call's arguments with the user's help.
+///
+/// Returns the arguments to run with, or an error message when the user
+/// declined, cancelled or did not confirm a destructive call.
+pub async fn prepare_arguments(
+    session: Option<&Arc<McpSession>>,
+    tool_name: &str,
+    input_schema: &Value,
+    arguments: Value,
+) -> Result<Value, String> {
+    let Some(session) = session.filter(|s| s.client_supports("elicitation")) else {
+        return Ok(arguments);
+    };
+
+    let missing = missing_required(input_schema, &arguments);
+    let destructive = is_destructive(tool_name);
+    let Some(mut requested) = requested_schema(input_schema, &missing) else {
+        // Something we cannot ask for (object/array field); running without
+        // it would also skip the confirmation of a destructive call
+        debug!("Cannot elicit {:?} for {}", missing, tool_name);
+        return Err(format!("Missing required arguments for {}: {}", tool_name, missing.join(", ")));
+    };
+    if missing.is_empty() && !destructive {
+        return Ok(arguments);
+    }
+
+    if destructive {
+        add_confirmation(&mut requested, tool_name);
+    }
+    let message = if missing.is_empty() {
+        format!(
+            "{} may change or remove system state. Run it with {}?",
+            tool_name,
+            simd_json::to_string(&arguments).unwrap_or_default()
+        )
+    } else {
+        format!("{} needs more information: {}", tool_name, missing.join(", "))
+    };
+
+    info!("Eliciting input for {} (missing: {:?}, destructive: {})", tool_name, missing, destructive);
+    let result = session
+        .request(
+            "elicitation/create",
+            json!({ "message": message, "requestedSchema": requested }),
+            ELICITATION_TIMEOUT,
+        )
+        .await?;
+
+    match result.get("action").and_then(|a| a.as_str()) {
+        Some("accept") => {}
+        Some("decline") => return Err(format!("User declined to provide input for {}", tool_name)),
+        _ => return Err(format!("User cancelled {}", tool_name)),
+    }
+
+    let content = result.get("content").cloned().unwrap_or_else(|| json!({}));
+    if destructive && content.get("confirm").and_then(|c| c.as_bool()) != Some(true) {
+        return Err(format!("User did not confirm {}", tool_name));
+    }
+    Ok(merge_answers(arguments, &content, &missing))
+}
+
+/// Required fields absent (or null) in the arguments
+fn missing_required(input_schema: &Value, arguments: &Value) -> Vec<String> {
+    input_schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|required| {
+            required
+                .iter()
+                .filter_map(|field| field.as_str())
+                .filter(|field| arguments.get(*field).map(|v| v.is_null()).unwrap_or(true))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Flat schema for the missing fields, or `None` if any is not a primitive
+fn requested_schema(input_schema: &Value, missing: &[String]) -> Option<Value> {
+    let mut properties = json!({});
+    for field in missing {
+        let property = input_schema.get("properties").and_then(|p| p.get(field.as_str()))?;
+        let field_type = property.get("type").and_then(|t| t.as_str()).unwrap_or("string");
+        if !PRIMITIVE_TYPES.contains(&field_type) {
+            return None;
+        }
+
+        let mut requested = json!({ "type": field_type });
+        for keyword in FIELD_KEYWORDS {
+            if let (Some(value), Some(obj)) = (property.get(*keyword), requested.as_object_mut()) {
+                obj.insert((*keyword).into(), value.clone());
+            }
+        }
+        properties.as_object_mut()?.insert(field.clone().into(), requested);
+    }
+
+    Some(json!({
+        "type": "object",
+        "properties": properties,
+        "required": missing
+    }))
+}
+
+fn add_confirmation(requested: &mut Value, tool_name: &str) {
+    if let Some(properties) = requested.get_mut("properties").and_then(|p| p.as_object_mut()) {
+        properties.insert(
+            "confirm".into(),
+            json!({
+                "type": "boolean",
+                "title": "Confirm",
+                "description": format!("Run {}", tool_name),
+                "default": false
+            }),
+        );
+    }
+    if let Some(required) = requested.get_mut("required").and_then(|r| r.as_array_mut()) {
+        required.push(json!("confirm"));
+    }
+}
+
+fn merge_answers(arguments: Value, content: &Value, missing: &[String]) -> Value {
+    let mut arguments = if arguments.is_object() { arguments } else { json!({}) };
+    if let Some(obj) = arguments.as_object_mut() {
+        for field in missing {
+            if let Some(value) = content.get(field.as_str()) {
+                obj.insert(field.clone().into(), value.clone());
+            }
+        }
+    }
+    arguments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destructive_tool_names() {
+        assert!(is_destructive("ovs_delete_bridge"));
+        assert!(is_destructive("dbus_systemd_stop_unit"));
+        assert!(!is_destructive("ovs_list_bridges"));
+        // Whole segments only
+        assert!(!is_destructive("dbus_systemd_list_stopped_units"));
+    }
+
+    #[test]
+    fn test_requested_schema_for_missing_fields() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "bridge": { "type": "string", "description": "Bridge name" },
+                "port": { "type": "string" },
+                "options": { "type": "object" }
+            },
+            "required": ["bridge", "port"]
+        });
+
+        let missing = missing_required(&schema, &json!({ "bridge": "br0" }));
+        assert_eq!(missing, vec!["port"]);
+        let requested = requested_schema(&schema, &missing).unwrap();
+        assert_eq!(requested.get("required"), Some(&json!(["port"])));
+
+        // Object fields cannot be elicited
+        assert!(requested_schema(&schema, &["options".to_string()]).is_none());
+    }
+}
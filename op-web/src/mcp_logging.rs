@@ -0,0 +1,189 @@
+//! MCP Logging - Bridge `tracing` events to `notifications/message`
+//!
+//! Implements the MCP `logging` capability. While a session's request is
+//! being processed, `tracing` events from the op-* crates (tool execution,
+//! errors, D-Bus projection warnings) are forwarded to that session's
+//! client at or above the level it chose with `logging/setLevel`.
+
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::sync::Arc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::mcp_session::McpSession;
+
+/// Only events from our own crates are forwarded to clients
+const FORWARDED_TARGET_PREFIX: &str = "op_";
+
+/// MCP log levels (RFC 5424 severities), lowest first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Notice,
+    #[default]
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl LogLevel {
+    pub fn parse(level: &str) -> Option<Self> {
+        match level {
+            "debug" => Some(Self::Debug),
+            "info" => Some(Self::Info),
+            "notice" => Some(Self::Notice),
+            "warning" => Some(Self::Warning),
+            "error" => Some(Self::Error),
+            "critical" => Some(Self::Critical),
+            "alert" => Some(Self::Alert),
+            "emergency" => Some(Self::Emergency),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Notice => "notice",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Critical => "critical",
+            Self::Alert => "alert",
+            Self::Emergency => "emergency",
+        }
+    }
+
+    fn from_tracing(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => Self::Error,
+            Level::WARN => Self::Warning,
+            Level::INFO => Self::Info,
+            Level::DEBUG | Level::TRACE => Self::Debug,
+        }
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_SESSION: Arc<McpSession>;
+}
+
+/// Run `fut` with log forwarding to `session` (if any)
+pub async fn with_session<F: Future>(session: Option<&Arc<McpSession>>, fut: F) -> F::Output {
+    match session {
+        Some(session) => CURRENT_SESSION.scope(session.clone(), fut).await,
+        None => fut.await,
+    }
+}
+
+/// The session of the MCP request running on this task, if any
+pub fn current_session() -> Option<Arc<McpSession>> {
+    CURRENT_SESSION.try_with(|s| s.clone()).ok()
+}
+
+/// Handle `logging/setLevel`, returning an error message for invalid levels
+pub fn handle_set_level(session: Option<&Arc<McpSession>>, params: &Value) -> Result<Value, String> {
+    let level = params
+        .get("level")
+        .and_then(|l| l.as_str())
+        .ok_or("Missing required parameter: level")?;
+    let level = LogLevel::parse(level).ok_or_else(|| format!("Invalid log level: {}", level))?;
+
+    let session = session.ok_or("logging/setLevel requires an SSE session (connect to the SSE endpoint first)")?;
+    session.set_log_level(level);
+    tracing::info!("MCP session {} log level set to {}", session.id, level.as_str());
+
+    Ok(json!({}))
+}
+
+/// Whether [`McpLogLayer`] may forward events from this callsite; use as its
+/// per-layer filter so other layers' filters do not decide what it sees
+pub fn forwards(metadata: &Metadata<'_>) -> bool {
+    metadata.target().starts_with(FORWARDED_TARGET_PREFIX) && *metadata.level() <= Level::DEBUG
+}
+
+/// `tracing` layer forwarding events to the MCP session that caused them
+pub struct McpLogLayer;
+
+impl<S: Subscriber> Layer<S> for McpLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !metadata.target().starts_with(FORWARDED_TARGET_PREFIX) {
+            return;
+        }
+
+        let Ok(session) = CURRENT_SESSION.try_with(|s| s.clone()) else {
+            return;
+        };
+
+        let level = LogLevel::from_tracing(metadata.level());
+        if level < session.log_level() {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        session.notify(
+            "notifications/message",
+            json!({
+                "level": level.as_str(),
+                "logger": metadata.target(),
+                "data": {
+                    "message": visitor.message,
+                    "fields": visitor.fields
+                }
+            }),
+        );
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            if !self.fields.is_empty() {
+                self.fields.push(' ');
+            }
+            let _ = write!(self.fields, "{}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_ordering_and_parse() {
+        assert!(LogLevel::Error > LogLevel::Warning);
+        assert!(LogLevel::Debug < LogLevel::Info);
+        assert_eq!(LogLevel::parse("notice"), Some(LogLevel::Notice));
+        assert_eq!(LogLevel::parse("verbose"), None);
+        assert_eq!(LogLevel::from_tracing(&Level::WARN), LogLevel::Warning);
+    }
+}
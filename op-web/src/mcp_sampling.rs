@@ -0,0 +1,245 @@
+//! MCP Sampling - borrow the connected client's model
+//!
+//! Server-side code that needs a completion (model-assisted agents) asks
+//! for one here instead of calling `ChatManager` directly. When the server has no LLM provider configured and the MCP
+//! request running on this task belongs to a session whose client declared
+//! the `sampling` capability, the prompt is sent to the client as
+//! `sampling/createMessage` and answered by the client's own model.
+//! Otherwise the server's `ChatManager` answers.
+
+use anyhow::{anyhow, bail, Result};
+use op_llm::chat::ChatManager;
+use op_llm::provider::{ChatMessage, ChatRequest, LlmProvider, ToolChoice};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::mcp_logging;
+use crate::mcp_session::McpSession;
+
+/// Clients usually ask the user to approve a sampling request
+const SAMPLING_TIMEOUT: Duration = Duration::from_secs(300);
+
+lazy_static::lazy_static! {
+    /// Server model used when the client cannot sample (set by `AppState`)
+    static ref FALLBACK_CHAT: RwLock<Option<Arc<ChatManager>>> = RwLock::new(None);
+}
+
+/// Register the server's `ChatManager` as the fallback model
+pub fn set_fallback(chat_manager: Arc<ChatManager>) {
+    *FALLBACK_CHAT.write().unwrap() = Some(chat_manager);
+}
+
+/// A single-turn completion request
+#[derive(Debug, Clone)]
+pub struct SamplingRequest {
+    pub system_prompt: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: u32,
+    pub temperature: Option<f32>,
+}
+
+impl SamplingRequest {
+    pub fn new(system_prompt: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            system_prompt: Some(system_prompt.into()),
+            messages: vec![ChatMessage::user(prompt.into())],
+            max_tokens: 2048,
+            temperature: None,
+        }
+    }
+}
+
+/// Where a completion came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelSource {
+    /// The MCP client's model, via `sampling/createMessage`
+    Client,
+    /// The server's configured LLM provider
+    Server,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub model: String,
+    pub source: ModelSource,
+}
+
+/// Get a completion from the server's provider, or from the client's model
+/// when the server has none
+pub async fn create_message(request: SamplingRequest) -> Result<Completion> {
+    let fallback = FALLBACK_CHAT.read().unwrap().clone();
+    let server_has_model = fallback
+        .as_ref()
+        .map(|chat| !chat.available_providers().is_empty())
+        .unwrap_or(false);
+
+    if !server_has_model {
+        if let Some(session) = sampling_session() {
+            return sample_from_client(&session, &request).await;
+        }
+    }
+
+    let Some(chat_manager) = fallback else {
+        bail!("No model available: no LLM provider configured and the MCP client does not support sampling");
+    };
+    let model = chat_manager.current_model().await;
+    let chat_request = ChatRequest {
+        messages: with_system_prompt(&request),
+        tools: vec![],
+        tool_choice: ToolChoice::None,
+        max_tokens: Some(request.max_tokens),
+        temperature: request.temperature.map(Into::into),
+        top_p: None,
+    };
+    let response = chat_manager
+        .chat_with_request(&model, chat_request)
+        .await
+        .map_err(|e| anyhow!("LLM request failed: {}", e))?;
+
+    Ok(Completion {
+        text: response.message.content,
+        model,
+        source: ModelSource::Server,
+    })
+}
+
+/// Get a completion from the client's model only
+pub async fn sample_client(request: &SamplingRequest) -> Result<Completion> {
+    let Some(session) = sampling_session() else {
+        bail!("The MCP client does not support sampling");
+    };
+    sample_from_client(&session, request).await
+}
+
+/// Whether the MCP request running on this task can borrow the client's model
+pub fn client_can_sample() -> bool {
+    sampling_session().is_some()
+}
+
+/// The current MCP session, if its client declared `sampling`
+fn sampling_session() -> Option<Arc<McpSession>> {
+    mcp_logging::current_session().filter(|session| session.client_supports("sampling"))
+}
+
+async fn sample_from_client(session: &McpSession, request: &SamplingRequest) -> Result<Completion> {
+    info!("Requesting completion from the MCP client (session {})", session.id);
+    let result = session
+        .request("sampling/createMessage", sampling_params(request), SAMPLING_TIMEOUT)
+        .await
+        .map_err(|e| anyhow!("Client sampling failed: {}", e))?;
+
+    let text = result
+        .get("content")
+        .and_then(|c| c.get("text"))
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| anyhow!("Client sampling returned no text content"))?
+        .to_string();
+    let model = result
+        .get("model")
+        .and_then(|m| m.as_str())
+        .unwrap_or("client")
+        .to_string();
+    debug!("Client model {} returned {} chars", model, text.len());
+
+    Ok(Completion {
+        text,
+        model,
+        source: ModelSource::Client,
+    })
+}
+
+/// Build `sampling/createMessage` params. Sampling only knows `user` and
+/// `assistant`, so system messages join the system prompt and tool results
+/// are passed back as user text.
+fn sampling_params(request: &SamplingRequest) -> Value {
+    let mut system: Vec<String> = request.system_prompt.iter().cloned().collect();
+    let mut messages = Vec::new();
+
+    for message in &request.messages {
+        let (role, text) = match message.role.as_str() {
+            "system" => {
+                system.push(message.content.clone());
+                continue;
+            }
+            "assistant" => ("assistant", message.content.clone()),
+            "tool" => ("user", format!("Tool result:\n{}", message.content)),
+            _ => ("user", message.content.clone()),
+        };
+        messages.push(json!({
+            "role": role,
+            "content": { "type": "text", "text": text }
+        }));
+    }
+
+    let mut params = json!({
+        "messages": messages,
+        "includeContext": "none",
+        "maxTokens": request.max_tokens
+    });
+    if let Some(obj) = params.as_object_mut() {
+        if !system.is_empty() {
+            obj.insert("systemPrompt".into(), json!(system.join("\n\n")));
+        }
+        if let Some(temperature) = request.temperature {
+            obj.insert("temperature".into(), json!(temperature as f64));
+        }
+    }
+    params
+}
+
+fn with_system_prompt(request: &SamplingRequest) -> Vec<ChatMessage> {
+    let mut messages = Vec::with_capacity(request.messages.len() + 1);
+    if let Some(system) = &request.system_prompt {
+        messages.push(ChatMessage {
+            role: "system".to_string(),
+            content: system.clone(),
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+    messages.extend(request.messages.iter().cloned());
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_params_map_roles() {
+        let request = SamplingRequest {
+            system_prompt: Some("Be brief".to_string()),
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: "Tools are listed below".to_string(),
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+                ChatMessage::user("restart nginx".to_string()),
+                ChatMessage {
+                    role: "tool".to_string(),
+                    content: "ok".to_string(),
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+            ],
+            max_tokens: 256,
+            temperature: None,
+        };
+
+        let params = sampling_params(&request);
+        assert_eq!(
+            params.get("systemPrompt").and_then(|s| s.as_str()),
+            Some("Be brief\n\nTools are listed below")
+        );
+        let messages = params.get("messages").and_then(|m| m.as_array()).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.get("role").and_then(|r| r.as_str()) == Some("user")));
+        assert_eq!(params.get("maxTokens").and_then(|m| m.as_u64()), Some(256));
+    }
+}
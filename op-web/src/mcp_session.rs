@@ -0,0 +1,534 @@
+//! MCP Sessions - Per-connection state for server-initiated messages
+//!
+//! Each SSE connection (`/mcp/sse`, `/mcp/compact`, `/mcp/agents`) opens a
+//! session. The `endpoint` event tells the client to POST with
+//! `?sessionId=...`, which lets the message handlers:
+//! - Send `notifications/progress` for calls carrying `_meta.progressToken`
+//! - Abort in-flight calls when the client sends `notifications/cancelled`
+//! - Forward log messages at the level set by `logging/setLevel`
+//! - Send server-initiated requests (e.g. `sampling/createMessage`) and
+//!   route the client's replies, POSTed like any other message, back to them
+
+use axum::response::sse::Event;
+use futures::future::{AbortHandle, Abortable};
+use futures::stream::Stream;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::StreamExt;
+use tracing::{debug, info, warn};
+
+use crate::mcp_logging::LogLevel;
+use crate::tool_output::{self, OutputSink};
+
+/// JSON-RPC error code for a request cancelled by the client
+pub const REQUEST_CANCELLED: i32 = -32800;
+
+/// Query string carried by POSTs from SSE clients
+#[derive(Debug, Default, Deserialize)]
+pub struct SessionQuery {
+    #[serde(rename = "sessionId", alias = "session_id")]
+    pub session_id: Option<String>,
+}
+
+/// The request was aborted by `notifications/cancelled`
+#[derive(Debug, Clone, Copy)]
+pub struct RequestCancelled;
+
+impl std::fmt::Display for RequestCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Request cancelled by client")
+    }
+}
+
+/// A single MCP client connection
+pub struct McpSession {
+    pub id: String,
+    tx: mpsc::UnboundedSender<String>,
+    /// In-flight requests keyed by serialized JSON-RPC id
+    in_flight: Mutex<HashMap<String, AbortHandle>>,
+    /// Minimum level forwarded as `notifications/message`
+    log_level: Mutex<LogLevel>,
+    /// `capabilities` the client declared in `initialize`
+    client_capabilities: Mutex<Value>,
+    /// Server-initiated requests awaiting a reply, keyed by serialized id
+    pending: Mutex<HashMap<String, oneshot::Sender<Result<Value, String>>>>,
+    next_request_id: AtomicU64,
+}
+
+impl McpSession {
+    /// Send a JSON-RPC message to the client over the SSE stream
+    pub fn send<T: Serialize>(&self, message: &T) {
+        if let Ok(json) = simd_json::to_string(message) {
+            let _ = self.tx.send(json);
+        }
+    }
+
+    /// Send a JSON-RPC notification to the client
+    pub fn notify(&self, method: &str, params: Value) {
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params
+        }));
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        *self.log_level.lock().unwrap()
+    }
+
+    pub fn set_log_level(&self, level: LogLevel) {
+        *self.log_level.lock().unwrap() = level;
+    }
+
+    pub fn set_client_capabilities(&self, capabilities: Value) {
+        *self.client_capabilities.lock().unwrap() = capabilities;
+    }
+
+    /// Whether the client declared `capability` (e.g. `sampling`) in `initialize`
+    pub fn client_supports(&self, capability: &str) -> bool {
+        self.client_capabilities
+            .lock()
+            .unwrap()
+            .get(capability)
+            .map(|c| !c.is_null())
+            .unwrap_or(false)
+    }
+
+    /// Send a request to the client and wait for its reply.
+    ///
+    /// Errors carry the client's JSON-RPC error message, or say the session
+    /// closed or the client did not answer within `timeout`.
+    pub async fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value, String> {
+        let id = json!(format!("srv-{}", self.next_request_id.fetch_add(1, Ordering::Relaxed)));
+        let key = request_key(&id);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(key.clone(), tx);
+
+        debug!("Session {} -> client request {} ({:?})", self.id, method, id);
+        self.send(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params
+        }));
+
+        let outcome = tokio::time::timeout(timeout, rx).await;
+        self.pending.lock().unwrap().remove(&key);
+        match outcome {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(_)) => Err("MCP session closed before the client replied".to_string()),
+            Err(_) => Err(format!("Client did not answer {} within {}s", method, timeout.as_secs())),
+        }
+    }
+
+    /// Deliver a client reply to the matching [`McpSession::request`];
+    /// returns false when no request with that id is waiting
+    pub fn resolve(&self, message: &Value) -> bool {
+        let Some(id) = message.get("id") else {
+            return false;
+        };
+        let Some(tx) = self.pending.lock().unwrap().remove(&request_key(id)) else {
+            return false;
+        };
+
+        let reply = match message.get("error") {
+            Some(error) => Err(error
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("Client returned an error")
+                .to_string()),
+            None => Ok(message.get("result").cloned().unwrap_or_else(|| json!({}))),
+        };
+        let _ = tx.send(reply);
+        true
+    }
+
+    /// Abort an in-flight request; returns false if it already finished
+    pub fn cancel(&self, request_id: &Value) -> bool {
+        let key = request_key(request_id);
+        match self.in_flight.lock().unwrap().remove(&key) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn track(&self, request_id: &Value, handle: AbortHandle) {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .insert(request_key(request_id), handle);
+    }
+
+    fn untrack(&self, request_id: &Value) {
+        self.in_flight.lock().unwrap().remove(&request_key(request_id));
+    }
+}
+
+fn request_key(request_id: &Value) -> String {
+    simd_json::to_string(request_id).unwrap_or_default()
+}
+
+/// Registry of open sessions
+#[derive(Default)]
+pub struct McpSessions {
+    sessions: RwLock<HashMap<String, Arc<McpSession>>>,
+}
+
+impl McpSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a session and return the SSE stream of server-initiated messages.
+    /// The session is removed when the stream is dropped (client disconnect).
+    pub fn open(
+        &'static self,
+    ) -> (Arc<McpSession>, impl Stream<Item = Result<Event, Infallible>>) {
+        let (session, messages) = self.open_raw();
+        let stream = messages.map(|data| Ok(Event::default().event("message").data(data)));
+        (session, stream)
+    }
+
+    /// Open a session whose messages are serialized JSON-RPC strings
+    /// (for transports other than SSE, e.g. stdio)
+    pub fn open_raw(&'static self) -> (Arc<McpSession>, impl Stream<Item = String>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let session = Arc::new(McpSession {
+            id: uuid::Uuid::new_v4().to_string(),
+            tx,
+            in_flight: Mutex::new(HashMap::new()),
+            log_level: Mutex::new(LogLevel::default()),
+            client_capabilities: Mutex::new(json!({})),
+            pending: Mutex::new(HashMap::new()),
+            next_request_id: AtomicU64::new(1),
+        });
+
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id.clone(), session.clone());
+        info!("MCP session opened: {}", session.id);
+
+        let guard = SessionGuard {
+            sessions: self,
+            id: session.id.clone(),
+        };
+        let stream = UnboundedReceiverStream::new(rx).map(move |data| {
+            let _ = &guard;
+            data
+        });
+
+        (session, stream)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<McpSession>> {
+        self.sessions.read().unwrap().get(id).cloned()
+    }
+
+    /// Resolve the session a POST belongs to, if any
+    pub fn from_query(&self, query: &SessionQuery) -> Option<Arc<McpSession>> {
+        query.session_id.as_deref().and_then(|id| self.get(id))
+    }
+
+    /// Send a notification to every open session
+    pub fn notify_all(&self, method: &str, params: Value) {
+        for session in self.sessions.read().unwrap().values() {
+            session.notify(method, params.clone());
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.sessions.read().unwrap().len()
+    }
+
+    /// Close a session before its stream is dropped, ending the stream once
+    /// the last handle to the session is gone (stdio shutdown)
+    pub fn close(&self, id: &str) {
+        self.remove(id);
+    }
+
+    fn remove(&self, id: &str) {
+        if let Some(session) = self.sessions.write().unwrap().remove(id) {
+            for (_, handle) in session.in_flight.lock().unwrap().drain() {
+                handle.abort();
+            }
+            // Dropping the senders fails any request still waiting on the client
+            session.pending.lock().unwrap().clear();
+            info!("MCP session closed: {}", id);
+        }
+    }
+}
+
+struct SessionGuard {
+    sessions: &'static McpSessions,
+    id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.sessions.remove(&self.id);
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Global session registry shared by all MCP surfaces
+    pub static ref MCP_SESSIONS: McpSessions = McpSessions::new();
+}
+
+/// Progress reporter for a request that carried `_meta.progressToken`
+#[derive(Clone)]
+pub struct ProgressReporter {
+    session: Arc<McpSession>,
+    token: Value,
+}
+
+impl ProgressReporter {
+    /// Build a reporter from `tools/call` params, if the client asked for progress
+    pub fn from_params(session: Option<&Arc<McpSession>>, params: &Value) -> Option<Self> {
+        let token = params.get("_meta")?.get("progressToken")?.clone();
+        Some(Self {
+            session: session?.clone(),
+            token,
+        })
+    }
+
+    /// Emit `notifications/progress`
+    pub fn report(&self, progress: f64, total: Option<f64>, message: Option<&str>) {
+        let mut params = json!({
+            "progressToken": self.token.clone(),
+            "progress": progress
+        });
+        if let Some(obj) = params.as_object_mut() {
+            if let Some(total) = total {
+                obj.insert("total".into(), json!(total));
+            }
+            if let Some(message) = message {
+                obj.insert("message".into(), json!(message));
+            }
+        }
+        self.session.notify("notifications/progress", params);
+    }
+
+    /// Sink reporting each output chunk as progress below `1.0`, which
+    /// stays increasing however many chunks arrive
+    fn output_sink(&self) -> OutputSink {
+        let reporter = self.clone();
+        OutputSink::new(move |chunk| {
+            let progress = chunk.seq as f64 / (chunk.seq + 1) as f64;
+            reporter.report(progress, Some(1.0), Some(&chunk.data));
+        })
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_PROGRESS: ProgressReporter;
+}
+
+/// Report progress for the MCP request currently executing on this task.
+/// No-op when the caller did not send a progress token.
+pub fn report_progress(progress: f64, total: Option<f64>, message: Option<&str>) {
+    let _ = CURRENT_PROGRESS.try_with(|p| p.report(progress, total, message));
+}
+
+/// Run a request as a cancellable in-flight call on its session.
+///
+/// Emits start/finish progress when the client sent a progress token and
+/// makes [`report_progress`] available to anything running inside `fut`;
+/// tool output chunks are relayed as progress messages in between.
+pub async fn run_request<F, T>(
+    session: Option<&Arc<McpSession>>,
+    request_id: &Value,
+    params: &Value,
+    fut: F,
+) -> Result<T, RequestCancelled>
+where
+    F: Future<Output = T>,
+{
+    let progress = ProgressReporter::from_params(session, params);
+
+    let (handle, registration) = AbortHandle::new_pair();
+    if let Some(session) = session {
+        session.track(request_id, handle);
+    }
+
+    let run = Abortable::new(fut, registration);
+    let result = match progress.clone() {
+        Some(reporter) => {
+            reporter.report(0.0, Some(1.0), Some("started"));
+            let output = reporter.output_sink();
+            let result = CURRENT_PROGRESS
+                .scope(reporter.clone(), tool_output::with_sink(Some(output), run))
+                .await;
+            if result.is_ok() {
+                reporter.report(1.0, Some(1.0), Some("completed"));
+            }
+            result
+        }
+        None => run.await,
+    };
+
+    if let Some(session) = session {
+        session.untrack(request_id);
+    }
+
+    result.map_err(|_| {
+        debug!("Request {:?} cancelled", request_id);
+        RequestCancelled
+    })
+}
+
+/// Remember what the client declared in `initialize`
+pub fn record_initialize(session: Option<&Arc<McpSession>>, params: &Value) {
+    if let Some(session) = session {
+        let capabilities = params.get("capabilities").cloned().unwrap_or_else(|| json!({}));
+        session.set_client_capabilities(capabilities);
+    }
+}
+
+/// Whether `message` is a reply to a server-initiated request
+/// (an id and a result or error, but no method)
+pub fn is_client_response(message: &Value) -> bool {
+    message.get("method").is_none()
+        && message.get("id").map(|id| !id.is_null()).unwrap_or(false)
+        && (message.get("result").is_some() || message.get("error").is_some())
+}
+
+/// Hand a client reply to its session. Returns false if `message` is not a
+/// reply, so the caller should dispatch it as a request or notification.
+pub fn route_response(session: Option<&Arc<McpSession>>, message: &Value) -> bool {
+    if !is_client_response(message) {
+        return false;
+    }
+    match session {
+        Some(session) if session.resolve(message) => {}
+        Some(session) => debug!("Unexpected client reply {:?} on session {}", message.get("id"), session.id),
+        None => warn!("Client reply {:?} without a session, dropping", message.get("id")),
+    }
+    true
+}
+
+/// Parse a POSTed message body. Client replies are routed to the session
+/// and yield `Ok(None)` (answer with 202 Accepted).
+pub fn parse_post<T: DeserializeOwned>(
+    session: Option<&Arc<McpSession>>,
+    body: &[u8],
+) -> Result<Option<T>, String> {
+    let mut buf = body.to_vec();
+    let message: Value = simd_json::from_slice(&mut buf).map_err(|e| e.to_string())?;
+    if route_response(session, &message) {
+        return Ok(None);
+    }
+    simd_json::serde::from_owned_value(message)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Handle `notifications/cancelled` for a session
+pub fn handle_cancelled(session: Option<&Arc<McpSession>>, params: &Value) {
+    let Some(request_id) = params.get("requestId") else {
+        return;
+    };
+    let reason = params
+        .get("reason")
+        .and_then(|r| r.as_str())
+        .unwrap_or("no reason given");
+
+    match session {
+        Some(session) if session.cancel(request_id) => {
+            info!("Cancelled request {:?} on session {} ({})", request_id, session.id, reason);
+        }
+        Some(session) => {
+            debug!("Cancel for unknown request {:?} on session {}", request_id, session.id);
+        }
+        None => debug!("Cancel for request {:?} without a session", request_id),
+    }
+}
+
+/// Build the `endpoint` event URL for a session
+pub fn endpoint_url(headers: &axum::http::HeaderMap, path: &str, session: &McpSession) -> String {
+    let host = headers
+        .get("host")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("localhost");
+
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+
+    format!("{}://{}{}?sessionId={}", scheme, host, path, session.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_aborts_in_flight_request() {
+        let (session, _stream) = MCP_SESSIONS.open();
+        let id = json!(7);
+
+        let pending = {
+            let session = session.clone();
+            let id = id.clone();
+            tokio::spawn(async move {
+                run_request(Some(&session), &id, &json!({}), futures::future::pending::<()>()).await
+            })
+        };
+
+        // Wait for the request to register before cancelling
+        while !session.cancel(&id) {
+            tokio::task::yield_now().await;
+        }
+        assert!(pending.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_progress_token_emits_notifications() {
+        let (session, stream) = MCP_SESSIONS.open();
+        let params = json!({ "_meta": { "progressToken": "tok" } });
+
+        let value = run_request(Some(&session), &json!(1), &params, async { 42 }).await;
+        assert_eq!(value.unwrap(), 42);
+
+        drop(session);
+        let events: Vec<_> = stream.take(2).collect().await;
+        assert_eq!(events.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_reply_resolves_server_request() {
+        let (session, mut stream) = MCP_SESSIONS.open_raw();
+
+        let waiting = {
+            let session = session.clone();
+            tokio::spawn(async move {
+                session
+                    .request("sampling/createMessage", json!({}), Duration::from_secs(5))
+                    .await
+            })
+        };
+
+        let mut sent = stream.next().await.unwrap();
+        let sent: Value = unsafe { simd_json::from_str(&mut sent) }.unwrap();
+        let reply = json!({ "jsonrpc": "2.0", "id": sent.get("id").cloned().unwrap(), "result": { "ok": true } });
+        assert!(route_response(Some(&session), &reply));
+        assert_eq!(waiting.await.unwrap().unwrap(), json!({ "ok": true }));
+
+        // Requests and notifications are left for the dispatcher
+        assert!(!route_response(Some(&session), &json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" })));
+    }
+}
@@ -1,113 +1,624 @@
-//! Smart MCP Router - Single entry point that detects client capabilities
+//! Smart MCP Router - Single negotiated entry point at `/mcp`
+//!
+//! Clients no longer have to choose between `/mcp/sse`, `/mcp/compact`,
+//! `/mcp/agents`, `/jsonrpc` and `/rpc`. The surface is negotiated, first
+//! match wins:
+//! 1. `x-mcp-mode: full|compact|agents` header
+//! 2. Per-key configuration (`keys` in the routing config, by the name of
+//!    the authenticated API key; see [`crate::api_keys`])
+//! 3. The mode negotiated earlier in the same session
+//! 4. `initialize` `clientInfo.name` (`clients` in the routing config)
+//! 5. The configured default (full)
+//!
+//! The transport follows the request: `GET` (with `Accept: text/event-stream`)
+//! opens an SSE session whose `endpoint` is `/mcp?sessionId=...`; `POST`
+//! answers with JSON (or a single SSE event when the client only accepts
+//! `text/event-stream`) and hands out an `Mcp-Session-Id` on `initialize` so
+//! stateless HTTP clients keep their negotiated mode. Every decision is
+//! logged and echoed back in the `x-mcp-mode` response header. JSON-RPC
+//! batches are answered with an array; notifications get `202 Accepted`.
 
 use axum::{
-    extract::{State, Request},
-    response::{Response, IntoResponse},
-    http::{HeaderMap, StatusCode},
-    Router, routing::{get, post},
+    body::Bytes,
+    extract::{Extension, Query},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
 };
-use std::sync::Arc;
+use futures::stream::{self, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
 
-/// Detect client capabilities from headers
-#[derive(Debug, Clone)]
-enum ClientCapability {
-    SSE,           // Server-Sent Events
-    Streaming,     // HTTP streaming
-    Compact,       // Compact mode (limited tools)
-    Agents,        // Critical agents direct access
-    JsonRpc,       // Standard JSON-RPC
-}
-
-fn detect_client_capability(headers: &HeaderMap) -> ClientCapability {
-    // Check Accept header
-    if let Some(accept) = headers.get("accept") {
-        if let Ok(accept_str) = accept.to_str() {
-            if accept_str.contains("text/event-stream") {
-                return ClientCapability::SSE;
+use crate::api_keys;
+use crate::audit;
+use crate::mcp_logging;
+use crate::mcp_session::{self, McpSession, SessionQuery, MCP_SESSIONS};
+use crate::state::AppState;
+use crate::tool_profiles::ToolProfile;
+
+const MCP_ROUTING_CONFIG_PATH: &str = "/var/lib/op-dbus/mcp-routing.json";
+
+/// Negotiated sessions idle for longer than this are forgotten
+const NEGOTIATION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Which MCP surface serves a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum McpMode {
+    /// Every registry tool in `tools/list`
+    Full,
+    /// Four meta-tools for discovery and execution
+    Compact,
+    /// Critical agents as tools
+    Agents,
+}
+
+impl McpMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "full" | "standard" | "jsonrpc" => Some(Self::Full),
+            "compact" => Some(Self::Compact),
+            "agents" => Some(Self::Agents),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Compact => "compact",
+            Self::Agents => "agents",
+        }
+    }
+}
+
+/// Routing for a single API key
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct KeyRoute {
+    #[serde(default)]
+    pub mode: Option<McpMode>,
+    /// Tool groups profile applied to full and compact modes
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// Contents of the routing config file
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoutingConfig {
+    #[serde(default = "default_mode")]
+    pub default_mode: McpMode,
+    /// Lowercase substring of `clientInfo.name` -> mode
+    #[serde(default = "default_clients")]
+    pub clients: HashMap<String, McpMode>,
+    /// API key name -> routing; never the key itself
+    #[serde(default)]
+    pub keys: HashMap<String, KeyRoute>,
+}
+
+fn default_mode() -> McpMode {
+    McpMode::Full
+}
+
+fn default_clients() -> HashMap<String, McpMode> {
+    // Clients with small tool budgets get the meta-tools
+    [
+        ("claude", McpMode::Compact),
+        ("cursor", McpMode::Compact),
+        ("windsurf", McpMode::Compact),
+        ("critical-agent", McpMode::Agents),
+    ]
+    .into_iter()
+    .map(|(name, mode)| (name.to_string(), mode))
+    .collect()
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            default_mode: default_mode(),
+            clients: default_clients(),
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl RoutingConfig {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(MCP_ROUTING_CONFIG_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<RoutingConfig>(&mut raw) } {
+            Ok(mut config) => {
+                // Earlier configs mapped the keys themselves
+                config.keys.retain(|name, _| {
+                    let raw_key = name.starts_with(api_keys::KEY_PREFIX);
+                    if raw_key {
+                        warn!("Ignoring a raw API key in {}; route keys by name", MCP_ROUTING_CONFIG_PATH);
+                    }
+                    !raw_key
+                });
+                info!(
+                    "Loaded MCP routing config from {} ({} keys, {} clients)",
+                    MCP_ROUTING_CONFIG_PATH,
+                    config.keys.len(),
+                    config.clients.len()
+                );
+                config
             }
-            if accept_str.contains("application/x-ndjson") {
-                return ClientCapability::Streaming;
+            Err(e) => {
+                warn!("Invalid MCP routing config {}: {}", MCP_ROUTING_CONFIG_PATH, e);
+                Self::default()
             }
         }
     }
-    
-    // Check User-Agent for known clients
-    if let Some(ua) = headers.get("user-agent") {
-        if let Ok(ua_str) = ua.to_str() {
-            if ua_str.contains("Claude") || ua_str.contains("cursor") {
-                return ClientCapability::Compact;
+
+    fn client_mode(&self, client_name: &str) -> Option<McpMode> {
+        let name = client_name.to_lowercase();
+        // Longest match wins so "claude-code" can override "claude"
+        self.clients
+            .iter()
+            .filter(|(pattern, _)| name.contains(pattern.as_str()))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, mode)| *mode)
+    }
+}
+
+/// Name of the API key the current request authenticated with
+fn caller_key() -> Option<String> {
+    audit::current_identity().and_then(|identity| identity.api_key)
+}
+
+/// Outcome of negotiation, remembered per session
+#[derive(Debug, Clone)]
+pub struct Negotiation {
+    pub mode: McpMode,
+    pub profile: Option<String>,
+    pub reason: String,
+    /// Header or key decisions are not overridden by `clientInfo`
+    explicit: bool,
+}
+
+impl Negotiation {
+    /// Negotiate from request headers, the authenticated API key's name and,
+    /// for `initialize`, the client info. A key bound to a tool profile only
+    /// gets the modes the profile applies to; asking for another is an error.
+    fn detect(
+        config: &RoutingConfig,
+        headers: &HeaderMap,
+        api_key: Option<&str>,
+        client_name: Option<&str>,
+    ) -> Result<Self, String> {
+        let key_route = api_key.and_then(|name| config.keys.get(name).cloned());
+        let profile = key_route.as_ref().and_then(|r| r.profile.clone());
+        let covered = |mode: McpMode| profile.is_none() || mode != McpMode::Agents;
+        let refuse = |mode: McpMode| {
+            format!(
+                "API key '{}' is bound to tool profile '{}', which does not apply to {} mode",
+                api_key.unwrap_or_default(),
+                profile.as_deref().unwrap_or_default(),
+                mode.as_str()
+            )
+        };
+
+        if let Some(mode) = headers
+            .get("x-mcp-mode")
+            .and_then(|v| v.to_str().ok())
+            .and_then(McpMode::parse)
+        {
+            if !covered(mode) {
+                return Err(refuse(mode));
             }
-            if ua_str.contains("critical-agent") {
-                return ClientCapability::Agents;
+            return Ok(Self { mode, profile, reason: "x-mcp-mode header".into(), explicit: true });
+        }
+
+        if let Some(mode) = key_route.as_ref().and_then(|r| r.mode) {
+            if !covered(mode) {
+                return Err(refuse(mode));
             }
+            return Ok(Self { mode, profile, reason: "api key config".into(), explicit: true });
         }
-    }
-    
-    // Check custom header
-    if let Some(mode) = headers.get("x-mcp-mode") {
-        if let Ok(mode_str) = mode.to_str() {
-            return match mode_str {
-                "sse" => ClientCapability::SSE,
-                "streaming" => ClientCapability::Streaming,
-                "compact" => ClientCapability::Compact,
-                "agents" => ClientCapability::Agents,
-                _ => ClientCapability::JsonRpc,
-            };
+
+        if let Some(name) = client_name {
+            if let Some(mode) = config.client_mode(name).filter(|mode| covered(*mode)) {
+                return Ok(Self { mode, profile, reason: format!("clientInfo '{}'", name), explicit: false });
+            }
         }
+
+        let mode = Some(config.default_mode).filter(|mode| covered(*mode)).unwrap_or(McpMode::Full);
+        Ok(Self { mode, profile, reason: "default".into(), explicit: false })
     }
-    
-    ClientCapability::JsonRpc
 }
 
-/// Smart router handler - GET for SSE, POST for messages
-pub async fn smart_mcp_handler(
+/// Negotiated modes keyed by SSE session id or `Mcp-Session-Id`
+#[derive(Default)]
+struct NegotiatedSessions {
+    entries: RwLock<HashMap<String, (Negotiation, Instant)>>,
+}
+
+impl NegotiatedSessions {
+    fn get(&self, id: &str) -> Option<Negotiation> {
+        let mut entries = self.entries.write().unwrap();
+        let entry = entries.get_mut(id)?;
+        entry.1 = Instant::now();
+        Some(entry.0.clone())
+    }
+
+    fn insert(&self, id: String, negotiation: Negotiation) {
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|_, (_, seen)| seen.elapsed() < NEGOTIATION_TTL);
+        entries.insert(id, (negotiation, Instant::now()));
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ROUTING_CONFIG: RoutingConfig = RoutingConfig::load();
+    static ref NEGOTIATED: NegotiatedSessions = NegotiatedSessions::default();
+}
+
+/// Mode for a client identified only by `initialize` (e.g. over stdio):
+/// the `clients` config, then the default
+pub fn mode_for_client(client_name: Option<&str>) -> McpMode {
+    client_name
+        .and_then(|name| ROUTING_CONFIG.client_mode(name))
+        .unwrap_or(ROUTING_CONFIG.default_mode)
+}
+
+/// GET /mcp - open an SSE session on the negotiated surface
+pub async fn smart_sse_handler(headers: HeaderMap) -> Response {
+    if !accepts(&headers, "text/event-stream") {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "GET /mcp opens an SSE stream; send Accept: text/event-stream or POST JSON-RPC",
+        )
+            .into_response();
+    }
+
+    let negotiation = match Negotiation::detect(&ROUTING_CONFIG, &headers, caller_key().as_deref(), None) {
+        Ok(negotiation) => negotiation,
+        Err(e) => return error_response(StatusCode::FORBIDDEN, -32600, e),
+    };
+    let (session, session_stream) = MCP_SESSIONS.open();
+
+    info!(
+        "MCP negotiated {} mode over SSE for session {} ({})",
+        negotiation.mode.as_str(),
+        session.id,
+        negotiation.reason
+    );
+    let mode = negotiation.mode;
+    NEGOTIATED.insert(session.id.clone(), negotiation);
+
+    let post_url = mcp_session::endpoint_url(&headers, "/mcp", &session);
+    let endpoint_event = Event::default().event("endpoint").data(&post_url);
+    let stream = stream::once(async move { Ok(endpoint_event) }).chain(session_stream);
+
+    let sse = Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("ping"),
+    );
+    with_mode_header(sse.into_response(), mode)
+}
+
+/// POST /mcp - JSON-RPC on the negotiated surface
+pub async fn smart_message_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Query(query): Query<SessionQuery>,
     headers: HeaderMap,
-    request: Request,
+    body: Bytes,
 ) -> Response {
-    let capability = detect_client_capability(&headers);
-    
-    match capability {
-        ClientCapability::SSE => {
-            // Route to SSE handler
-            crate::mcp::mcp_sse_handler(State(crate::mcp::get_app_state()), headers).await
+    let peek: Value = match parse(&body) {
+        Ok(value) => value,
+        Err(e) => return parse_error(e),
+    };
+    let session = MCP_SESSIONS.from_query(&query);
+    if mcp_session::route_response(session.as_ref(), &peek) {
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    // A JSON-RPC batch is dispatched message by message on one surface
+    let batch = peek.as_array().cloned();
+    if matches!(&batch, Some(items) if items.is_empty()) {
+        return error_response(StatusCode::BAD_REQUEST, -32600, "Invalid request: empty batch".to_string());
+    }
+    let messages = batch.clone().unwrap_or_else(|| vec![peek.clone()]);
+    let initialize = messages.iter().find(|m| method_of(m) == "initialize");
+    let method = if batch.is_some() { "batch" } else { method_of(&peek) };
+
+    let sse_only = accepts(&headers, "text/event-stream") && !accepts(&headers, "application/json");
+    let session_key = query.session_id.clone().or_else(|| {
+        headers
+            .get("mcp-session-id")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+    });
+
+    let client_name = initialize
+        .and_then(|m| m.get("params"))
+        .and_then(|p| p.get("clientInfo"))
+        .and_then(|c| c.get("name"))
+        .and_then(|n| n.as_str());
+
+    let previous = session_key.as_deref().and_then(|id| NEGOTIATED.get(id));
+    let detected = match Negotiation::detect(&ROUTING_CONFIG, &headers, caller_key().as_deref(), client_name) {
+        Ok(negotiation) => negotiation,
+        Err(e) => return error_response(StatusCode::FORBIDDEN, -32600, e),
+    };
+    let negotiation = match previous {
+        // clientInfo may still refine a session opened without hints
+        Some(prev) if !detected.explicit && (prev.explicit || client_name.is_none()) => prev,
+        _ => detected,
+    };
+
+    // Remember the decision: refresh SSE sessions, mint ids for plain HTTP
+    let mut new_session_id = None;
+    match &session_key {
+        Some(id) if initialize.is_some() => {
+            info!(
+                "MCP negotiated {} mode for session {} ({})",
+                negotiation.mode.as_str(),
+                id,
+                negotiation.reason
+            );
+            NEGOTIATED.insert(id.clone(), negotiation.clone());
         }
-        ClientCapability::Streaming => {
-            // Route to streaming handler (same as SSE for now)
-            crate::mcp::mcp_sse_handler(State(crate::mcp::get_app_state()), headers).await
+        Some(_) => {}
+        None if initialize.is_some() => {
+            let id = uuid::Uuid::new_v4().to_string();
+            info!(
+                "MCP negotiated {} mode for HTTP session {} ({})",
+                negotiation.mode.as_str(),
+                id,
+                negotiation.reason
+            );
+            NEGOTIATED.insert(id.clone(), negotiation.clone());
+            new_session_id = Some(id);
         }
-        ClientCapability::Compact => {
-            // Route to compact mode
-            if request.method() == axum::http::Method::GET {
-                crate::mcp_compact::mcp_compact_sse_handler(headers).await
-            } else {
-                crate::mcp_compact::mcp_compact_message_handler(
-                    axum::extract::Json(serde_json::Value::Null)
-                ).await.into_response()
+        None => debug!(
+            "MCP {} routed to {} mode ({})",
+            method,
+            negotiation.mode.as_str(),
+            negotiation.reason
+        ),
+    }
+
+    let profile = match negotiation.profile.as_deref() {
+        Some(name) => match ToolProfile::load(name).await {
+            Some(p) => Some(p),
+            None => {
+                warn!("MCP routing references unknown tool profile: {}", name);
+                None
             }
+        },
+        None => None,
+    };
+
+    let mut responses = Vec::new();
+    for message in messages {
+        // Batches may carry replies to server-initiated requests too
+        if mcp_session::route_response(session.as_ref(), &message) {
+            continue;
         }
-        ClientCapability::Agents => {
-            // Route to critical agents
-            if request.method() == axum::http::Method::GET {
-                crate::mcp_agents::mcp_agents_sse_handler_stateless(headers).await
-            } else {
-                crate::mcp_agents::mcp_agents_message_handler_stateless(
-                    axum::extract::Json(serde_json::Value::Null)
-                ).await.into_response()
+        let is_request = message.get("id").map(|id| !id.is_null()).unwrap_or(false);
+        let id = message.get("id").cloned().unwrap_or_else(|| json!(null));
+
+        match dispatch(&state, negotiation.mode, profile.as_ref(), session.as_ref(), message).await {
+            Ok(response) if is_request => responses.push(response),
+            Ok(_) => {}
+            Err(e) if batch.is_none() => {
+                return error_response(StatusCode::BAD_REQUEST, -32600, format!("Invalid request: {}", e));
             }
+            Err(e) => responses.push(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32600, "message": format!("Invalid request: {}", e) }
+            })),
         }
-        ClientCapability::JsonRpc => {
-            // Route to standard JSON-RPC
-            crate::mcp::jsonrpc_handler(
-                State(crate::mcp::get_app_state()),
-                axum::extract::Json(serde_json::Value::Null)
-            ).await.into_response()
+    }
+
+    let response = match (batch.is_some(), responses.len()) {
+        // Only notifications (and replies): nothing to answer
+        (_, 0) => StatusCode::ACCEPTED.into_response(),
+        (true, _) => reply(&json!(responses), session.as_ref(), sse_only),
+        (false, _) => reply(&responses[0], session.as_ref(), sse_only),
+    };
+
+    let mut response = with_mode_header(response, negotiation.mode);
+    if let Some(id) = new_session_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response.headers_mut().insert("mcp-session-id", id);
+    }
+    response
+}
+
+/// Run one JSON-RPC message through the dispatcher for `mode`
+async fn dispatch(
+    state: &Arc<AppState>,
+    mode: McpMode,
+    profile: Option<&ToolProfile>,
+    session: Option<&Arc<McpSession>>,
+    message: Value,
+) -> Result<Value, String> {
+    let response = match mode {
+        McpMode::Full => {
+            let request: crate::mcp::McpRequest =
+                simd_json::serde::from_owned_value(message).map_err(|e| e.to_string())?;
+            let response = mcp_logging::with_session(
+                session,
+                crate::mcp::process_request(state, request, profile, session),
+            )
+            .await;
+            simd_json::serde::to_owned_value(&response)
+        }
+        McpMode::Compact => {
+            let request: crate::mcp_compact::JsonRpcRequest =
+                simd_json::serde::from_owned_value(message).map_err(|e| e.to_string())?;
+            let response = mcp_logging::with_session(
+                session,
+                crate::mcp_compact::dispatch(state, &request, profile, session),
+            )
+            .await;
+            simd_json::serde::to_owned_value(&response)
         }
+        McpMode::Agents => {
+            let request: crate::mcp_agents::JsonRpcRequest =
+                simd_json::serde::from_owned_value(message).map_err(|e| e.to_string())?;
+            let agents = crate::mcp_agents::global_state();
+            let response = crate::mcp_agents::dispatch(&agents, &request, session).await;
+            simd_json::serde::to_owned_value(&response)
+        }
+    };
+    response.map_err(|e| e.to_string())
+}
+
+fn method_of(message: &Value) -> &str {
+    message.get("method").and_then(|m| m.as_str()).unwrap_or("")
+}
+
+/// Reply on the session's event stream for clients of an SSE session,
+/// which only get the POST acknowledged; otherwise in the body
+fn reply<T: Serialize>(response: &T, session: Option<&Arc<McpSession>>, sse_only: bool) -> Response {
+    if let Some(session) = session {
+        session.send(response);
+        return StatusCode::ACCEPTED.into_response();
+    }
+    if !sse_only {
+        return Json(response).into_response();
+    }
+
+    let data = simd_json::to_string(response).unwrap_or_default();
+    let event = Event::default().event("message").data(data);
+    Sse::new(stream::once(async move { Ok::<_, std::convert::Infallible>(event) })).into_response()
+}
+
+/// Whether the Accept header allows `media_type` (a missing header allows anything)
+fn accepts(headers: &HeaderMap, media_type: &str) -> bool {
+    match headers.get("accept").and_then(|v| v.to_str().ok()) {
+        Some(accept) => accept.contains(media_type) || accept.contains("*/*"),
+        None => true,
     }
 }
 
+fn with_mode_header(mut response: Response, mode: McpMode) -> Response {
+    response
+        .headers_mut()
+        .insert("x-mcp-mode", HeaderValue::from_static(mode.as_str()));
+    response
+}
+
+fn parse<T: DeserializeOwned>(body: &Bytes) -> Result<T, String> {
+    let mut buf = body.to_vec();
+    simd_json::from_slice(&mut buf).map_err(|e| e.to_string())
+}
+
+fn parse_error(message: String) -> Response {
+    error_response(StatusCode::BAD_REQUEST, -32700, format!("Parse error: {}", message))
+}
+
+fn error_response(status: StatusCode, code: i32, message: String) -> Response {
+    (
+        status,
+        Json(json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": code, "message": message }
+        })),
+    )
+        .into_response()
+}
+
+/// Negotiation hints advertised in `/.well-known/mcp.json`
+pub fn negotiation_info() -> Value {
+    json!({
+        "modes": ["full", "compact", "agents"],
+        "default_mode": ROUTING_CONFIG.default_mode.as_str(),
+        "order": ["x-mcp-mode header", "api key config", "session", "initialize clientInfo.name", "default"],
+        "header": "x-mcp-mode",
+        "session_header": "Mcp-Session-Id",
+        "transports": {
+            "sse": "GET with Accept: text/event-stream",
+            "http": "POST application/json"
+        }
+    })
+}
+
 /// Create smart router - single entry point
 pub fn create_smart_mcp_router() -> Router {
-    Router::new()
-        .route("/", get(smart_mcp_handler).post(smart_mcp_handler))
-        .route("/message", post(smart_mcp_handler))
+    Router::new().route("/", get(smart_sse_handler).post(smart_message_handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiation_precedence() {
+        let mut config = RoutingConfig::default();
+        config.keys.insert(
+            "ci-runner".to_string(),
+            KeyRoute { mode: Some(McpMode::Compact), profile: Some("dev".to_string()) },
+        );
+
+        let mut headers = HeaderMap::new();
+        let detected = Negotiation::detect(&config, &headers, None, Some("Claude Desktop")).unwrap();
+        assert_eq!(detected.mode, McpMode::Compact);
+        assert!(!detected.explicit);
+
+        // An unauthenticated token naming a configured key selects nothing
+        headers.insert("x-api-key", HeaderValue::from_static("ci-runner"));
+        let detected = Negotiation::detect(&config, &headers, None, Some("critical-agent")).unwrap();
+        assert_eq!(detected.mode, McpMode::Agents);
+
+        let detected = Negotiation::detect(&config, &headers, Some("ci-runner"), Some("critical-agent")).unwrap();
+        assert_eq!(detected.mode, McpMode::Compact);
+        assert_eq!(detected.profile.as_deref(), Some("dev"));
+
+        headers.insert("x-mcp-mode", HeaderValue::from_static("full"));
+        let detected = Negotiation::detect(&config, &headers, Some("ci-runner"), None).unwrap();
+        assert_eq!(detected.mode, McpMode::Full);
+        assert_eq!(detected.reason, "x-mcp-mode header");
+    }
+
+    #[test]
+    fn test_profile_bound_key_cannot_leave_its_profile() {
+        let mut config = RoutingConfig::default();
+        config.keys.insert("ci-runner".to_string(), KeyRoute { mode: None, profile: Some("dev".to_string()) });
+
+        // The profile does not apply to agents mode, so the key may not pick it
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mcp-mode", HeaderValue::from_static("agents"));
+        assert!(Negotiation::detect(&config, &headers, Some("ci-runner"), None).is_err());
+        assert!(Negotiation::detect(&config, &headers, None, None).is_ok());
+
+        let detected = Negotiation::detect(&config, &HeaderMap::new(), Some("ci-runner"), Some("critical-agent")).unwrap();
+        assert_eq!(detected.mode, McpMode::Full);
+    }
+
+    #[test]
+    fn test_accept_negotiation() {
+        let mut headers = HeaderMap::new();
+        assert!(accepts(&headers, "text/event-stream"));
+
+        headers.insert("accept", HeaderValue::from_static("text/event-stream"));
+        assert!(accepts(&headers, "text/event-stream"));
+        assert!(!accepts(&headers, "application/json"));
+    }
+
+    #[test]
+    fn test_unknown_client_uses_default() {
+        let config = RoutingConfig::default();
+        let detected = Negotiation::detect(&config, &HeaderMap::new(), None, Some("my-script")).unwrap();
+        assert_eq!(detected.mode, McpMode::Full);
+        assert_eq!(detected.reason, "default");
+    }
 }
@@ -0,0 +1,279 @@
+//! MCP Stdio Transport - `op-web-server --mcp-stdio`
+//!
+//! Serves the same dispatchers as `/mcp` over newline-delimited JSON-RPC
+//! on stdin/stdout, so desktop clients can launch a local process instead
+//! of talking to a TCP port. Logs go to stderr.
+//!
+//! ```text
+//! op-web-server --mcp-stdio [--mode full|compact|agents] [--profile NAME]
+//! ```
+//!
+//! Without `--mode` the surface is picked at `initialize` from
+//! `clientInfo.name`, like the negotiated HTTP entry point.
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tracing::{debug, info, warn};
+
+use crate::audit::{self, ClientIdentity};
+use crate::mcp_logging;
+use crate::mcp_session::{self, McpSession, MCP_SESSIONS};
+use crate::mcp_smart_router::{self, McpMode};
+use crate::state::AppState;
+use crate::tool_profiles::ToolProfile;
+
+/// Command line options for stdio mode
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StdioOptions {
+    pub mode: Option<McpMode>,
+    pub profile: Option<String>,
+}
+
+impl StdioOptions {
+    /// Parse process arguments; `Ok(None)` when `--mcp-stdio` is absent
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Self>> {
+        let mut stdio = false;
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            match flag.as_str() {
+                "--mcp-stdio" => stdio = true,
+                "--mode" => {
+                    let value = inline.or_else(|| args.next()).ok_or_else(|| anyhow!("--mode needs a value"))?;
+                    let mode = McpMode::parse(&value)
+                        .ok_or_else(|| anyhow!("Invalid --mode '{}' (full, compact or agents)", value))?;
+                    options.mode = Some(mode);
+                }
+                "--profile" => {
+                    let value = inline.or_else(|| args.next()).ok_or_else(|| anyhow!("--profile needs a value"))?;
+                    options.profile = Some(value);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(stdio.then_some(options))
+    }
+
+    /// Arguments that reproduce these options (for generated client configs)
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec!["--mcp-stdio".to_string()];
+        if let Some(mode) = self.mode {
+            args.push("--mode".to_string());
+            args.push(mode.as_str().to_string());
+        }
+        if let Some(profile) = &self.profile {
+            args.push("--profile".to_string());
+            args.push(profile.clone());
+        }
+        args
+    }
+}
+
+/// Serve MCP over stdin/stdout until stdin closes
+pub async fn serve(state: Arc<AppState>, options: StdioOptions) -> Result<()> {
+    let profile = match &options.profile {
+        Some(name) => Some(Arc::new(
+            ToolProfile::load(name)
+                .await
+                .ok_or_else(|| anyhow!("Unknown tool profile: {}", name))?,
+        )),
+        None => None,
+    };
+
+    let (session, mut outgoing) = MCP_SESSIONS.open_raw();
+    let mode = RwLock::new(options.mode);
+    info!(
+        "MCP stdio transport ready (mode: {}, profile: {})",
+        options.mode.map(|m| m.as_str()).unwrap_or("negotiated"),
+        options.profile.as_deref().unwrap_or("none")
+    );
+
+    // Single writer so responses and notifications never interleave
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = outgoing.next().await {
+            if stdout.write_all(message.as_bytes()).await.is_err()
+                || stdout.write_all(b"\n").await.is_err()
+                || stdout.flush().await.is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut handlers: Vec<tokio::task::JoinHandle<()>> = Vec::new();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut raw = line;
+        let message = match unsafe { simd_json::from_str::<Value>(&mut raw) } {
+            Ok(message) => message,
+            Err(e) => {
+                session.send(&json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": -32700, "message": format!("Parse error: {}", e) }
+                }));
+                continue;
+            }
+        };
+
+        // Negotiate in input order and finish `initialize` before reading on,
+        // so later messages see the client's capabilities; run everything else
+        // on its own task so notifications/cancelled can reach a call that is
+        // still in flight
+        let mode = resolve_mode(&mode, &message);
+        if message.get("method").and_then(|m| m.as_str()) == Some("initialize") {
+            dispatch(state.clone(), session.clone(), mode, profile.clone(), message).await;
+            continue;
+        }
+        handlers.retain(|handler| !handler.is_finished());
+        handlers.push(tokio::spawn(dispatch(state.clone(), session.clone(), mode, profile.clone(), message)));
+    }
+
+    info!("MCP stdio input closed, finishing {} in-flight requests", handlers.len());
+    for handler in handlers {
+        if let Err(e) = handler.await {
+            warn!("MCP stdio handler failed: {}", e);
+        }
+    }
+    // Without the session the writer drains what is queued and stops
+    MCP_SESSIONS.close(&session.id);
+    drop(session);
+    if let Err(e) = writer.await {
+        warn!("MCP stdio writer failed: {}", e);
+    }
+    Ok(())
+}
+
+/// Handle one message as the local stdio client
+async fn dispatch(
+    state: Arc<AppState>,
+    session: Arc<McpSession>,
+    mode: McpMode,
+    profile: Option<Arc<ToolProfile>>,
+    message: Value,
+) {
+    let handle = mcp_logging::with_session(
+        Some(&session),
+        handle_message(&state, &session, mode, profile.as_deref(), message),
+    );
+    audit::with_identity(ClientIdentity::local(), handle).await;
+}
+
+/// The fixed `--mode`, or the one negotiated from `initialize`
+fn resolve_mode(mode: &RwLock<Option<McpMode>>, message: &Value) -> McpMode {
+    if let Some(mode) = *mode.read().unwrap() {
+        return mode;
+    }
+    if message.get("method").and_then(|m| m.as_str()) != Some("initialize") {
+        return mcp_smart_router::mode_for_client(None);
+    }
+
+    let client_name = message
+        .get("params")
+        .and_then(|p| p.get("clientInfo"))
+        .and_then(|c| c.get("name"))
+        .and_then(|n| n.as_str());
+    let negotiated = mcp_smart_router::mode_for_client(client_name);
+    info!(
+        "MCP stdio negotiated {} mode for client {}",
+        negotiated.as_str(),
+        client_name.unwrap_or("unknown")
+    );
+    *mode.write().unwrap() = Some(negotiated);
+    negotiated
+}
+
+async fn handle_message(
+    state: &Arc<AppState>,
+    session: &Arc<McpSession>,
+    mode: McpMode,
+    profile: Option<&ToolProfile>,
+    message: Value,
+) {
+    // Replies to server-initiated requests (sampling) are not dispatched
+    if mcp_session::route_response(Some(session), &message) {
+        return;
+    }
+
+    let is_request = message.get("id").map(|id| !id.is_null()).unwrap_or(false);
+    let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
+    debug!("MCP stdio {} ({} mode)", method, mode.as_str());
+
+    match mode {
+        McpMode::Full => match simd_json::serde::from_owned_value::<crate::mcp::McpRequest>(message) {
+            Ok(request) => {
+                let response = crate::mcp::process_request(state, request, profile, Some(session)).await;
+                session_reply(session, is_request, &response);
+            }
+            Err(e) => invalid_request(session, e),
+        },
+        McpMode::Compact => match simd_json::serde::from_owned_value::<crate::mcp_compact::JsonRpcRequest>(message) {
+            Ok(request) => {
+                let response = crate::mcp_compact::dispatch(state, &request, profile, Some(session)).await;
+                session_reply(session, is_request, &response);
+            }
+            Err(e) => invalid_request(session, e),
+        },
+        McpMode::Agents => match simd_json::serde::from_owned_value::<crate::mcp_agents::JsonRpcRequest>(message) {
+            Ok(request) => {
+                let agents = crate::mcp_agents::global_state();
+                let response = crate::mcp_agents::dispatch(&agents, &request, Some(session)).await;
+                session_reply(session, is_request, &response);
+            }
+            Err(e) => invalid_request(session, e),
+        },
+    }
+}
+
+fn invalid_request(session: &McpSession, error: impl std::fmt::Display) {
+    warn!("Invalid MCP stdio request: {}", error);
+    session.send(&json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": -32600, "message": format!("Invalid request: {}", error) }
+    }));
+}
+
+fn session_reply<T: serde::Serialize>(session: &McpSession, is_request: bool, response: &T) {
+    if is_request {
+        session.send(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_stdio_args() {
+        assert_eq!(StdioOptions::from_args(args(&["op-web-server"])).unwrap(), None);
+
+        let options = StdioOptions::from_args(args(&["op-web-server", "--mcp-stdio", "--mode", "compact", "--profile=dev"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.mode, Some(McpMode::Compact));
+        assert_eq!(options.profile.as_deref(), Some("dev"));
+        assert_eq!(options.to_args(), args(&["--mcp-stdio", "--mode", "compact", "--profile", "dev"]));
+
+        assert!(StdioOptions::from_args(args(&["--mcp-stdio", "--mode", "huge"])).is_err());
+    }
+}
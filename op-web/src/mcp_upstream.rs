@@ -0,0 +1,743 @@
+//! MCP Upstreams - Import tools from external MCP servers
+//!
+//! op-web acts as an MCP *client* for the servers listed in
+//! `/etc/op-dbus/mcp-servers.json`:
+//!
+//! ```json
+//! {
+//!   "servers": {
+//!     "gh":   { "command": "github-mcp-server", "args": ["stdio"], "env": { "GITHUB_TOKEN": "..." } },
+//!     "docs": { "url": "http://10.0.0.5:3000/sse" }
+//!   }
+//! }
+//! ```
+//!
+//! Each server's tools are registered in the shared `ToolRegistry` as
+//! `{prefix}__{tool}` (prefix defaults to the server name), so they show up
+//! in compact-mode search and the chat orchestrator like any other tool.
+//! Calls are proxied as `tools/call`, `notifications/tools/list_changed`
+//! triggers a re-sync, and dropped connections are retried with backoff.
+//! A server's tools are withdrawn while it is disconnected; every change is
+//! announced to MCP clients as `notifications/tools/list_changed`.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use op_tools::tool::Tool;
+use op_tools::ToolRegistry;
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::mcp_session::MCP_SESSIONS;
+
+const MCP_SERVERS_CONFIG_PATH: &str = "/etc/op-dbus/mcp-servers.json";
+
+/// Separator between the server prefix and the upstream tool name
+pub const NAMESPACE_SEPARATOR: &str = "__";
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+/// One upstream server entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamConfig {
+    /// Command to spawn (stdio transport)
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// SSE endpoint URL (SSE transport)
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Tool name prefix, defaults to the server name
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+/// Contents of the upstream config file
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpstreamsConfig {
+    #[serde(default, alias = "mcpServers")]
+    pub servers: HashMap<String, UpstreamConfig>,
+}
+
+impl UpstreamsConfig {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(MCP_SERVERS_CONFIG_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<UpstreamsConfig>(&mut raw) } {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Invalid upstream MCP config {}: {}", MCP_SERVERS_CONFIG_PATH, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Connection state of an upstream server
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamStatus {
+    pub name: String,
+    pub prefix: String,
+    pub transport: &'static str,
+    pub connected: bool,
+    pub tools: usize,
+    pub last_error: Option<String>,
+}
+
+/// A configured upstream server and its live connection (if any)
+pub struct Upstream {
+    name: String,
+    prefix: String,
+    config: UpstreamConfig,
+    client: RwLock<Option<Arc<UpstreamClient>>>,
+    /// Registry names currently imported from this server
+    tools: Mutex<HashSet<String>>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Upstream {
+    fn client(&self) -> Option<Arc<UpstreamClient>> {
+        self.client.read().unwrap().clone()
+    }
+
+    fn set_client(&self, client: Option<Arc<UpstreamClient>>) {
+        *self.client.write().unwrap() = client;
+    }
+
+    fn set_error(&self, error: impl ToString) {
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+    }
+
+    pub fn status(&self) -> UpstreamStatus {
+        UpstreamStatus {
+            name: self.name.clone(),
+            prefix: self.prefix.clone(),
+            transport: if self.config.url.is_some() { "sse" } else { "stdio" },
+            connected: self.client().is_some(),
+            tools: self.tools.lock().unwrap().len(),
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Owns all upstream connections
+#[derive(Default)]
+pub struct UpstreamManager {
+    upstreams: Vec<Arc<Upstream>>,
+}
+
+impl UpstreamManager {
+    /// Load the config and start a connection task per enabled server
+    pub fn start(registry: Arc<ToolRegistry>) -> Arc<Self> {
+        let config = UpstreamsConfig::load();
+        let mut upstreams = Vec::new();
+
+        for (name, server) in config.servers {
+            if !server.enabled {
+                continue;
+            }
+            if server.command.is_none() && server.url.is_none() {
+                warn!("Upstream MCP server '{}' has neither command nor url", name);
+                continue;
+            }
+
+            let upstream = Arc::new(Upstream {
+                prefix: server.prefix.clone().unwrap_or_else(|| name.clone()),
+                name,
+                config: server,
+                client: RwLock::new(None),
+                tools: Mutex::new(HashSet::new()),
+                last_error: Mutex::new(None),
+            });
+            tokio::spawn(run(upstream.clone(), registry.clone()));
+            upstreams.push(upstream);
+        }
+
+        if !upstreams.is_empty() {
+            info!("Starting {} upstream MCP server(s)", upstreams.len());
+        }
+        Arc::new(Self { upstreams })
+    }
+
+    pub fn status(&self) -> Vec<UpstreamStatus> {
+        self.upstreams.iter().map(|u| u.status()).collect()
+    }
+}
+
+/// Connect, import tools and follow changes until the connection drops; repeat
+async fn run(upstream: Arc<Upstream>, registry: Arc<ToolRegistry>) {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        match Connection::open(&upstream).await {
+            Ok(mut connection) => match initialize(&connection.client).await {
+                Ok(server_name) => {
+                    info!("Upstream MCP server '{}' connected ({})", upstream.name, server_name);
+                    upstream.set_client(Some(connection.client.clone()));
+                    backoff = MIN_BACKOFF;
+
+                    if let Err(e) = sync_tools(&upstream, &connection.client, &registry).await {
+                        warn!("Failed to import tools from '{}': {}", upstream.name, e);
+                        upstream.set_error(e);
+                    }
+
+                    while let Some(notification) = connection.incoming.recv().await {
+                        let method = notification.get("method").and_then(|m| m.as_str()).unwrap_or("");
+                        if method == "notifications/tools/list_changed" {
+                            info!("Upstream MCP server '{}' changed its tools", upstream.name);
+                            if let Err(e) = sync_tools(&upstream, &connection.client, &registry).await {
+                                warn!("Failed to re-import tools from '{}': {}", upstream.name, e);
+                                upstream.set_error(e);
+                            }
+                        } else {
+                            debug!("Upstream '{}' notification: {}", upstream.name, method);
+                        }
+                    }
+
+                    warn!("Upstream MCP server '{}' disconnected", upstream.name);
+                    upstream.set_error("disconnected");
+                    upstream.set_client(None);
+                    withdraw_tools(&upstream, &registry).await;
+                }
+                Err(e) => {
+                    warn!("Upstream MCP server '{}' failed to initialize: {}", upstream.name, e);
+                    upstream.set_error(e);
+                }
+            },
+            Err(e) => {
+                warn!("Failed to connect to upstream MCP server '{}': {}", upstream.name, e);
+                upstream.set_error(e);
+            }
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn initialize(client: &UpstreamClient) -> Result<String> {
+    let result = client
+        .request(
+            "initialize",
+            json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": {
+                    "name": "op-web",
+                    "version": env!("CARGO_PKG_VERSION")
+                }
+            }),
+        )
+        .await?;
+    client.notify("notifications/initialized", json!({})).await?;
+
+    Ok(result
+        .get("serverInfo")
+        .and_then(|s| s.get("name"))
+        .and_then(|n| n.as_str())
+        .unwrap_or("unknown")
+        .to_string())
+}
+
+/// Bring the registry in line with the upstream's current `tools/list`
+async fn sync_tools(upstream: &Arc<Upstream>, client: &UpstreamClient, registry: &ToolRegistry) -> Result<()> {
+    let mut remote = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let params = match &cursor {
+            Some(c) => json!({ "cursor": c.as_str() }),
+            None => json!({}),
+        };
+        let page = client.request("tools/list", params).await?;
+        if let Some(tools) = page.get("tools").and_then(|t| t.as_array()) {
+            remote.extend(tools.iter().cloned());
+        }
+        cursor = page.get("nextCursor").and_then(|c| c.as_str()).map(String::from);
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    let previous = upstream.tools.lock().unwrap().clone();
+    let mut current = HashSet::new();
+
+    for tool in remote {
+        let Some(remote_name) = tool.get("name").and_then(|n| n.as_str()) else {
+            continue;
+        };
+        let name = namespaced(&upstream.prefix, remote_name);
+
+        if previous.contains(&name) {
+            // Re-register so schema/description changes take effect
+            registry.unregister_tool(&name).await;
+        } else if registry.get_definition(&name).await.is_some() {
+            warn!("Skipping upstream tool {}: name already registered", name);
+            continue;
+        }
+
+        let proxy = ProxyTool {
+            name: name.clone(),
+            remote_name: remote_name.to_string(),
+            description: format!(
+                "[{}] {}",
+                upstream.name,
+                tool.get("description").and_then(|d| d.as_str()).unwrap_or("")
+            ),
+            input_schema: tool
+                .get("inputSchema")
+                .cloned()
+                .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+            upstream: upstream.clone(),
+        };
+        registry.register_tool(Arc::new(proxy)).await?;
+        current.insert(name);
+    }
+
+    for stale in previous.difference(&current) {
+        registry.unregister_tool(stale).await;
+    }
+
+    info!("Imported {} tools from upstream MCP server '{}'", current.len(), upstream.name);
+    *upstream.tools.lock().unwrap() = current;
+    *upstream.last_error.lock().unwrap() = None;
+    MCP_SESSIONS.notify_all("notifications/tools/list_changed", json!({}));
+    Ok(())
+}
+
+/// Remove a disconnected upstream's tools until it reconnects
+async fn withdraw_tools(upstream: &Upstream, registry: &ToolRegistry) {
+    let imported = std::mem::take(&mut *upstream.tools.lock().unwrap());
+    if imported.is_empty() {
+        return;
+    }
+    for name in &imported {
+        registry.unregister_tool(name).await;
+    }
+    info!("Withdrew {} tools of upstream MCP server '{}'", imported.len(), upstream.name);
+    MCP_SESSIONS.notify_all("notifications/tools/list_changed", json!({}));
+}
+
+/// Registry name for an upstream tool
+pub fn namespaced(prefix: &str, tool: &str) -> String {
+    format!("{}{}{}", prefix, NAMESPACE_SEPARATOR, tool)
+}
+
+/// Registry tool that forwards `execute` to the upstream server
+struct ProxyTool {
+    name: String,
+    remote_name: String,
+    description: String,
+    input_schema: Value,
+    upstream: Arc<Upstream>,
+}
+
+#[async_trait]
+impl Tool for ProxyTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn input_schema(&self) -> Value {
+        self.input_schema.clone()
+    }
+
+    fn category(&self) -> &str {
+        "upstream"
+    }
+
+    fn namespace(&self) -> &str {
+        &self.upstream.prefix
+    }
+
+    async fn execute(&self, input: Value) -> Result<Value> {
+        let client = self
+            .upstream
+            .client()
+            .ok_or_else(|| anyhow!("Upstream MCP server '{}' is not connected", self.upstream.name))?;
+
+        let result = client
+            .request("tools/call", json!({ "name": self.remote_name.as_str(), "arguments": input }))
+            .await?;
+        call_result_to_value(result).map_err(|e| anyhow!(e))
+    }
+}
+
+/// Convert an MCP `tools/call` result into a registry tool result
+fn call_result_to_value(result: Value) -> Result<Value, String> {
+    let content = result.get("content").and_then(|c| c.as_array()).cloned().unwrap_or_default();
+    let text: Vec<&str> = content
+        .iter()
+        .filter_map(|c| c.get("text").and_then(|t| t.as_str()))
+        .collect();
+
+    if result.get("isError").and_then(|e| e.as_bool()).unwrap_or(false) {
+        return Err(if text.is_empty() { "Upstream tool failed".to_string() } else { text.join("\n") });
+    }
+
+    if let Some(structured) = result.get("structuredContent") {
+        return Ok(structured.clone());
+    }
+
+    match (content.len(), text.first()) {
+        (1, Some(text)) => {
+            let mut raw = text.to_string();
+            Ok(unsafe { simd_json::from_str::<Value>(&mut raw) }.unwrap_or_else(|_| json!(*text)))
+        }
+        _ => Ok(json!({ "content": content })),
+    }
+}
+
+enum Outbound {
+    Stdio(mpsc::UnboundedSender<String>),
+    Sse { http: reqwest::Client, endpoint: String },
+}
+
+/// JSON-RPC client for one upstream connection
+struct UpstreamClient {
+    name: String,
+    outbound: Outbound,
+    pending: Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>,
+    next_id: AtomicU64,
+    timeout: Duration,
+}
+
+impl UpstreamClient {
+    async fn send(&self, message: &Value) -> Result<()> {
+        let body = simd_json::to_string(message)?;
+        match &self.outbound {
+            Outbound::Stdio(tx) => tx.send(body).map_err(|_| anyhow!("upstream process stdin closed")),
+            Outbound::Sse { http, endpoint } => {
+                http.post(endpoint)
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(e) = self.send(&message).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(result)) => result.map_err(|e| anyhow!("{} ({}): {}", self.name, method, e)),
+            Ok(Err(_)) => Err(anyhow!("Upstream MCP server '{}' disconnected", self.name)),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(anyhow!("Upstream MCP server '{}' timed out on {}", self.name, method))
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params })).await
+    }
+
+    /// Route one incoming message: responses resolve pending requests,
+    /// requests from the server get answered, notifications are returned
+    async fn handle_incoming(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned();
+        let method = message.get("method").and_then(|m| m.as_str()).map(String::from);
+
+        match (method, id) {
+            (Some(method), Some(id)) if !id.is_null() => {
+                let reply = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("Method not found: {}", method) }
+                    })
+                };
+                let _ = self.send(&reply).await;
+            }
+            (Some(_), _) => return Some(message),
+            (None, Some(id)) => {
+                let tx = id.as_u64().and_then(|id| self.pending.lock().unwrap().remove(&id))?;
+                let outcome = match message.get("error") {
+                    Some(error) => Err(error
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error")
+                        .to_string()),
+                    None => Ok(message.get("result").cloned().unwrap_or_else(|| json!({}))),
+                };
+                let _ = tx.send(outcome);
+            }
+            (None, None) => {}
+        }
+        None
+    }
+
+    fn fail_pending(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+}
+
+/// A live connection; dropping it kills the process / closes the stream
+struct Connection {
+    client: Arc<UpstreamClient>,
+    incoming: mpsc::UnboundedReceiver<Value>,
+    reader: JoinHandle<()>,
+    _child: Option<Child>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
+        self.client.fail_pending();
+    }
+}
+
+impl Connection {
+    async fn open(upstream: &Upstream) -> Result<Self> {
+        let config = &upstream.config;
+        match (&config.url, &config.command) {
+            (Some(url), _) => Self::open_sse(upstream, url).await,
+            (None, Some(command)) => Self::open_stdio(upstream, command),
+            (None, None) => Err(anyhow!("no command or url configured")),
+        }
+    }
+
+    fn client(upstream: &Upstream, outbound: Outbound) -> Arc<UpstreamClient> {
+        Arc::new(UpstreamClient {
+            name: upstream.name.clone(),
+            outbound,
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            timeout: Duration::from_secs(upstream.config.timeout_secs),
+        })
+    }
+
+    fn open_stdio(upstream: &Upstream, command: &str) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(&upstream.config.args)
+            .envs(&upstream.config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("no stdout"))?;
+        let stderr = child.stderr.take().ok_or_else(|| anyhow!("no stderr"))?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                if stdin.write_all(line.as_bytes()).await.is_err() || stdin.write_all(b"\n").await.is_err() {
+                    break;
+                }
+                let _ = stdin.flush().await;
+            }
+        });
+
+        let name = upstream.name.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("[upstream {}] {}", name, line);
+            }
+        });
+
+        // The reader owns the notification sender, so the run loop sees
+        // the channel close when the process exits
+        let client = Self::client(upstream, Outbound::Stdio(tx));
+        let (notifications, incoming) = mpsc::unbounded_channel();
+        let reader_client = client.clone();
+        let reader = tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let mut raw = line;
+                if let Ok(message) = unsafe { simd_json::from_str::<Value>(&mut raw) } {
+                    if let Some(notification) = reader_client.handle_incoming(message).await {
+                        let _ = notifications.send(notification);
+                    }
+                }
+            }
+            reader_client.fail_pending();
+        });
+
+        Ok(Self { client, incoming, reader, _child: Some(child) })
+    }
+
+    async fn open_sse(upstream: &Upstream, url: &str) -> Result<Self> {
+        let http = reqwest::Client::new();
+        let mut response = http
+            .get(url)
+            .header("Accept", "text/event-stream")
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // The first event names the POST endpoint; events that arrived in
+        // the same chunk after it are delivered once the reader starts
+        let mut parser = SseParser::default();
+        let (endpoint, early) = loop {
+            let chunk = response
+                .chunk()
+                .await?
+                .ok_or_else(|| anyhow!("stream closed before endpoint event"))?;
+            if let Some((data, rest)) = take_endpoint(parser.push(&chunk)) {
+                break (reqwest::Url::parse(url)?.join(data.trim())?.to_string(), rest);
+            }
+        };
+        debug!("Upstream '{}' POST endpoint: {}", upstream.name, endpoint);
+
+        let client = Self::client(upstream, Outbound::Sse { http, endpoint });
+        let (notifications, incoming) = mpsc::unbounded_channel();
+        let reader_client = client.clone();
+        let reader = tokio::spawn(async move {
+            let mut events = early;
+            loop {
+                for (event, data) in events {
+                    if event != "message" {
+                        continue;
+                    }
+                    let mut raw = data;
+                    if let Ok(message) = unsafe { simd_json::from_str::<Value>(&mut raw) } {
+                        if let Some(notification) = reader_client.handle_incoming(message).await {
+                            let _ = notifications.send(notification);
+                        }
+                    }
+                }
+                match response.chunk().await {
+                    Ok(Some(chunk)) => events = parser.push(&chunk),
+                    _ => break,
+                }
+            }
+            reader_client.fail_pending();
+        });
+
+        Ok(Self { client, incoming, reader, _child: None })
+    }
+}
+
+/// Split off the `endpoint` event's data and the events that followed it
+fn take_endpoint(events: Vec<(String, String)>) -> Option<(String, Vec<(String, String)>)> {
+    let mut events = events.into_iter();
+    let (_, data) = events.by_ref().find(|(event, _)| event == "endpoint")?;
+    Some((data, events.collect()))
+}
+
+/// Incremental `text/event-stream` parser yielding `(event, data)` pairs
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn push(&mut self, chunk: &[u8]) -> Vec<(String, String)> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    let event = if self.event.is_empty() { "message".to_string() } else { std::mem::take(&mut self.event) };
+                    events.push((event, self.data.join("\n")));
+                }
+                self.event.clear();
+                self.data.clear();
+            } else if let Some(value) = line.strip_prefix("event:") {
+                self.event = value.trim().to_string();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"event: endpoint\r\ndata: /mess").is_empty());
+        let events = parser.push(b"age?sessionId=1\r\n\r\n: ping\n\ndata: {\"id\":1}\n\n");
+        assert_eq!(
+            events,
+            vec![
+                ("endpoint".to_string(), "/message?sessionId=1".to_string()),
+                ("message".to_string(), "{\"id\":1}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_after_endpoint_are_kept() {
+        let mut parser = SseParser::default();
+        let events = parser.push(b"event: endpoint\ndata: /message\n\ndata: {\"id\":1}\n\n");
+        let (endpoint, rest) = take_endpoint(events).unwrap();
+        assert_eq!(endpoint, "/message");
+        assert_eq!(rest, vec![("message".to_string(), "{\"id\":1}".to_string())]);
+        assert!(take_endpoint(vec![("message".to_string(), "{}".to_string())]).is_none());
+    }
+
+    #[test]
+    fn test_call_result_conversion() {
+        let ok = json!({ "content": [{ "type": "text", "text": "{\"number\": 42}" }] });
+        assert_eq!(call_result_to_value(ok).unwrap(), json!({ "number": 42 }));
+
+        let err = json!({ "content": [{ "type": "text", "text": "not found" }], "isError": true });
+        assert_eq!(call_result_to_value(err).unwrap_err(), "not found");
+
+        assert_eq!(namespaced("gh", "create_issue"), "gh__create_issue");
+    }
+}
@@ -1,65 +1,37 @@
 //! Security middleware for op-web
 //!
-//! Provides IP-based security zones with API key bypass support.
+//! Provides IP-based security zones; managed API keys (see
+//! [`crate::api_keys`]) lift the IP restriction within their scopes, and the
+//! access policy (see [`crate::access_policy`]) decides which routes a zone
+//! may reach.
 
 use axum::{
     extract::{ConnectInfo, Request},
-    http::HeaderMap,
+    http::{HeaderMap, Method, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Json, Response},
 };
+use chrono::Utc;
 use op_core::security::AccessZone;
-use std::net::SocketAddr;
-use tracing::{debug, info};
-
-/// API keys that bypass IP restrictions and grant TrustedMesh access
-const BYPASS_API_KEYS: &[&str] = &[
-    "4f8c2b5d-9a1e-4b7c-8d2f-3a6b5c9e4d1f", // Primary MCP access key
-    "test-key-huggingface-2024",              // Hugging Face test key
-];
-
-/// Check for API key in headers that bypasses IP restrictions
-fn check_bypass_api_key(headers: &HeaderMap) -> Option<&'static str> {
-    // Check X-API-Key header
-    if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
-        let key = key.trim();
-        for &bypass_key in BYPASS_API_KEYS {
-            if key == bypass_key {
-                return Some(bypass_key);
-            }
-        }
-    }
-    
-    // Check Authorization: Bearer <key> header
-    if let Some(auth) = headers.get("authorization").and_then(|v| v.to_str().ok()) {
-        if let Some(key) = auth.trim().strip_prefix("Bearer ") {
-            let key = key.trim();
-            for &bypass_key in BYPASS_API_KEYS {
-                if key == bypass_key {
-                    return Some(bypass_key);
-                }
-            }
-        }
-    }
-    
-    // Check x-op-mcp-token header
-    if let Some(key) = headers.get("x-op-mcp-token").and_then(|v| v.to_str().ok()) {
-        let key = key.trim();
-        for &bypass_key in BYPASS_API_KEYS {
-            if key == bypass_key {
-                return Some(bypass_key);
-            }
-        }
-    }
-    
-    None
-}
+use simd_json::json;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::access_policy;
+use crate::api_keys;
+use crate::audit::{self, AuditRecord, Caller, ClientIdentity};
+use crate::executor;
 
-fn extract_auth_token(headers: &HeaderMap) -> Option<String> {
-    if let Some(raw) = headers.get("x-op-mcp-token").and_then(|v| v.to_str().ok()) {
-        let token = raw.trim();
-        if !token.is_empty() {
-            return Some(token.to_string());
+/// Extract the caller's API key / token from any of the accepted headers
+pub fn extract_auth_token(headers: &HeaderMap) -> Option<String> {
+    for name in ["x-op-mcp-token", "x-api-key"] {
+        if let Some(raw) = headers.get(name).and_then(|v| v.to_str().ok()) {
+            let token = raw.trim();
+            if !token.is_empty() {
+                return Some(token.to_string());
+            }
         }
     }
 
@@ -76,37 +48,128 @@ fn extract_auth_token(headers: &HeaderMap) -> Option<String> {
     None
 }
 
-/// Extract IP from headers or connection info
-pub fn extract_ip(headers: &HeaderMap, addr: Option<&SocketAddr>) -> String {
-    // 1. Check X-Forwarded-For (standard proxy header)
-    if let Some(forwarded) = headers.get("x-forwarded-for") {
-        if let Ok(s) = forwarded.to_str() {
-            if let Some(client_ip) = s.split(',').next() {
-                return client_ip.trim().to_string();
-            }
+/// Trusted reverse proxies, one address or CIDR network per entry
+const TRUSTED_PROXIES_PATH: &str = "/etc/op-dbus/trusted-proxies.json";
+
+lazy_static::lazy_static! {
+    static ref TRUSTED_PROXIES: Vec<Network> = load_trusted_proxies();
+}
+
+/// An address or CIDR network (`10.0.0.0/8`, `::1`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Network {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Network {
+    fn parse(raw: &str) -> Option<Self> {
+        let (addr, prefix) = match raw.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse().ok()?)),
+            None => (raw.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().ok()?;
+        let width = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(width);
+        (prefix <= width).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: &IpAddr) -> bool {
+        let (net, ip, width) = match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => (u32::from(net) as u128, u32::from(*ip) as u128, 32),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => (u128::from(net), u128::from(*ip), 128),
+            _ => return false,
+        };
+        self.prefix == 0 || net >> (width - self.prefix) == ip >> (width - self.prefix)
+    }
+}
+
+/// Load the trusted proxies; without a config only a proxy on this host is
+/// trusted
+fn load_trusted_proxies() -> Vec<Network> {
+    let defaults = || ["127.0.0.1", "::1"].iter().filter_map(|raw| Network::parse(raw)).collect();
+    let Ok(content) = std::fs::read_to_string(TRUSTED_PROXIES_PATH) else {
+        return defaults();
+    };
+    let mut raw = content;
+    match unsafe { simd_json::from_str::<Vec<String>>(&mut raw) } {
+        Ok(entries) => {
+            let proxies: Vec<Network> = entries
+                .iter()
+                .filter_map(|entry| {
+                    let network = Network::parse(entry);
+                    if network.is_none() {
+                        warn!("Ignoring invalid trusted proxy '{}' in {}", entry, TRUSTED_PROXIES_PATH);
+                    }
+                    network
+                })
+                .collect();
+            info!("Loaded {} trusted proxies from {}", proxies.len(), TRUSTED_PROXIES_PATH);
+            proxies
+        }
+        Err(e) => {
+            warn!("Invalid {}: {}; trusting only local proxies", TRUSTED_PROXIES_PATH, e);
+            defaults()
         }
     }
+}
+
+/// Extract the client IP from the connection, or from the forwarding
+/// headers when the connection comes from a trusted proxy
+pub fn extract_ip(headers: &HeaderMap, addr: Option<&SocketAddr>) -> String {
+    client_ip(headers, addr.map(|a| a.ip()), &TRUSTED_PROXIES).to_string()
+}
+
+/// Whether the connection comes from a trusted proxy, so its forwarding
+/// headers may be believed
+pub fn is_trusted_proxy(addr: Option<&SocketAddr>) -> bool {
+    addr.is_some_and(|addr| TRUSTED_PROXIES.iter().any(|network| network.contains(&addr.ip())))
+}
+
+fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted: &[Network]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|network| network.contains(ip));
+    // Without a socket address there is no peer to vouch for the headers
+    let Some(peer) = peer else {
+        return IpAddr::from([0, 0, 0, 0]);
+    };
+    if !is_trusted(&peer) {
+        return peer;
+    }
 
-    // 2. Check X-Real-IP (nginx convention)
-    if let Some(real_ip) = headers.get("x-real-ip") {
-        if let Ok(s) = real_ip.to_str() {
-            return s.trim().to_string();
+    // 1. X-Forwarded-For: the nearest hop that is not one of our proxies;
+    //    anything further left was written by the client
+    if let Some(forwarded) = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()) {
+        for hop in forwarded.rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) if is_trusted(&ip) => continue,
+                Ok(ip) => return ip,
+                Err(_) => break,
+            }
         }
     }
 
-    // 3. Fallback to socket address
-    if let Some(addr) = addr {
-        return addr.ip().to_string();
+    // 2. X-Real-IP (nginx convention)
+    if let Some(real_ip) = headers
+        .get("x-real-ip")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.trim().parse().ok())
+    {
+        return real_ip;
     }
 
-    "0.0.0.0".to_string()
+    // 3. The proxy itself
+    peer
 }
 
 /// Middleware to identify Client IP and attach AccessZone to the request
 /// 
 /// Security Logic:
-/// 1. If request has a valid bypass API key -> TrustedMesh (full access)
+/// 1. If request has a valid managed API key -> TrustedMesh, limited to the
+///    key's scopes
 /// 2. Otherwise, determine zone from IP address
+/// 3. Zone and identity must pass the access policy's route rules
+///
+/// Denials are answered with 403 and an explanation, and audited.
 pub async fn ip_security_middleware(
     connect_info: Option<ConnectInfo<SocketAddr>>,
     mut request: Request,
@@ -115,15 +178,15 @@ pub async fn ip_security_middleware(
     let headers = request.headers();
     let addr = connect_info.map(|ci| ci.0);
     let client_ip = extract_ip(headers, addr.as_ref());
-    
-    // Check for bypass API key first
-    let zone = if let Some(key) = check_bypass_api_key(headers) {
-        info!(
-            "API key bypass granted: IP={} key={}...{}",
-            client_ip,
-            &key[..8],
-            &key[key.len()-4..]
-        );
+    let token = extract_auth_token(headers);
+
+    // Check for a managed API key first
+    let api_key = match (&token, api_keys::global()) {
+        (Some(token), Some(store)) => store.authenticate(token).await,
+        _ => None,
+    };
+    let zone = if let Some(key) = &api_key {
+        info!("API key access: IP={} key={} ({})", client_ip, key.name, key.prefix);
         AccessZone::TrustedMesh
     } else {
         // Determine zone from IP
@@ -132,8 +195,95 @@ pub async fn ip_security_middleware(
         zone
     };
 
-    // Attach AccessZone to the request extensions
+    // Who is calling, for the execution audit trail; only a key that
+    // authenticated names the caller
+    let identity = ClientIdentity {
+        ip: client_ip,
+        zone: format!("{:?}", zone),
+        api_key: api_key.as_ref().map(|key| key.name.clone()),
+    };
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let mut denial = api_key.as_ref().and_then(|key| {
+        api_keys::required_scope(&method, &path)
+            .filter(|required| !key.allows(required))
+            .map(|required| format!("API key '{}' lacks the '{}' scope", key.name, required))
+    });
+    if let (None, Some(policy)) = (&denial, access_policy::global()) {
+        denial = policy
+            .authorize_route(&zone, identity.api_key.as_deref(), &method, &path)
+            .await
+            .err();
+    }
+    if let Some(reason) = denial {
+        warn!("Access denied: {} {} from {} [Zone: {:?}]: {}", method, path, identity.ip, zone, reason);
+        audit_denial(&identity, &method, &path, &reason).await;
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": "Access denied",
+                "reason": reason,
+                "zone": identity.zone.clone()
+            })),
+        )
+            .into_response();
+    }
+
+    // Attach AccessZone, identity and the API key to the request extensions
     request.extensions_mut().insert(zone);
+    request.extensions_mut().insert(identity.clone());
+    if let Some(key) = api_key {
+        request.extensions_mut().insert(key);
+    }
+
+    audit::with_identity(identity, next.run(request)).await
+}
+
+/// Audit a denied request as a failed `METHOD /path` by an `http` caller
+async fn audit_denial(identity: &ClientIdentity, method: &Method, path: &str, reason: &str) {
+    let Some(executor) = executor::global() else {
+        return;
+    };
+    let caller = Caller {
+        source: "http".to_string(),
+        ip: Some(identity.ip.clone()),
+        zone: Some(identity.zone.clone()),
+        api_key: identity.api_key.clone(),
+        ..Default::default()
+    };
+    let record = AuditRecord::new(
+        Uuid::new_v4(),
+        &format!("{} {}", method, path),
+        caller,
+        json!({}),
+        Utc::now(),
+        Duration::ZERO,
+        &Err(reason.to_string()),
+    );
+    executor.audit().record(record).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    next.run(request).await
+    #[test]
+    fn test_forwarded_headers_only_from_trusted_proxies() {
+        let trusted = vec![Network::parse("127.0.0.1").unwrap(), Network::parse("10.0.0.0/8").unwrap()];
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "127.0.0.1, 203.0.113.7, 10.1.2.3".parse().unwrap());
+        let proxy: IpAddr = "10.9.9.9".parse().unwrap();
+        let client: IpAddr = "198.51.100.4".parse().unwrap();
+
+        // Spoofed leftmost entry is ignored; the nearest untrusted hop wins
+        assert_eq!(client_ip(&headers, Some(proxy), &trusted).to_string(), "203.0.113.7");
+        // A direct client cannot claim another address
+        assert_eq!(client_ip(&headers, Some(client), &trusted), client);
+        assert_eq!(client_ip(&headers, None, &trusted).to_string(), "0.0.0.0");
+
+        assert!(Network::parse("::1").unwrap().contains(&"::1".parse().unwrap()));
+        assert!(!Network::parse("10.0.0.0/8").unwrap().contains(&"11.0.0.1".parse().unwrap()));
+        assert!(Network::parse("10.0.0.0/33").is_none());
+    }
 }
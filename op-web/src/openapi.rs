@@ -0,0 +1,431 @@
+//! OpenAPI - `/api/openapi.json` generated from the routes and the registry
+//!
+//! The fixed REST API is described by [`OPERATIONS`], which mirrors
+//! `routes::create_router`; a test fails when a mounted `/api`, `/admin` or
+//! `/groups-admin` route has no operation. Every registered tool also
+//! gets its own operation at `/api/tools/<name>/execute` whose request body is
+//! the tool's `input_schema`, so D-Bus-projected tools appear as ordinary HTTP
+//! operations to client generators and gateways.
+
+use axum::{
+    extract::{ConnectInfo, Extension},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Json, Response},
+};
+use op_tools::registry::ToolDefinition;
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::mcp_discovery;
+use crate::state::AppState;
+use crate::tool_catalog;
+
+/// A fixed REST operation
+struct Operation {
+    method: &'static str,
+    /// OpenAPI path template (`{param}`)
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    /// Component schema of the JSON request body
+    body: Option<&'static str>,
+    query: &'static [&'static str],
+}
+
+const fn op(method: &'static str, path: &'static str, tag: &'static str, summary: &'static str) -> Operation {
+    Operation { method, path, tag, summary, body: None, query: &[] }
+}
+
+const fn op_body(
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    body: &'static str,
+) -> Operation {
+    Operation { method, path, tag, summary, body: Some(body), query: &[] }
+}
+
+const fn op_query(
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    query: &'static [&'static str],
+) -> Operation {
+    Operation { method, path, tag, summary, body: None, query }
+}
+
+const OPERATIONS: &[Operation] = &[
+    op("get", "/api/health", "status", "Health check"),
+    op("get", "/api/status", "status", "System status"),
+    op("get", "/api/openapi.json", "status", "This document"),
+    op_body("post", "/api/chat", "chat", "Send a chat message", "ChatRequest"),
+    op_body("post", "/api/chat/stream", "chat", "Send a chat message, streaming the reply", "ChatRequest"),
+    op("get", "/api/chat/history/{session_id}", "chat", "Conversation history"),
+    op_body("post", "/api/chat/transcript", "chat", "Save a transcript", "Object"),
+    op_query("get", "/api/tools", "tools", "List tools", &["category", "tag", "namespace", "q", "offset", "limit"]),
+    op_body("post", "/api/tools/batch", "tools", "Execute tools as a batch", "BatchRequest"),
+    op("get", "/api/tools/{name}", "tools", "Tool details"),
+    op_body("post", "/api/tool", "tools", "Execute a tool", "DirectToolRequest"),
+    op_body("post", "/api/tools/{name}/execute", "tools", "Execute a named tool", "Object"),
+    op_body("post", "/api/tools/{name}/stream", "tools", "Execute a named tool, streaming its output (SSE)", "Object"),
+    op("get", "/api/macros", "macros", "List tool macros"),
+    op_body("post", "/api/macros", "macros", "Create or replace a tool macro", "Object"),
+    op("get", "/api/macros/{name}", "macros", "Macro definition"),
+    op_body("put", "/api/macros/{name}", "macros", "Replace a tool macro", "Object"),
+    op("delete", "/api/macros/{name}", "macros", "Remove a tool macro"),
+    op_query("get", "/api/jobs", "jobs", "List jobs", &["tool", "status", "since", "limit"]),
+    op_body("post", "/api/jobs", "jobs", "Run a tool as a background job", "DirectToolRequest"),
+    op("get", "/api/jobs/{id}", "jobs", "Job status and result"),
+    op("delete", "/api/jobs/{id}", "jobs", "Cancel a job"),
+    op_query(
+        "get",
+        "/api/audit",
+        "audit",
+        "Tool execution audit trail",
+        &["tool", "source", "ip", "zone", "session", "success", "since", "limit"],
+    ),
+    op("get", "/api/cache", "tools", "Tool result cache statistics"),
+    op("delete", "/api/cache", "tools", "Clear the tool result cache"),
+    op("get", "/api/limits", "tools", "Tool limits and violations"),
+    op("get", "/api/agents", "agents", "List agents"),
+    op_body("post", "/api/agents", "agents", "Spawn an agent", "Object"),
+    op("get", "/api/agents/types", "agents", "Agent types"),
+    op("get", "/api/agents/{id}", "agents", "Agent details"),
+    op("delete", "/api/agents/{id}", "agents", "Stop an agent"),
+    op("get", "/api/llm/status", "llm", "LLM status"),
+    op("get", "/api/llm/providers", "llm", "LLM providers"),
+    op("get", "/api/llm/models", "llm", "Models of the current provider"),
+    op("get", "/api/llm/models/{provider}", "llm", "Models of a provider"),
+    op_body("post", "/api/llm/provider", "llm", "Switch provider", "Object"),
+    op_body("post", "/api/llm/model", "llm", "Switch model", "Object"),
+    op_query("get", "/api/mcp/_config", "mcp", "MCP client configuration", &["client"]),
+    op("get", "/api/events", "events", "Server-sent event stream"),
+    op_body("post", "/api/privacy/signup", "privacy", "Sign up for the privacy router", "Object"),
+    op_query("get", "/api/privacy/verify", "privacy", "Verify a magic link", &["token"]),
+    op("get", "/api/privacy/config/{user_id}", "privacy", "WireGuard client config"),
+    op("get", "/api/privacy/status", "privacy", "Privacy router status"),
+    op_body("post", "/api/privacy/credentials", "privacy", "Set credentials", "Object"),
+    op("get", "/api/privacy/google/auth", "privacy", "Start Google sign-in"),
+    op("get", "/api/privacy/google/callback", "privacy", "Google sign-in callback"),
+    op("get", "/admin/prompt", "admin", "System prompt"),
+    op("get", "/admin/prompt/custom", "admin", "Custom prompt"),
+    op_body("post", "/admin/prompt/custom", "admin", "Set the custom prompt", "Object"),
+    op_body("post", "/admin/prompt/test", "admin", "Test a prompt", "Object"),
+    op("post", "/admin/prompt/reload", "admin", "Reload prompts"),
+    op("get", "/admin/config", "admin", "Server configuration"),
+    op("get", "/admin/agents/roster", "admin", "Agent roster"),
+    op_body("post", "/admin/agents/roster", "admin", "Add an agent to the roster", "Object"),
+    op("delete", "/admin/agents/roster/{name}", "admin", "Remove an agent from the roster"),
+    op_body("post", "/admin/registry/refresh", "admin", "Re-project D-Bus tools of a bus or service", "Object"),
+    op("get", "/admin/api-keys", "admin", "List API keys"),
+    op_body("post", "/admin/api-keys", "admin", "Create an API key", "Object"),
+    op("delete", "/admin/api-keys/{id}", "admin", "Revoke an API key"),
+    op("post", "/admin/api-keys/{id}/rotate", "admin", "Rotate an API key"),
+    op("get", "/groups-admin", "groups", "Tool groups admin page (HTML)"),
+    op("get", "/groups-admin/api/groups", "groups", "Built-in tool groups by domain"),
+    op("get", "/groups-admin/api/presets", "groups", "Tool group presets"),
+    op("get", "/groups-admin/api/profiles", "groups", "Saved tool profiles"),
+    op("get", "/groups-admin/api/profiles/{name}", "groups", "Tool profile"),
+    op_body("post", "/groups-admin/api/profiles/{name}", "groups", "Save a tool profile", "Object"),
+    op("get", "/groups-admin/api/access-zone", "groups", "Access zone of the caller"),
+    op("get", "/groups-admin/api/trusted-networks", "groups", "Trusted networks"),
+    op_body("post", "/groups-admin/api/trusted-networks", "groups", "Add a trusted network", "Object"),
+    op("get", "/.well-known/mcp.json", "mcp", "MCP discovery document"),
+];
+
+/// GET /api/openapi.json
+pub async fn openapi_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response {
+    let tools = state.tool_registry.list().await;
+    let document = generate(&mcp_discovery::base_url(&headers, connect_info.map(|ci| ci.0).as_ref()), &tools);
+
+    (
+        // Tools come and go with the registry
+        [(header::CACHE_CONTROL, "no-cache")],
+        Json(document),
+    )
+        .into_response()
+}
+
+/// Build the OpenAPI document for the fixed API plus `tools`
+pub fn generate(base_url: &str, tools: &[ToolDefinition]) -> Value {
+    let mut paths = json!({});
+    let obj = paths.as_object_mut().expect("object literal");
+
+    for operation in OPERATIONS {
+        let entry = obj.entry(operation.path.to_string()).or_insert_with(|| json!({}));
+        if let Some(item) = entry.as_object_mut() {
+            item.insert(operation.method.into(), fixed_operation(operation));
+        }
+    }
+
+    let mut tools: Vec<&ToolDefinition> = tools.iter().collect();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    for tool in tools {
+        obj.insert(
+            format!("/api/tools/{}/execute", tool.name),
+            json!({ "post": tool_operation(tool) }),
+        );
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "op-dbus API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Operation D-Bus REST API. Each registered tool is exposed as POST /api/tools/<name>/execute with its input schema as the request body."
+        },
+        "servers": [{ "url": base_url }],
+        "security": [{ "apiKey": [] }, { "bearer": [] }, {}],
+        "paths": paths,
+        "components": components()
+    })
+}
+
+fn fixed_operation(operation: &Operation) -> Value {
+    let mut parameters: Vec<Value> = path_params(operation.path)
+        .map(|name| json!({ "name": name, "in": "path", "required": true, "schema": { "type": "string" } }))
+        .collect();
+    parameters.extend(
+        operation
+            .query
+            .iter()
+            .map(|name| json!({ "name": *name, "in": "query", "required": false, "schema": { "type": "string" } })),
+    );
+
+    let mut value = json!({
+        "tags": [operation.tag],
+        "summary": operation.summary,
+        "operationId": operation_id(operation.method, operation.path),
+        "responses": { "200": { "description": "OK" } }
+    });
+    if let Some(obj) = value.as_object_mut() {
+        if !parameters.is_empty() {
+            obj.insert("parameters".into(), Value::from(parameters));
+        }
+        if let Some(body) = operation.body {
+            obj.insert("requestBody".into(), json_body(json!({ "$ref": format!("#/components/schemas/{}", body) })));
+        }
+    }
+    value
+}
+
+fn tool_operation(tool: &ToolDefinition) -> Value {
+    json!({
+        "tags": ["tool:".to_string() + &tool_catalog::category_of(tool)],
+        "summary": tool.description.clone(),
+        "operationId": tool.name.clone(),
+        "parameters": [{
+            "name": "async",
+            "in": "query",
+            "required": false,
+            "description": "Run as a background job (202 with the job)",
+            "schema": { "type": "boolean" }
+        }],
+        "requestBody": json_body(tool.input_schema.clone()),
+        "responses": {
+            "200": {
+                "description": "Execution result",
+                "content": { "application/json": { "schema": { "$ref": "#/components/schemas/DirectToolResponse" } } }
+            },
+            "202": { "description": "Job accepted (`?async=true`)" }
+        }
+    })
+}
+
+fn json_body(schema: Value) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema } }
+    })
+}
+
+/// `{param}` names in a path template
+fn path_params(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')))
+}
+
+/// `get /api/jobs/{id}` → `get_api_jobs_id`
+fn operation_id(method: &str, path: &str) -> String {
+    let mut id = method.to_string();
+    for part in path.split(|c: char| !c.is_ascii_alphanumeric()).filter(|p| !p.is_empty()) {
+        id.push('_');
+        id.push_str(part);
+    }
+    id
+}
+
+fn components() -> Value {
+    json!({
+        "securitySchemes": {
+            "apiKey": { "type": "apiKey", "in": "header", "name": "x-api-key" },
+            "bearer": { "type": "http", "scheme": "bearer" }
+        },
+        "schemas": {
+            "Object": { "type": "object" },
+            "ChatRequest": {
+                "type": "object",
+                "properties": {
+                    "message": { "type": "string" },
+                    "session_id": { "type": "string" },
+                    "model": { "type": "string" },
+                    "user_id": { "type": "string" }
+                },
+                "required": ["message"]
+            },
+            "DirectToolRequest": {
+                "type": "object",
+                "properties": {
+                    "tool_name": { "type": "string" },
+                    "arguments": { "type": "object" }
+                },
+                "required": ["tool_name"]
+            },
+            "DirectToolResponse": {
+                "type": "object",
+                "properties": {
+                    "success": { "type": "boolean" },
+                    "tool_name": { "type": "string" },
+                    "result": {},
+                    "error": { "type": ["string", "null"] },
+                    "execution_time_ms": { "type": "integer" }
+                }
+            },
+            "BatchRequest": {
+                "type": "object",
+                "properties": {
+                    "steps": {
+                        "type": "array",
+                        "items": { "$ref": "#/components/schemas/DirectToolRequest" }
+                    },
+                    "mode": { "type": "string", "enum": ["sequential", "parallel"] },
+                    "stop_on_error": { "type": "boolean", "default": true },
+                    "rollback": { "type": "boolean", "default": true }
+                },
+                "required": ["steps"]
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_operations_use_input_schema() {
+        let tool = ToolDefinition {
+            name: "ovs_create_bridge".to_string(),
+            description: "Create an OVS bridge".to_string(),
+            input_schema: json!({ "type": "object", "properties": { "name": { "type": "string" } } }),
+            schema_version: String::new(),
+            category: "networking".to_string(),
+            tags: Vec::new(),
+            namespace: "ovs".to_string(),
+        };
+
+        let document = generate("http://localhost:8080", &[tool]);
+        let paths = document.get("paths").unwrap();
+
+        let operation = paths.get("/api/tools/ovs_create_bridge/execute").and_then(|p| p.get("post")).unwrap();
+        assert_eq!(operation.get("operationId"), Some(&json!("ovs_create_bridge")));
+        let schema = operation
+            .get("requestBody")
+            .and_then(|b| b.get("content"))
+            .and_then(|c| c.get("application/json"))
+            .and_then(|j| j.get("schema"))
+            .unwrap();
+        assert_eq!(schema.get("properties").and_then(|p| p.get("name")), Some(&json!({ "type": "string" })));
+
+        // Fixed routes sharing a path are merged
+        let jobs = paths.get("/api/jobs/{id}").unwrap();
+        assert!(jobs.get("get").is_some() && jobs.get("delete").is_some());
+    }
+
+    /// `(method, path)` of every `.route(...)` in `source`, under `prefix`
+    fn mounted(source: &str, prefix: &str) -> Vec<(String, String)> {
+        let mut routes = Vec::new();
+        for (start, _) in source.match_indices(".route(") {
+            let rest = &source[start + ".route(".len()..];
+            let mut depth = 1;
+            let end = rest
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })
+                .map(|(i, _)| i)
+                .unwrap();
+            let call = &rest[..end];
+            let path = call.split('"').nth(1).unwrap();
+            let path = path
+                .split('/')
+                .map(|segment| match segment.strip_prefix(':') {
+                    Some(param) => format!("{{{}}}", param),
+                    None => segment.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let path = if path == "/" { prefix.to_string() } else { format!("{}{}", prefix, path) };
+            for method in ["get", "post", "put", "delete", "patch"] {
+                let called = call.match_indices(&format!("{}(", method)).any(|(i, _)| {
+                    let before = if i == 0 { b' ' } else { call.as_bytes()[i - 1] };
+                    !before.is_ascii_alphanumeric() && before != b'_'
+                });
+                if called {
+                    routes.push((method.to_string(), path.clone()));
+                }
+            }
+        }
+        routes
+    }
+
+    /// The part of `source` from `from` up to the next `until`
+    fn section<'a>(source: &'a str, from: &str, until: &str) -> &'a str {
+        let start = source.find(from).unwrap();
+        let end = source[start..].find(until).map(|i| start + i).unwrap_or(source.len());
+        &source[start..end]
+    }
+
+    #[test]
+    fn test_every_mounted_route_has_an_operation() {
+        let routes_mod = include_str!("routes/mod.rs");
+        let mut routes = mounted(section(routes_mod, "let api_routes", "let mcp_route"), "/api");
+        routes.extend(
+            mounted(section(routes_mod, "let mut router", "router.fallback"), "")
+                .into_iter()
+                .filter(|(_, path)| path.starts_with("/.well-known/")),
+        );
+        routes.extend(mounted(section(include_str!("routes/admin.rs"), "fn admin_routes", "\n}"), "/admin"));
+        routes.extend(mounted(
+            section(include_str!("groups_admin.rs"), "fn create_groups_admin_router", "\n}"),
+            "/groups-admin",
+        ));
+        assert!(routes.len() > 50, "route scan found only {} routes", routes.len());
+
+        let missing: Vec<String> = routes
+            .iter()
+            .filter(|(method, path)| !OPERATIONS.iter().any(|op| op.method == method.as_str() && op.path == path.as_str()))
+            .map(|(method, path)| format!("{} {}", method.to_uppercase(), path))
+            .collect();
+        assert!(missing.is_empty(), "routes without an OpenAPI operation: {:?}", missing);
+    }
+
+    #[test]
+    fn test_operation_ids() {
+        assert_eq!(operation_id("get", "/api/jobs/{id}"), "get_api_jobs_id");
+        assert_eq!(path_params("/api/chat/history/{session_id}").collect::<Vec<_>>(), vec!["session_id"]);
+    }
+}
@@ -4,6 +4,7 @@ use tracing::error;
 use anyhow::Result;
 use super::types::{ToolResult, OrchestratorResponse};
 use super::UnifiedOrchestrator;
+use crate::tool_catalog;
 
 impl UnifiedOrchestrator {
     /// Execute a single tool
@@ -27,34 +28,31 @@ impl UnifiedOrchestrator {
             _ => {}
         }
 
-        // Execute actual tool from registry
-        match self.tool_registry.get(name).await {
-            Some(tool) => {
-                match tool.execute(args).await {
-                    Ok(result) => ToolResult {
-                        name: name.to_string(),
-                        success: true,
-                        result: Some(result),
-                        error: None,
-                    },
-                    Err(e) => {
-                        error!("Tool {} failed: {}", name, e);
-                        ToolResult {
-                            name: name.to_string(),
-                            success: false,
-                            result: None,
-                            error: Some(e.to_string()),
-                        }
-                    }
-                }
-            }
-            None => {
-                error!("Tool not found: {}", name);
+        if self.tool_registry.get(name).await.is_none() {
+            error!("Tool not found: {}", name);
+            return ToolResult {
+                name: name.to_string(),
+                success: false,
+                result: None,
+                error: Some(format!("Tool not found: {}. Use list_tools or search_tools to find available tools.", name)),
+            };
+        }
+
+        // Execute actual tool from registry (audited)
+        match self.executor.execute("chat", name, args).await {
+            Ok(result) => ToolResult {
+                name: name.to_string(),
+                success: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => {
+                error!("Tool {} failed: {}", name, e);
                 ToolResult {
                     name: name.to_string(),
                     success: false,
                     result: None,
-                    error: Some(format!("Tool not found: {}. Use list_tools or search_tools to find available tools.", name)),
+                    error: Some(e.to_string()),
                 }
             }
         }
@@ -98,24 +96,16 @@ impl UnifiedOrchestrator {
         let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
 
         let all_tools = self.tool_registry.list().await;
-        
+        let categories = tool_catalog::count_by_category(&all_tools);
+
+        // Same categories as `GET /api/tools?category=`
         let filtered: Vec<_> = if category == "all" {
             all_tools
         } else {
-            all_tools.into_iter()
-                .filter(|t| {
-                    match category {
-                        "ovs" => t.name.starts_with("ovs_"),
-                        "systemd" => t.name.starts_with("dbus_systemd_"),
-                        "dbus" => t.name.starts_with("dbus_"),
-                        "file" => t.name.starts_with("file_"),
-                        "shell" => t.name.starts_with("shell_"),
-                        "network" => t.name.starts_with("rtnetlink_"),
-                        "openflow" => t.name.starts_with("openflow_"),
-                        "agent" => t.name.starts_with("agent_"),
-                        _ => false,
-                    }
-                })
+            let category = category.to_lowercase();
+            all_tools
+                .into_iter()
+                .filter(|t| tool_catalog::category_of(t) == category)
                 .collect()
         };
 
@@ -135,6 +125,7 @@ impl UnifiedOrchestrator {
                 "total": filtered.len(),
                 "showing": tools_json.len(),
                 "category": category,
+                "categories": categories.keys().cloned().collect::<Vec<_>>(),
             })),
             error: None,
         }
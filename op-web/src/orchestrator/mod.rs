@@ -2,6 +2,8 @@ use std::sync::Arc;
 use op_llm::chat::ChatManager;
 use op_tools::registry::ToolRegistry;
 
+use crate::executor::ToolExecutor;
+
 // Export types publicly
 pub mod types;
 pub use types::*;
@@ -25,6 +27,8 @@ mod process;
 pub struct UnifiedOrchestrator {
     pub chat_manager: Arc<ChatManager>,
     pub tool_registry: Arc<ToolRegistry>,
+    /// Audited path for the tools the model calls
+    pub executor: Arc<ToolExecutor>,
     pub config: OrchestratorConfig,
 }
 
@@ -32,10 +36,12 @@ impl UnifiedOrchestrator {
     pub fn new(
         tool_registry: Arc<ToolRegistry>,
         chat_manager: Arc<ChatManager>,
+        executor: Arc<ToolExecutor>,
     ) -> Self {
         Self {
             tool_registry,
             chat_manager,
+            executor,
             config: OrchestratorConfig::default(),
         }
     }
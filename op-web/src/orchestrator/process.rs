@@ -8,6 +8,8 @@ use op_llm::{
     provider::{ChatMessage, ChatRequest, LlmProvider, ToolChoice, ModelInfo},
 };
 
+use crate::tool_output::{self, OutputSink};
+
 use super::{UnifiedOrchestrator, OrchestratorResponse, OrchestratorEvent, MAX_TURNS};
 
 impl UnifiedOrchestrator {
@@ -65,7 +67,7 @@ impl UnifiedOrchestrator {
 
         // Build system prompt: Capabilities + Compact Instructions + Tool Directory
         let system_msg_core = op_chat::system_prompt::generate_system_prompt().await;
-        let compact_instructions = self.build_compact_mode_system_prompt();
+        let compact_instructions = self.build_compact_mode_system_prompt().await;
         
         let combined_prompt = format!("{}
 
@@ -232,8 +234,19 @@ The following tools are available via execute_tool():
                     }).await;
                 }
 
-                // Execute the tool
-                let tool_result = self.execute_tool(&name, args.clone()).await;
+                // Execute the tool, relaying its output chunks as they arrive
+                let output = event_tx.clone().map(|tx| {
+                    let name = name.clone();
+                    OutputSink::new(move |chunk| {
+                        let _ = tx.try_send(OrchestratorEvent::ToolOutput {
+                            name: name.clone(),
+                            seq: chunk.seq,
+                            stream: chunk.stream,
+                            data: chunk.data,
+                        });
+                    })
+                });
+                let tool_result = tool_output::with_sink(output, self.execute_tool(&name, args.clone())).await;
 
                 // Emit ToolResult event
                 if let Some(tx) = &event_tx {
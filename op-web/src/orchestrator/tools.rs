@@ -109,8 +109,8 @@ impl UnifiedOrchestrator {
     /// Build system prompt for compact mode
     ///
     /// This explains the meta-tool architecture to the LLM.
-    pub(crate) fn build_compact_mode_system_prompt(&self) -> String {
-        r#"You are an AI system administrator with access to 138+ system management tools via a compact interface.
+    pub(crate) async fn build_compact_mode_system_prompt(&self) -> String {
+        let mut prompt = r#"You are an AI system administrator with access to 138+ system management tools via a compact interface.
 
 CRITICAL RULES:
 1. ALWAYS use tools for system operations - NEVER suggest CLI commands
@@ -158,15 +158,23 @@ User: "What tools are available for networking?"
 1. list_tools({"category": "network"})  → Browse network tools
 
 REMEMBER: You have access to D-Bus (systemd, NetworkManager), OVSDB (OVS), and Netlink (kernel) - all via native protocols, not CLI.
+"#.to_string();
 
+        // The built-in macro is only registered when its OVS and rtnetlink tools are
+        if self.tool_registry.get_definition("create_internal_bridge").await.is_some() {
+            prompt.push_str(r#"
 HINT - OVS NETWORKING:
 Creating an OVS bridge (`ovs_create_bridge`) does NOT create a Linux network interface automatically.
-To assign an IP address to a bridge, you MUST add an internal port with the same name (or different name) to the bridge first.
-Example:
-1. `execute_tool("ovs_create_bridge", {"name": "br0"})`
-2. `execute_tool("ovs_add_port", {"bridge": "br0", "port": "br0", "type": "internal"})`
-3. `execute_tool("rtnetlink_add_address", {"interface": "br0", ...})`
-"#.to_string()
+To create a bridge with an address, use the `create_internal_bridge` macro, which creates the bridge,
+adds an internal port of the same name and assigns the address, undoing what it can if a step fails:
+`execute_tool("create_internal_bridge", {"name": "br0", "cidr": "10.0.0.1/24"})`
+"#);
+        }
+
+        prompt.push_str(r#"
+Macros (category "macro") are composite tools; list_tools({"category": "macro"}) shows them.
+"#);
+        prompt
     }
 
     /// Build system prompt with tool context
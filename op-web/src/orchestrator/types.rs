@@ -30,6 +30,7 @@ impl Default for OrchestratorConfig {
 pub enum OrchestratorEvent {
     Thinking,
     ToolExecution { name: String, args: Value },
+    ToolOutput { name: String, seq: u64, stream: String, data: String },
     ToolResult { name: String, success: bool, result: Option<Value>, error: Option<String> },
     Finished { success: bool, message: String, tools_executed: Vec<String> },
     Error { message: String },
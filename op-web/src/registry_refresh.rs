@@ -0,0 +1,391 @@
+//! Registry Refresh - keep D-Bus projected tools in step with the buses
+//!
+//! Startup projection (`register_all_tools`) sees only the services running
+//! at that moment. [`RegistryRefresher`] adopts the tools it registered, then
+//! re-projects a bus, or services on it, into a scratch registry and applies
+//! the difference to the live one:
+//!
+//! - `POST /admin/registry/refresh` with `{"bus": "system", "service": "org.freedesktop.NetworkManager"}`
+//!   (omit `service` for the whole bus)
+//! - optionally, a `NameOwnerChanged` watcher configured in
+//!   `/etc/op-dbus/registry-watch.json`:
+//!
+//! ```json
+//! { "buses": ["system"], "debounce_ms": 2000 }
+//! ```
+//!
+//! Owner changes within one debounce window share a single projection. The
+//! projection engine introspects whole buses, so a service refresh still
+//! projects its bus and keeps only the service's tools.
+//!
+//! Every change is announced as `notifications/tools/list_changed` to MCP
+//! sessions and as a `tools_changed` event on `/api/events`.
+//!
+//! The projection engine does not report which service a tool came from, so
+//! a tool belongs to a service when its name contains the service name in
+//! tool form (`org.freedesktop.NetworkManager` → `org_freedesktop_networkmanager`)
+//! or its description names the service.
+
+use futures::StreamExt;
+use op_tools::registry::ToolDefinition;
+use op_tools::ToolRegistry;
+use serde::{Deserialize, Serialize};
+use simd_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::mcp_session::MCP_SESSIONS;
+use crate::sse::SseEventBroadcaster;
+
+const REGISTRY_WATCH_CONFIG_PATH: &str = "/etc/op-dbus/registry-watch.json";
+
+fn default_debounce_ms() -> u64 {
+    2000
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    Session,
+    System,
+}
+
+impl Bus {
+    fn bus_type(self) -> op_core::BusType {
+        match self {
+            Bus::Session => op_core::BusType::Session,
+            Bus::System => op_core::BusType::System,
+        }
+    }
+
+    async fn connect(self) -> zbus::Result<zbus::Connection> {
+        match self {
+            Bus::Session => zbus::Connection::session().await,
+            Bus::System => zbus::Connection::system().await,
+        }
+    }
+}
+
+/// `/etc/op-dbus/registry-watch.json`; no buses means no watcher
+#[derive(Debug, Default, Deserialize)]
+pub struct WatchConfig {
+    #[serde(default)]
+    pub buses: Vec<Bus>,
+    /// How long owner changes are collected, from the first one, before
+    /// re-projecting
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+impl WatchConfig {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(REGISTRY_WATCH_CONFIG_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<WatchConfig>(&mut raw) } {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Invalid registry watch config {}: {}, watcher disabled", REGISTRY_WATCH_CONFIG_PATH, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Body of `POST /admin/registry/refresh`
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub bus: Bus,
+    #[serde(default)]
+    pub service: Option<String>,
+}
+
+/// What a refresh changed
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RefreshReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl RefreshReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Whether `tool` was projected from `service`
+pub fn belongs_to(tool: &ToolDefinition, service: &str) -> bool {
+    let tool_form: String = service
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    tool.name.to_lowercase().contains(&tool_form) || tool.description.contains(service)
+}
+
+/// Whether `tool` falls under `services` (every service when empty)
+fn in_scope(tool: &ToolDefinition, services: &[&str]) -> bool {
+    services.is_empty() || services.iter().any(|service| belongs_to(tool, service))
+}
+
+/// Project `bus` into a scratch registry
+async fn project(bus: Bus) -> anyhow::Result<Arc<ToolRegistry>> {
+    let introspection = Arc::new(op_introspection::IntrospectionService::new());
+    let projection = op_tools::discovery::ProjectionEngine::new(introspection);
+    let scratch = Arc::new(ToolRegistry::new());
+    projection.discover_all(&scratch, bus.bus_type()).await?;
+    Ok(scratch)
+}
+
+pub struct RegistryRefresher {
+    registry: Arc<ToolRegistry>,
+    events: Arc<SseEventBroadcaster>,
+    /// Tools this refresher registered, with the bus they came from
+    projected: Mutex<HashMap<String, (Bus, ToolDefinition)>>,
+    /// One refresh at a time
+    running: tokio::sync::Mutex<()>,
+}
+
+impl RegistryRefresher {
+    pub fn new(registry: Arc<ToolRegistry>, events: Arc<SseEventBroadcaster>) -> Self {
+        Self {
+            registry,
+            events,
+            projected: Mutex::new(HashMap::new()),
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Adopt the tools startup projection registered, so refreshes can
+    /// update and remove them
+    pub async fn seed(&self) {
+        let _running = self.running.lock().await;
+        for bus in [Bus::Session, Bus::System] {
+            let scratch = match project(bus).await {
+                Ok(scratch) => scratch,
+                Err(e) => {
+                    warn!("Projecting the {:?} bus to seed the refresher failed: {}", bus, e);
+                    continue;
+                }
+            };
+            let mut adopted = Vec::new();
+            for definition in scratch.list().await {
+                // Same name and description: the live tool came from this projection
+                let live = self.registry.get_definition(&definition.name).await;
+                if live.is_some_and(|live| live.description == definition.description) {
+                    adopted.push(definition);
+                }
+            }
+            debug!("Seeded {} projected tools from the {:?} bus", adopted.len(), bus);
+            let mut projected = self.projected.lock().unwrap();
+            for definition in adopted {
+                projected.insert(definition.name.clone(), (bus, definition));
+            }
+        }
+    }
+
+    /// Re-project `bus` (or only `service` on it) and apply the difference
+    pub async fn refresh(&self, bus: Bus, service: Option<&str>) -> anyhow::Result<RefreshReport> {
+        let services: Vec<&str> = service.into_iter().collect();
+        self.reproject(bus, &services).await
+    }
+
+    /// Re-project `bus` once for `services` (all of them when empty)
+    async fn reproject(&self, bus: Bus, services: &[&str]) -> anyhow::Result<RefreshReport> {
+        let _running = self.running.lock().await;
+
+        let scratch = project(bus).await?;
+        let fresh: Vec<ToolDefinition> = scratch
+            .list()
+            .await
+            .into_iter()
+            .filter(|tool| in_scope(tool, services))
+            .collect();
+        let fresh_names: HashSet<String> = fresh.iter().map(|t| t.name.clone()).collect();
+
+        let mut report = RefreshReport::default();
+        for definition in fresh {
+            let Some(tool) = scratch.get(&definition.name).await else {
+                continue;
+            };
+            match self.registry.get_definition(&definition.name).await {
+                Some(existing)
+                    if existing.description == definition.description
+                        && existing.input_schema == definition.input_schema => {}
+                Some(_) => {
+                    self.registry.unregister_tool(&definition.name).await;
+                    self.registry.register_tool(tool).await?;
+                    report.updated.push(definition.name.clone());
+                }
+                None => {
+                    self.registry.register_tool(tool).await?;
+                    report.added.push(definition.name.clone());
+                }
+            }
+            self.projected
+                .lock()
+                .unwrap()
+                .insert(definition.name.clone(), (bus, definition));
+        }
+
+        // Tools we projected earlier from this scope that are gone now
+        let stale = self.tracked(bus, services, |name| !fresh_names.contains(name));
+        for name in stale {
+            self.registry.unregister_tool(&name).await;
+            self.projected.lock().unwrap().remove(&name);
+            report.removed.push(name);
+        }
+
+        self.announce(bus, services, &report);
+        Ok(report)
+    }
+
+    /// Drop the tools of services that left the bus, without introspecting
+    pub async fn remove_services(&self, bus: Bus, services: &[&str]) -> RefreshReport {
+        let _running = self.running.lock().await;
+
+        let mut report = RefreshReport::default();
+        for name in self.tracked(bus, services, |_| true) {
+            self.registry.unregister_tool(&name).await;
+            self.projected.lock().unwrap().remove(&name);
+            report.removed.push(name);
+        }
+        self.announce(bus, services, &report);
+        report
+    }
+
+    fn tracked(&self, bus: Bus, services: &[&str], keep: impl Fn(&str) -> bool) -> Vec<String> {
+        self.projected
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, (tool_bus, definition))| *tool_bus == bus && in_scope(definition, services) && keep(name))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    fn announce(&self, bus: Bus, services: &[&str], report: &RefreshReport) {
+        if report.is_empty() {
+            return;
+        }
+        info!(
+            "Registry refresh ({:?}{}): {} added, {} updated, {} removed",
+            bus,
+            if services.is_empty() { String::new() } else { format!(" {}", services.join(", ")) },
+            report.added.len(),
+            report.updated.len(),
+            report.removed.len()
+        );
+
+        MCP_SESSIONS.notify_all("notifications/tools/list_changed", json!({}));
+        let event = json!({
+            "bus": bus,
+            "services": services,
+            "added": report.added.clone(),
+            "updated": report.updated.clone(),
+            "removed": report.removed.clone()
+        });
+        self.events
+            .broadcast("tools_changed", &simd_json::to_string(&event).unwrap_or_default());
+    }
+
+    /// Seed from the startup projection, then start the `NameOwnerChanged`
+    /// watchers from `/etc/op-dbus/registry-watch.json`
+    pub fn start(self: &Arc<Self>) {
+        let config = WatchConfig::load();
+        let debounce = Duration::from_millis(config.debounce_ms);
+        let refresher = self.clone();
+        tokio::spawn(async move {
+            refresher.seed().await;
+            for bus in config.buses {
+                let refresher = refresher.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = refresher.watch(bus, debounce).await {
+                        warn!("Registry watcher ({:?}) stopped: {}", bus, e);
+                    }
+                });
+            }
+        });
+    }
+
+    async fn watch(&self, bus: Bus, debounce: Duration) -> anyhow::Result<()> {
+        let connection = bus.connect().await?;
+        let dbus = zbus::fdo::DBusProxy::new(&connection).await?;
+        let mut changes = dbus.receive_name_owner_changed().await?;
+        info!("Watching NameOwnerChanged on the {:?} bus", bus);
+
+        // Well-known name -> whether it has an owner after the latest change
+        let mut pending: HashMap<String, bool> = HashMap::new();
+        // Counted from the first pending change, so a steady stream of
+        // changes cannot hold the refresh back indefinitely
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let signal = match deadline {
+                None => changes.next().await,
+                Some(at) => match tokio::time::timeout_at(at, changes.next()).await {
+                    Ok(signal) => signal,
+                    Err(_) => {
+                        deadline = None;
+                        self.apply(bus, std::mem::take(&mut pending)).await;
+                        continue;
+                    }
+                },
+            };
+            let Some(signal) = signal else {
+                return Ok(());
+            };
+
+            let args = signal.args()?;
+            let name = args.name().to_string();
+            if name.starts_with(':') {
+                continue;
+            }
+            debug!("NameOwnerChanged on {:?}: {}", bus, name);
+            pending.insert(name, args.new_owner().is_some());
+            deadline.get_or_insert_with(|| Instant::now() + debounce);
+        }
+    }
+
+    async fn apply(&self, bus: Bus, pending: HashMap<String, bool>) {
+        let services = |owned: bool| -> Vec<&str> {
+            pending
+                .iter()
+                .filter(|(_, has_owner)| **has_owner == owned)
+                .map(|(service, _)| service.as_str())
+                .collect()
+        };
+        let (owned, gone) = (services(true), services(false));
+        if !gone.is_empty() {
+            self.remove_services(bus, &gone).await;
+        }
+        if !owned.is_empty() {
+            if let Err(e) = self.reproject(bus, &owned).await {
+                warn!("Re-projecting {} on {:?} failed: {}", owned.join(", "), bus, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tools_are_attributed_to_their_service() {
+        let tool = ToolDefinition {
+            name: "dbus_org_freedesktop_networkmanager_get_devices".to_string(),
+            description: "GetDevices on /org/freedesktop/NetworkManager".to_string(),
+            input_schema: json!({ "type": "object" }),
+            schema_version: String::new(),
+            category: String::new(),
+            tags: Vec::new(),
+            namespace: String::new(),
+        };
+        assert!(belongs_to(&tool, "org.freedesktop.NetworkManager"));
+        assert!(!belongs_to(&tool, "org.freedesktop.login1"));
+    }
+}
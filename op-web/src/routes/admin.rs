@@ -4,18 +4,23 @@
 //! - Viewing system prompt (fixed + custom parts)
 //! - Editing custom prompt part
 //! - Testing prompt changes
+//! - Managing the agents MCP roster
+//! - Re-projecting D-Bus tools
+//! - Managing API keys
 
 use axum::{
     extract::Extension,
     http::StatusCode,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{info, error};
 
+use crate::handlers;
+use crate::registry_refresh::RefreshRequest;
 use crate::AppState;
 
 /// Create admin routes
@@ -27,6 +32,18 @@ pub fn admin_routes() -> Router {
         .route("/prompt/test", post(test_prompt))
         .route("/prompt/reload", post(reload_prompt))
         .route("/config", get(get_config))
+        .route(
+            "/agents/roster",
+            get(crate::mcp_agents::roster_list_handler).post(crate::mcp_agents::roster_add_handler),
+        )
+        .route("/agents/roster/:name", delete(crate::mcp_agents::roster_remove_handler))
+        .route("/registry/refresh", post(refresh_registry))
+        .route(
+            "/api-keys",
+            get(handlers::api_keys::list_api_keys_handler).post(handlers::api_keys::create_api_key_handler),
+        )
+        .route("/api-keys/:id", delete(handlers::api_keys::revoke_api_key_handler))
+        .route("/api-keys/:id/rotate", post(handlers::api_keys::rotate_api_key_handler))
 }
 
 // =============================================================================
@@ -260,3 +277,31 @@ async fn get_config(
         tool_count,
     })
 }
+
+/// POST /admin/registry/refresh - Re-project D-Bus tools of a bus or one service
+async fn refresh_registry(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(request): Json<RefreshRequest>,
+) -> impl IntoResponse {
+    let service = request.service.as_deref();
+    match state.registry_refresh.refresh(request.bus, service).await {
+        Ok(report) => (
+            StatusCode::OK,
+            Json(simd_json::json!({
+                "success": true,
+                "bus": request.bus,
+                "service": service,
+                "added": report.added,
+                "updated": report.updated,
+                "removed": report.removed
+            })),
+        ),
+        Err(e) => {
+            error!("Registry refresh failed: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(simd_json::json!({ "success": false, "error": e.to_string() })),
+            )
+        }
+    }
+}
@@ -18,6 +18,7 @@ use crate::mcp_agents;
 use crate::mcp_discovery;
 use crate::groups_admin;
 use crate::middleware::security;
+use crate::openapi;
 use crate::sse;
 use crate::state::AppState;
 use crate::websocket;
@@ -52,6 +53,7 @@ pub fn create_router(state: Arc<AppState>) -> Router {
         // Health & Status
         .route("/health", get(handlers::health::health_handler))
         .route("/status", get(handlers::status::status_handler))
+        .route("/openapi.json", get(openapi::openapi_handler))
         // Chat endpoints
         .route("/chat", post(handlers::chat::chat_handler))
         .route("/chat/stream", post(handlers::chat::chat_stream_handler))
@@ -59,9 +61,31 @@ pub fn create_router(state: Arc<AppState>) -> Router {
         .route("/chat/transcript", post(handlers::chat::save_transcript_handler))
         // Tool endpoints
         .route("/tools", get(handlers::tools::list_tools_handler))
+        .route("/tools/batch", post(handlers::tools::batch_tools_handler))
         .route("/tools/:name", get(handlers::tools::get_tool_handler))
         .route("/tool", post(handlers::tools::execute_tool_handler))
         .route("/tools/:name/execute", post(handlers::tools::execute_named_tool_handler))
+        .route("/tools/:name/stream", post(handlers::tools::stream_tool_handler))
+        // Composite tool macros
+        .route(
+            "/macros",
+            get(handlers::macros::list_macros_handler).post(handlers::macros::save_macro_handler),
+        )
+        .route(
+            "/macros/:name",
+            get(handlers::macros::get_macro_handler)
+                .put(handlers::macros::update_macro_handler)
+                .delete(handlers::macros::delete_macro_handler),
+        )
+        // Background jobs
+        .route("/jobs", get(handlers::jobs::list_jobs_handler).post(handlers::jobs::submit_job_handler))
+        .route("/jobs/:id", get(handlers::jobs::get_job_handler).delete(handlers::jobs::cancel_job_handler))
+        .route("/audit", get(handlers::audit::audit_handler))
+        .route(
+            "/cache",
+            get(handlers::tools::cache_stats_handler).delete(handlers::tools::clear_cache_handler),
+        )
+        .route("/limits", get(handlers::tools::limits_handler))
         // Agent endpoints
         .route("/agents", get(handlers::agents::list_agents_handler))
         .route("/agents", post(handlers::agents::spawn_agent_handler))
@@ -0,0 +1,566 @@
+//! Scope Policies - what `file_*` and `shell_*` tools may touch
+//!
+//! Policies are declared in `/etc/op-dbus/tool-scope.json` and selected per
+//! caller: by authenticated API key name, then tool profile,
+//! then access zone, then `default`. Callers no rule selects are unrestricted.
+//!
+//! ```json
+//! {
+//!   "policies": {
+//!     "restricted": {
+//!       "paths": {
+//!         "read":  { "allow": ["/etc/**", "/var/log/**"], "deny": ["/etc/shadow"] },
+//!         "write": { "allow": ["/tmp/**"] }
+//!       },
+//!       "commands": { "allow": ["systemctl", "ip", "ovs-vsctl"] },
+//!       "env": { "keep": ["LANG"], "set": { "PATH": "/usr/sbin:/usr/bin" } }
+//!     }
+//!   },
+//!   "select": {
+//!     "api_keys": { "ci-runner": "restricted" },
+//!     "profiles": { "readonly": "restricted" },
+//!     "zones": { "Public": "restricted" },
+//!     "default": null
+//!   }
+//! }
+//! ```
+//!
+//! Globs: `*` within one path segment, `**` across segments, `?` one
+//! character. Deny wins over allow; an empty allow list allows everything not
+//! denied. Paths are normalized (`..`, symlinks of existing parents) before
+//! matching. Path arguments are found by name (`path`, `*_dir`, `source`, ...)
+//! and by the tool's input schema (properties with `"format": "path"` or
+//! described as a path or directory), at any depth; a `file_*` call with no
+//! path to check is refused. Under a command rule a `shell_*` call must carry
+//! its command as a string, and the command line must be a single
+//! simple command: any character outside `[A-Za-z0-9 _./=:,@+-]` (separators,
+//! `&`, redirections, substitutions, quotes, globs) is refused, and the
+//! program must be allowed. Environment variables are filtered in the `env`
+//! argument and in leading `VAR=value` assignments.
+//!
+//! Checked by [`crate::executor::ToolExecutor::begin`], so every execution
+//! path enforces the policy and a violation is an audited failure.
+
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::audit::Caller;
+
+const TOOL_SCOPE_CONFIG_PATH: &str = "/etc/op-dbus/tool-scope.json";
+
+const FILE_TOOL_PREFIX: &str = "file_";
+const SHELL_TOOL_PREFIX: &str = "shell_";
+
+/// `file_*` name segments that only read
+const READ_OPERATIONS: &[&str] = &["read", "list", "stat", "exists", "search", "find", "info", "hash", "tail", "head"];
+
+/// Argument keys holding paths (besides keys ending in `path`, `dir` or `file`)
+const PATH_KEYS: &[&str] = &["source", "destination", "src", "dst", "from", "to", "target", "directory", "cwd"];
+
+/// Argument keys holding a shell command line
+const COMMAND_KEYS: &[&str] = &["command", "cmd", "script"];
+
+const ENV_KEY: &str = "env";
+
+/// Policy applied when the selected one is missing or the config is invalid
+const DENY_ALL: &str = "deny-all";
+
+/// Always dropped from the environment under a policy
+const UNSAFE_ENV: &[&str] = &["LD_PRELOAD", "LD_LIBRARY_PATH", "LD_AUDIT", "BASH_ENV", "ENV", "PROMPT_COMMAND", "IFS"];
+
+/// Punctuation a command line may use under a command rule; everything else
+/// the shell could interpret is refused
+const COMMAND_PUNCTUATION: &[char] = &[' ', '_', '.', '/', '=', ':', ',', '@', '+', '-'];
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct GlobRule {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl GlobRule {
+    fn permits(&self, candidate: &str) -> bool {
+        !self.deny.iter().any(|g| glob_match(g, candidate))
+            && (self.allow.is_empty() || self.allow.iter().any(|g| glob_match(g, candidate)))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PathRules {
+    #[serde(default)]
+    pub read: Option<GlobRule>,
+    #[serde(default)]
+    pub write: Option<GlobRule>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EnvRules {
+    /// Caller-supplied variables to keep; empty keeps all but the unsafe ones
+    #[serde(default)]
+    pub keep: Vec<String>,
+    /// Variables forced into the `env` argument
+    #[serde(default)]
+    pub set: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ScopePolicy {
+    #[serde(default)]
+    pub paths: PathRules,
+    /// Programs, matched as globs against the program's file name
+    #[serde(default)]
+    pub commands: Option<GlobRule>,
+    #[serde(default)]
+    pub env: Option<EnvRules>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PolicySelection {
+    #[serde(default)]
+    pub api_keys: HashMap<String, String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, String>,
+    #[serde(default)]
+    pub zones: HashMap<String, String>,
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// `/etc/op-dbus/tool-scope.json`
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ScopeConfig {
+    #[serde(default)]
+    pub policies: HashMap<String, ScopePolicy>,
+    #[serde(default)]
+    pub select: PolicySelection,
+}
+
+impl ScopeConfig {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(TOOL_SCOPE_CONFIG_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<ScopeConfig>(&mut raw) } {
+            Ok(config) => {
+                info!("Loaded {} tool scope policies from {}", config.policies.len(), TOOL_SCOPE_CONFIG_PATH);
+                config
+            }
+            Err(e) => {
+                // Fail closed: an unreadable policy file must not lift restrictions
+                warn!("Invalid tool scope config {}: {}, file and shell tools are denied", TOOL_SCOPE_CONFIG_PATH, e);
+                Self::deny_all()
+            }
+        }
+    }
+
+    fn deny_all() -> Self {
+        Self {
+            policies: HashMap::from([(DENY_ALL.to_string(), ScopePolicy::deny_all())]),
+            select: PolicySelection { default: Some(DENY_ALL.to_string()), ..Default::default() },
+        }
+    }
+}
+
+impl ScopePolicy {
+    /// No paths, no commands
+    fn deny_all() -> Self {
+        let nothing = GlobRule { allow: Vec::new(), deny: vec!["**".to_string()] };
+        Self {
+            paths: PathRules { read: Some(nothing.clone()), write: Some(nothing.clone()) },
+            commands: Some(nothing),
+            env: None,
+        }
+    }
+}
+
+/// Whether a tool falls under scope policies
+pub fn is_scoped(tool_name: &str) -> bool {
+    tool_name.starts_with(FILE_TOOL_PREFIX) || tool_name.starts_with(SHELL_TOOL_PREFIX)
+}
+
+pub struct ScopePolicies {
+    config: ScopeConfig,
+    deny_all: ScopePolicy,
+}
+
+impl ScopePolicies {
+    /// Policies from `/etc/op-dbus/tool-scope.json`
+    pub fn load() -> Self {
+        Self::new(ScopeConfig::load())
+    }
+
+    pub fn new(config: ScopeConfig) -> Self {
+        Self { config, deny_all: ScopePolicy::deny_all() }
+    }
+
+    /// Name and policy that apply to `caller`
+    pub fn select(&self, caller: &Caller) -> Option<(&str, &ScopePolicy)> {
+        let select = &self.config.select;
+        let name = caller
+            .api_key
+            .as_ref()
+            .and_then(|k| select.api_keys.get(k))
+            .or_else(|| caller.profile.as_ref().and_then(|p| select.profiles.get(p)))
+            // Like the access policy, an unidentified caller is `Public`
+            .or_else(|| select.zones.get(caller.zone.as_deref().unwrap_or("Public")))
+            .or(select.default.as_ref())?;
+
+        match self.config.policies.get_key_value(name) {
+            Some((name, policy)) => Some((name.as_str(), policy)),
+            None => {
+                warn!("Scope policy '{}' is selected but not defined; denying", name);
+                Some((DENY_ALL, &self.deny_all))
+            }
+        }
+    }
+
+    /// Whether a policy governs `tool_name` for `caller`
+    pub fn governs(&self, tool_name: &str, caller: &Caller) -> bool {
+        is_scoped(tool_name) && self.select(caller).is_some()
+    }
+
+    /// Check `arguments` against the caller's policy, sanitizing the
+    /// environment in place; `schema` is the tool's input schema, when known.
+    /// The error names the policy and the violation.
+    pub fn enforce(
+        &self,
+        tool_name: &str,
+        schema: Option<&Value>,
+        caller: &Caller,
+        arguments: &mut Value,
+    ) -> Result<(), String> {
+        if !is_scoped(tool_name) {
+            return Ok(());
+        }
+        let Some((name, policy)) = self.select(caller) else {
+            return Ok(());
+        };
+
+        let checked = if tool_name.starts_with(FILE_TOOL_PREFIX) {
+            check_paths(policy, tool_name, schema, arguments)
+        } else {
+            check_command(policy, arguments).map(|()| sanitize_env(policy, arguments))
+        };
+        checked.map_err(|violation| {
+            warn!("Scope policy '{}' denied {}: {}", name, tool_name, violation);
+            format!("Policy violation ({}): {}", name, violation)
+        })
+    }
+}
+
+fn check_paths(policy: &ScopePolicy, tool_name: &str, schema: Option<&Value>, arguments: &Value) -> Result<(), String> {
+    let reads = tool_name
+        .trim_start_matches(FILE_TOOL_PREFIX)
+        .split('_')
+        .all(|segment| READ_OPERATIONS.contains(&segment));
+    let (operation, rule) = if reads {
+        ("read", &policy.paths.read)
+    } else {
+        ("write", &policy.paths.write)
+    };
+    let Some(rule) = rule else {
+        return Ok(());
+    };
+
+    let mut paths = Vec::new();
+    collect_paths(arguments, schema, &mut paths);
+    if paths.is_empty() {
+        return Err(format!("no path argument to check for {} access", operation));
+    }
+    for path in paths {
+        let normalized = normalize(Path::new(&path));
+        let shown = normalized.to_string_lossy();
+        if !normalized.is_absolute() || !rule.permits(&shown) {
+            return Err(format!("{} access to '{}' is not allowed", operation, shown));
+        }
+    }
+    Ok(())
+}
+
+/// Values of path arguments in `arguments`, at any depth, walking `schema`
+/// alongside
+fn collect_paths(arguments: &Value, schema: Option<&Value>, out: &mut Vec<String>) {
+    if let Some(items) = arguments.as_array() {
+        let item_schema = schema.and_then(|s| s.get("items"));
+        for item in items.iter() {
+            collect_paths(item, item_schema, out);
+        }
+        return;
+    }
+    let Some(obj) = arguments.as_object() else {
+        return;
+    };
+    let properties = schema.and_then(|s| s.get("properties"));
+    for (key, value) in obj.iter() {
+        let property = properties.and_then(|p| p.get(key.as_str()));
+        if is_path_key(key) || property.is_some_and(is_path_property) {
+            if let Some(s) = value.as_str() {
+                out.push(s.to_string());
+            } else if let Some(items) = value.as_array() {
+                out.extend(items.iter().filter_map(|i| i.as_str()).map(String::from));
+            }
+        } else {
+            collect_paths(value, property, out);
+        }
+    }
+}
+
+fn is_path_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    PATH_KEYS.contains(&key.as_str()) || key.ends_with("path") || key.ends_with("dir") || key.ends_with("file")
+}
+
+/// Schema property declared or described as a path
+fn is_path_property(property: &Value) -> bool {
+    if property.get("format").and_then(|f| f.as_str()) == Some("path") {
+        return true;
+    }
+    let description = property
+        .get("description")
+        .and_then(|d| d.as_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    description.contains("path") || description.contains("directory")
+}
+
+/// Absolute, `..`-free form of `path`, following symlinks of the longest
+/// existing prefix
+fn normalize(path: &Path) -> PathBuf {
+    let mut lexical = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            Component::CurDir => {}
+            other => lexical.push(other),
+        }
+    }
+
+    let mut existing = lexical.as_path();
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(real) = std::fs::canonicalize(existing) {
+            return rest.iter().rev().fold(real, |acc, part| acc.join(part));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return lexical,
+        }
+    }
+}
+
+fn check_command(policy: &ScopePolicy, arguments: &Value) -> Result<(), String> {
+    let Some(rule) = &policy.commands else {
+        return Ok(());
+    };
+    let Some(obj) = arguments.as_object() else {
+        return Err("arguments must be an object".to_string());
+    };
+
+    // Only a command this check understood may run
+    let mut recognized = false;
+    for key in COMMAND_KEYS {
+        let Some(value) = obj.get(*key) else {
+            continue;
+        };
+        let Some(line) = value.as_str() else {
+            return Err(format!("'{}' must be a string", key));
+        };
+        if let Some(ch) = line.chars().find(|ch| !ch.is_ascii_alphanumeric() && !COMMAND_PUNCTUATION.contains(ch)) {
+            return Err(format!("shell metacharacter {:?} is not allowed", ch));
+        }
+        let Some(program) = program_of(line, policy)? else {
+            continue;
+        };
+        if !rule.permits(&program) {
+            return Err(format!("command '{}' is not allowed", program));
+        }
+        recognized = true;
+    }
+    // Separate program argument (`{"program": "ip", "args": [...]}`)
+    if let Some(value) = obj.get("program") {
+        let Some(program) = value.as_str() else {
+            return Err("'program' must be a string".to_string());
+        };
+        let program = base_name(program);
+        if !rule.permits(&program) {
+            return Err(format!("command '{}' is not allowed", program));
+        }
+        recognized = true;
+    }
+    if !recognized {
+        return Err("no command to check".to_string());
+    }
+    Ok(())
+}
+
+/// Program of one command, after checking leading `VAR=value` assignments
+fn program_of(segment: &str, policy: &ScopePolicy) -> Result<Option<String>, String> {
+    for token in segment.split_whitespace() {
+        match token.split_once('=') {
+            Some((var, _)) if !var.is_empty() && !var.contains('/') => {
+                if !env_permitted(policy, var) {
+                    return Err(format!("environment variable '{}' is not allowed", var));
+                }
+            }
+            _ => return Ok(Some(base_name(token))),
+        }
+    }
+    Ok(None)
+}
+
+fn base_name(program: &str) -> String {
+    Path::new(program)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| program.to_string())
+}
+
+fn env_permitted(policy: &ScopePolicy, var: &str) -> bool {
+    if UNSAFE_ENV.contains(&var) {
+        return false;
+    }
+    match &policy.env {
+        Some(env) if !env.keep.is_empty() => env.keep.iter().any(|k| k == var),
+        _ => true,
+    }
+}
+
+/// Drop disallowed variables from the `env` argument and apply `set`
+fn sanitize_env(policy: &ScopePolicy, arguments: &mut Value) {
+    let Some(obj) = arguments.as_object_mut() else {
+        return;
+    };
+
+    let mut env = json!({});
+    if let (Some(given), Some(out)) = (obj.get(ENV_KEY).and_then(|e| e.as_object()), env.as_object_mut()) {
+        for (var, value) in given.iter().filter(|(var, _)| env_permitted(policy, var)) {
+            out.insert(var.clone(), value.clone());
+        }
+    }
+    let forced = policy.env.as_ref().map(|e| &e.set);
+    if let (Some(forced), Some(out)) = (forced, env.as_object_mut()) {
+        for (var, value) in forced {
+            out.insert(var.clone(), json!(value.as_str()));
+        }
+    }
+
+    let has_env = env.as_object().is_some_and(|e| !e.is_empty());
+    if has_env || obj.contains_key(ENV_KEY) {
+        obj.insert(ENV_KEY.into(), env);
+    }
+}
+
+/// Glob match where `*` and `?` stay within a path segment and `**` spans segments
+pub(crate) fn glob_match(pattern: &str, candidate: &str) -> bool {
+    fn matches(p: &[u8], c: &[u8]) -> bool {
+        match p.first() {
+            None => c.is_empty(),
+            Some(b'*') if p.get(1) == Some(&b'*') => {
+                let rest = p[2..].strip_prefix(b"/").unwrap_or(&p[2..]);
+                (0..=c.len()).any(|i| matches(rest, &c[i..])) || matches(&p[2..], c)
+            }
+            Some(b'*') => (0..=c.len())
+                .take_while(|&i| i == 0 || c[i - 1] != b'/')
+                .any(|i| matches(&p[1..], &c[i..])),
+            Some(b'?') => c.first().is_some_and(|&ch| ch != b'/') && matches(&p[1..], &c[1..]),
+            Some(&ch) => c.first() == Some(&ch) && matches(&p[1..], &c[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), candidate.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policies() -> ScopePolicies {
+        let mut raw = r#"{
+            "policies": {
+                "restricted": {
+                    "paths": {
+                        "read": { "allow": ["/etc/**"], "deny": ["/etc/shadow"] },
+                        "write": { "allow": ["/tmp/op-web-scope-test/**"] }
+                    },
+                    "commands": { "allow": ["systemctl", "ip"] },
+                    "env": { "set": { "PATH": "/usr/bin" } }
+                }
+            },
+            "select": { "zones": { "Public": "restricted" } }
+        }"#
+        .to_string();
+        ScopePolicies::new(unsafe { simd_json::from_str(&mut raw) }.unwrap())
+    }
+
+    fn public() -> Caller {
+        Caller { source: "api".to_string(), zone: Some("Public".to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_glob_semantics() {
+        assert!(glob_match("/etc/**", "/etc/ssh/sshd_config"));
+        assert!(glob_match("/var/log/*.log", "/var/log/syslog.log"));
+        assert!(!glob_match("/var/log/*.log", "/var/log/nginx/access.log"));
+        assert!(glob_match("/var/**/access.log", "/var/log/nginx/access.log"));
+    }
+
+    #[test]
+    fn test_paths_and_commands_are_enforced() {
+        let scope = policies();
+        let caller = public();
+
+        assert!(scope.enforce("file_read", None, &caller, &mut json!({ "path": "/etc/hostname" })).is_ok());
+        assert!(scope.enforce("file_read", None, &caller, &mut json!({ "path": "/etc/shadow" })).is_err());
+        assert!(scope.enforce("file_read", None, &caller, &mut json!({ "path": "/etc/../root/.ssh/id_rsa" })).is_err());
+        assert!(scope.enforce("file_write", None, &caller, &mut json!({ "path": "/etc/hostname" })).is_err());
+
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "ip addr show | grep inet" })).is_err());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "systemctl status nginx" })).is_ok());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "ip link && rm -rf /" })).is_err());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "ip link & rm -rf /" })).is_err());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "ip addr > /etc/shadow" })).is_err());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "ip -f <(cat /etc/shadow)" })).is_err());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "LD_PRELOAD=/tmp/x.so ip link" })).is_err());
+
+        let mut exec = json!({ "command": "ip link", "env": { "LD_PRELOAD": "/tmp/x.so", "LANG": "C" } });
+        scope.enforce("shell_exec", None, &caller, &mut exec).unwrap();
+        assert_eq!(exec.get("env"), Some(&json!({ "LANG": "C", "PATH": "/usr/bin" })));
+
+        // Callers no rule selects are unrestricted
+        let trusted = Caller { zone: Some("TrustedMesh".to_string()), ..public() };
+        assert!(scope.enforce("file_write", None, &trusted, &mut json!({ "path": "/etc/hostname" })).is_ok());
+    }
+
+    #[test]
+    fn test_unrecognized_arguments_are_refused() {
+        let scope = policies();
+        let caller = public();
+
+        // A command the check cannot see is not run
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": ["rm", "-rf", "/"] })).is_err());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "argv": "rm -rf /" })).is_err());
+        assert!(scope.enforce("shell_exec", None, &caller, &mut json!({ "command": "FOO=1" })).is_err());
+
+        // Paths found through the schema and in nested arguments
+        let schema = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string", "description": "Absolute path of the file" } }
+        });
+        let mut renamed = json!({ "name": "/etc/shadow" });
+        assert!(scope.enforce("file_read", Some(&schema), &caller, &mut renamed).is_err());
+        let mut nested = json!({ "files": [{ "target": "/etc/shadow" }] });
+        assert!(scope.enforce("file_read", None, &caller, &mut nested).is_err());
+        assert!(scope.enforce("file_read", None, &caller, &mut json!({ "name": "/etc/shadow" })).is_err());
+    }
+}
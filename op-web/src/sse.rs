@@ -31,7 +31,6 @@ impl SseEventBroadcaster {
         Self { tx }
     }
 
-    #[allow(dead_code)]
     pub fn broadcast(&self, event_type: &str, data: &str) {
         let _ = self.tx.send(SseEvent {
             event_type: event_type.to_string(),
@@ -19,6 +19,17 @@ use crate::orchestrator::UnifiedOrchestrator;
 use crate::sse::SseEventBroadcaster;
 use crate::users::UserStore;
 use crate::email::{EmailConfig, EmailSender};
+use crate::access_policy::{self, AccessPolicy};
+use crate::api_keys::ApiKeyStore;
+use crate::audit::AuditLog;
+use crate::executor::ToolExecutor;
+use crate::jobs::JobManager;
+use crate::macros::MacroManager;
+use crate::tool_cache::ToolCache;
+use crate::tool_limits::ToolLimiter;
+use crate::mcp_upstream::UpstreamManager;
+use crate::registry_refresh::RegistryRefresher;
+use crate::scope_policy::ScopePolicies;
 use crate::wireguard::WgServerConfig;
 
 /// Google OAuth configuration
@@ -86,12 +97,37 @@ pub struct AppState {
     pub state_store: Arc<dyn StateStore>,
     /// Google OAuth configuration (optional)
     pub google_oauth_config: Option<GoogleOAuthConfig>,
+    /// External MCP servers whose tools are imported into the registry
+    pub upstreams: Arc<UpstreamManager>,
+    /// Tracked tool executions and background jobs
+    pub jobs: Arc<JobManager>,
+    /// Execution audit trail
+    pub audit: Arc<AuditLog>,
+    /// Audited path for every tool execution
+    pub executor: Arc<ToolExecutor>,
+    /// Composite tools defined by admins
+    pub macros: Arc<MacroManager>,
+    /// Re-projects D-Bus tools as services come and go
+    pub registry_refresh: Arc<RegistryRefresher>,
+    /// Managed API keys
+    pub api_keys: Arc<ApiKeyStore>,
 }
 
 impl AppState {
     /// Create new AppState with an optional shared tool registry
     /// If registry is provided, skips tool discovery (use registry from main binary)
     pub async fn new_with_registry(tool_registry: Option<Arc<ToolRegistry>>) -> anyhow::Result<Self> {
+        Self::build(tool_registry, true).await
+    }
+
+    /// AppState over `tool_registry` that touches nothing on the host: stores
+    /// are in memory, no config is read from `/etc/op-dbus`, and neither
+    /// upstream MCP servers nor the D-Bus registry refresher are started
+    pub async fn in_memory(tool_registry: Arc<ToolRegistry>) -> anyhow::Result<Self> {
+        Self::build(Some(tool_registry), false).await
+    }
+
+    async fn build(tool_registry: Option<Arc<ToolRegistry>>, persistent: bool) -> anyhow::Result<Self> {
         info!("Initializing application state...");
 
         let tool_registry = if let Some(registry) = tool_registry {
@@ -112,11 +148,18 @@ impl AppState {
         info!("✅ Using registry with {} tools", tools.len());
         log_tool_summary(&tools);
 
+        // Import tools from external MCP servers (connects in the background)
+        let upstreams = if persistent {
+            UpstreamManager::start(tool_registry.clone())
+        } else {
+            Arc::new(UpstreamManager::default())
+        };
+
         // Create chat manager for LLM access
         let chat_manager = Arc::new(ChatManager::new());
 
         // Load persisted provider/model
-        if let Some(provider) = read_persisted_provider().await {
+        if let Some(provider) = read_persisted_provider().await.filter(|_| persistent) {
             if let Ok(provider_type) = provider.parse() {
                 if let Err(e) = chat_manager.switch_provider(provider_type).await {
                     warn!("Failed to load provider '{}': {}", provider, e);
@@ -126,7 +169,7 @@ impl AppState {
             }
         }
 
-        if let Some(model) = read_persisted_model().await {
+        if let Some(model) = read_persisted_model().await.filter(|_| persistent) {
             if let Err(e) = chat_manager.switch_model(model.clone()).await {
                 warn!("Failed to load model '{}': {}", model, e);
             } else {
@@ -141,21 +184,81 @@ impl AppState {
 
         info!("✅ LLM: {} ({})", provider_name, default_model);
 
+        // Model-assisted agents fall back to this when clients cannot sample
+        crate::mcp_sampling::set_fallback(chat_manager.clone());
+
         // Create agent registry
         let agent_registry = Arc::new(RwLock::new(AgentRegistry::new()));
 
+        // Create SSE broadcaster
+        let sse_broadcaster = Arc::new(SseEventBroadcaster::new());
+
+        // Initialize State Store
+        let state_store_path = if persistent { "/var/lib/op-dbus/state.db" } else { ":memory:" };
+        let state_store: Arc<dyn StateStore> = match SqliteStore::new(state_store_path).await {
+            Ok(store) => Arc::new(store),
+            Err(e) => {
+                warn!("Failed to initialize state store at {}: {}, using in-memory", state_store_path, e);
+                // Fallback to in-memory if file access fails
+                Arc::new(SqliteStore::new(":memory:").await
+                    .expect("Failed to create in-memory state store"))
+            }
+        };
+
+        // API keys and the access policy are checked by the security
+        // middleware, outside AppState
+        let api_keys = Arc::new(if persistent { ApiKeyStore::open().await } else { ApiKeyStore::open_at(None).await });
+        crate::api_keys::install(api_keys.clone());
+        let access = Arc::new(if persistent { AccessPolicy::load() } else { AccessPolicy::new(Default::default()) });
+        access_policy::install(access.clone());
+
+        // Every tool execution is tracked as a job and audited
+        let jobs = Arc::new(JobManager::new(state_store.clone(), sse_broadcaster.clone()));
+        let audit = Arc::new(if persistent { AuditLog::open().await } else { AuditLog::open_at(None).await });
+        let (tool_cache, limiter, scopes) = if persistent {
+            (ToolCache::load(), ToolLimiter::load(), ScopePolicies::load())
+        } else {
+            (
+                ToolCache::new(Default::default()),
+                ToolLimiter::new(Default::default()),
+                ScopePolicies::new(Default::default()),
+            )
+        };
+        let executor = Arc::new(ToolExecutor::new(
+            tool_registry.clone(),
+            jobs.clone(),
+            audit.clone(),
+            Arc::new(tool_cache),
+            Arc::new(limiter),
+            Arc::new(scopes),
+            access,
+        ));
+        crate::executor::install(executor.clone());
+
+        // Composite tools (registered after every tool they may call)
+        let macros = Arc::new(if persistent {
+            MacroManager::load(tool_registry.clone(), sse_broadcaster.clone()).await
+        } else {
+            MacroManager::builtin(tool_registry.clone(), sse_broadcaster.clone()).await
+        });
+        macros.watch();
+
+        // Keep projected D-Bus tools current (watchers only when configured)
+        let registry_refresh = Arc::new(RegistryRefresher::new(tool_registry.clone(), sse_broadcaster.clone()));
+        if persistent {
+            registry_refresh.start();
+        }
+
         // Create orchestrator with direct tool access
         let orchestrator = Arc::new(UnifiedOrchestrator::new(
             tool_registry.clone(),
             chat_manager.clone(),
+            executor.clone(),
         ));
 
         // Create broadcast channel for WebSocket
         let (broadcast_tx, _) = broadcast::channel(100);
 
-        // Create SSE broadcaster
-        let sse_broadcaster = Arc::new(SseEventBroadcaster::new());
-
         // Initialize privacy router components
         let user_store = match UserStore::new("/var/lib/op-dbus/privacy-users.json").await {
             Ok(store) => Arc::new(store),
@@ -192,18 +295,6 @@ impl AppState {
             info!("⚠️  Google OAuth not configured (set GOOGLE_OAUTH_CLIENT_ID and GOOGLE_OAUTH_CLIENT_SECRET)");
         }
 
-        // Initialize State Store
-        let state_store_path = "/var/lib/op-dbus/state.db";
-        let state_store: Arc<dyn StateStore> = match SqliteStore::new(state_store_path).await {
-            Ok(store) => Arc::new(store),
-            Err(e) => {
-                warn!("Failed to initialize state store at {}: {}, using in-memory", state_store_path, e);
-                // Fallback to in-memory if file access fails
-                Arc::new(SqliteStore::new(":memory:").await
-                    .expect("Failed to create in-memory state store"))
-            }
-        };
-
         info!("✅ Application state initialized");
 
         Ok(Self {
@@ -222,6 +313,13 @@ impl AppState {
             server_config,
             state_store,
             google_oauth_config,
+            upstreams,
+            jobs,
+            audit,
+            executor,
+            macros,
+            registry_refresh,
+            api_keys,
         })
     }
 
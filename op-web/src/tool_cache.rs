@@ -0,0 +1,320 @@
+//! Tool Result Cache - TTL cache for read-only tools
+//!
+//! Opt-in per tool: a TTL comes from `"x-cache-ttl"` (seconds) in the tool's
+//! input schema, or from `/etc/op-dbus/tool-cache.json`:
+//!
+//! ```json
+//! {
+//!   "tools": { "ovs_list_bridges": 5, "dbus_systemd_list_units": 10 },
+//!   "read_only_ttl_secs": 0,
+//!   "max_entries": 1000
+//! }
+//! ```
+//!
+//! `read_only_ttl_secs` caches every tool with a read verb in its name
+//! (`list`, `get`, `show`, ...). Entries are keyed by tool name and the
+//! canonicalized arguments. Concurrent identical calls share one execution,
+//! and any mutating tool in the same domain (namespace) drops that domain's
+//! entries. Callers bypass the cache with `Cache-Control: no-cache`.
+
+use op_tools::registry::ToolDefinition;
+use serde::Deserialize;
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+
+const TOOL_CACHE_CONFIG_PATH: &str = "/etc/op-dbus/tool-cache.json";
+
+/// Name segments that mark a tool as read-only
+const READ_VERBS: &[&str] = &[
+    "list", "get", "show", "status", "read", "describe", "query", "search", "info", "stat",
+];
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+/// `/etc/op-dbus/tool-cache.json`
+#[derive(Debug, Deserialize)]
+pub struct CacheConfig {
+    /// TTL in seconds per tool
+    #[serde(default)]
+    pub tools: HashMap<String, u64>,
+    /// TTL for every read-only tool; 0 disables
+    #[serde(default)]
+    pub read_only_ttl_secs: u64,
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            tools: HashMap::new(),
+            read_only_ttl_secs: 0,
+            max_entries: default_max_entries(),
+        }
+    }
+}
+
+impl CacheConfig {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(TOOL_CACHE_CONFIG_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<CacheConfig>(&mut raw) } {
+            Ok(config) => {
+                info!("Loaded tool cache config from {} ({} tools)", TOOL_CACHE_CONFIG_PATH, config.tools.len());
+                config
+            }
+            Err(e) => {
+                warn!("Invalid tool cache config {}: {}, caching disabled", TOOL_CACHE_CONFIG_PATH, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Whether the tool name says it only reads (`ovs_list_bridges`)
+pub fn is_read_only(tool_name: &str) -> bool {
+    tool_name.split('_').any(|segment| READ_VERBS.contains(&segment))
+}
+
+/// Domain whose entries a mutating tool invalidates
+pub fn domain_of(tool: &ToolDefinition) -> String {
+    if tool.namespace.is_empty() {
+        tool.name.split('_').next().unwrap_or_default().to_string()
+    } else {
+        tool.namespace.clone()
+    }
+}
+
+/// Cache key: tool name plus arguments with object keys sorted
+pub fn cache_key(tool_name: &str, arguments: &Value) -> String {
+    let mut key = String::from(tool_name);
+    key.push(':');
+    write_canonical(arguments, &mut key);
+    key
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    if let Some(obj) = value.as_object() {
+        let mut keys: Vec<&String> = obj.keys().collect();
+        keys.sort();
+        out.push('{');
+        for (i, key) in keys.into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&simd_json::to_string(&json!(key.as_str())).unwrap_or_default());
+            out.push(':');
+            if let Some(item) = obj.get(key.as_str()) {
+                write_canonical(item, out);
+            }
+        }
+        out.push('}');
+    } else if let Some(items) = value.as_array() {
+        out.push('[');
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_canonical(item, out);
+        }
+        out.push(']');
+    } else {
+        out.push_str(&simd_json::to_string(value).unwrap_or_default());
+    }
+}
+
+struct Entry {
+    value: Value,
+    domain: String,
+    expires: Instant,
+}
+
+#[derive(Default)]
+struct Stats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+type InFlight = Arc<OnceCell<Result<Value, String>>>;
+
+pub struct ToolCache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<String, Entry>>,
+    inflight: Mutex<HashMap<String, InFlight>>,
+    stats: Stats,
+}
+
+impl ToolCache {
+    /// Cache configured from `/etc/op-dbus/tool-cache.json`
+    pub fn load() -> Self {
+        Self::new(CacheConfig::load())
+    }
+
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            inflight: Mutex::new(HashMap::new()),
+            stats: Stats::default(),
+        }
+    }
+
+    /// How long results of `tool` are cached, if at all
+    pub fn ttl(&self, tool: &ToolDefinition) -> Option<Duration> {
+        let secs = tool
+            .input_schema
+            .get("x-cache-ttl")
+            .and_then(|t| t.as_u64())
+            .or_else(|| self.config.tools.get(&tool.name).copied())
+            .or_else(|| {
+                (self.config.read_only_ttl_secs > 0 && is_read_only(&tool.name))
+                    .then_some(self.config.read_only_ttl_secs)
+            })?;
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
+    /// Fresh cached result for `key`
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => {
+                self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                self.stats.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => {
+                self.stats.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: String, domain: String, value: Value, ttl: Duration) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.config.max_entries {
+            entries.retain(|_, entry| entry.expires > now);
+        }
+        if entries.len() >= self.config.max_entries {
+            debug!("Tool cache full ({} entries), not caching {}", entries.len(), key);
+            return;
+        }
+        entries.insert(key, Entry { value, domain, expires: now + ttl });
+    }
+
+    /// Drop every entry in `domain`
+    pub fn invalidate_domain(&self, domain: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|_, entry| entry.domain != domain);
+        let dropped = before - entries.len();
+        if dropped > 0 {
+            debug!("Tool cache: dropped {} entries for domain {}", dropped, domain);
+            self.stats.invalidations.fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Run `fut` unless an identical call is already running, in which case
+    /// wait for and share its result
+    pub async fn single_flight<F>(&self, key: &str, fut: F) -> Result<Value, String>
+    where
+        F: Future<Output = Result<Value, String>>,
+    {
+        let cell = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(key) {
+                Some(cell) => {
+                    self.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                    cell.clone()
+                }
+                None => {
+                    let cell = InFlight::default();
+                    inflight.insert(key.to_string(), cell.clone());
+                    cell
+                }
+            }
+        };
+
+        let result = cell.get_or_init(|| fut).await.clone();
+
+        // Whoever finishes first retires the flight
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight.get(key).is_some_and(|current| Arc::ptr_eq(current, &cell)) {
+            inflight.remove(key);
+        }
+        result
+    }
+
+    pub fn stats(&self) -> Value {
+        let hits = self.stats.hits.load(Ordering::Relaxed);
+        let misses = self.stats.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        json!({
+            "entries": self.entries.lock().unwrap().len(),
+            "in_flight": self.inflight.lock().unwrap().len(),
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 },
+            "coalesced": self.stats.coalesced.load(Ordering::Relaxed),
+            "invalidations": self.stats.invalidations.load(Ordering::Relaxed),
+            "max_entries": self.config.max_entries,
+            "read_only_ttl_secs": self.config.read_only_ttl_secs,
+            "configured_tools": self.config.tools.len()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_cache_key_ignores_key_order() {
+        let a = json!({ "bridge": "br0", "options": { "b": 1, "a": [1, 2] } });
+        let b = json!({ "options": { "a": [1, 2], "b": 1 }, "bridge": "br0" });
+        assert_eq!(cache_key("ovs_list_ports", &a), cache_key("ovs_list_ports", &b));
+        assert_ne!(cache_key("ovs_list_ports", &a), cache_key("ovs_list_ports", &json!({ "bridge": "br1" })));
+    }
+
+    #[tokio::test]
+    async fn test_single_flight_shares_one_execution() {
+        let cache = ToolCache::new(CacheConfig::default());
+        let runs = AtomicUsize::new(0);
+        let run = || async {
+            runs.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(json!(["br0"]))
+        };
+
+        let (a, b) = tokio::join!(
+            cache.single_flight("ovs_list_bridges:{}", run()),
+            cache.single_flight("ovs_list_bridges:{}", run()),
+        );
+        assert_eq!(a, Ok(json!(["br0"])));
+        assert_eq!(a, b);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(cache.inflight.lock().unwrap().is_empty());
+    }
+}
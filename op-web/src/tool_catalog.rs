@@ -0,0 +1,159 @@
+//! Tool Catalog - one category taxonomy, filtering and facets
+//!
+//! Categories come from registry metadata (`ToolDefinition::category`), the
+//! same source the compact MCP meta-tools use, so `/api/tools`, the status
+//! endpoint and MCP agree. Tools registered without a category are "other".
+
+use op_tools::registry::ToolDefinition;
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use std::collections::BTreeMap;
+
+/// Category for tools registered without one
+pub const UNCATEGORIZED: &str = "other";
+
+/// Normalized category of a tool
+pub fn category_of(tool: &ToolDefinition) -> String {
+    let category = tool.category.trim().to_lowercase();
+    if category.is_empty() {
+        UNCATEGORIZED.to_string()
+    } else {
+        category
+    }
+}
+
+/// Tool count per category
+pub fn count_by_category(tools: &[ToolDefinition]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for tool in tools {
+        *counts.entry(category_of(tool)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// REST view of a tool
+pub fn tool_summary(tool: &ToolDefinition) -> Value {
+    json!({
+        "name": tool.name.clone(),
+        "description": tool.description.clone(),
+        "category": category_of(tool),
+        "namespace": tool.namespace.clone(),
+        "tags": tool.tags.clone(),
+        "schema_version": tool.schema_version.clone()
+    })
+}
+
+/// Query for `GET /api/tools`
+#[derive(Debug, Default, Deserialize)]
+pub struct ToolQuery {
+    pub category: Option<String>,
+    pub tag: Option<String>,
+    pub namespace: Option<String>,
+    /// Substring of the name, description or a tag
+    pub q: Option<String>,
+    #[serde(default)]
+    pub offset: usize,
+    /// All matches when unset
+    pub limit: Option<usize>,
+}
+
+impl ToolQuery {
+    fn matches(&self, tool: &ToolDefinition) -> bool {
+        let eq = |wanted: &Option<String>, actual: &str| {
+            wanted.as_deref().is_none_or(|w| w.eq_ignore_ascii_case(actual))
+        };
+        let tagged = self.tag.as_deref().is_none_or(|tag| {
+            tool.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+        });
+        let searched = self.q.as_deref().map(str::to_lowercase).is_none_or(|q| {
+            tool.name.to_lowercase().contains(&q)
+                || tool.description.to_lowercase().contains(&q)
+                || tool.tags.iter().any(|t| t.to_lowercase().contains(&q))
+        });
+
+        eq(&self.category, &category_of(tool)) && eq(&self.namespace, &tool.namespace) && tagged && searched
+    }
+}
+
+/// Counts over the matching tools
+#[derive(Debug, Default, Serialize)]
+pub struct Facets {
+    pub categories: BTreeMap<String, usize>,
+    pub namespaces: BTreeMap<String, usize>,
+    pub tags: BTreeMap<String, usize>,
+}
+
+/// One page of matching tools
+pub struct CatalogPage<'a> {
+    pub tools: Vec<&'a ToolDefinition>,
+    pub total: usize,
+    pub facets: Facets,
+}
+
+/// Filter, facet and paginate (tools sorted by name)
+pub fn search<'a>(tools: &'a [ToolDefinition], query: &ToolQuery) -> CatalogPage<'a> {
+    let mut matching: Vec<&ToolDefinition> = tools.iter().filter(|t| query.matches(t)).collect();
+    matching.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut facets = Facets::default();
+    for tool in &matching {
+        *facets.categories.entry(category_of(tool)).or_insert(0) += 1;
+        if !tool.namespace.is_empty() {
+            *facets.namespaces.entry(tool.namespace.clone()).or_insert(0) += 1;
+        }
+        for tag in &tool.tags {
+            *facets.tags.entry(tag.to_lowercase()).or_insert(0) += 1;
+        }
+    }
+
+    let total = matching.len();
+    let tools = matching
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    CatalogPage { tools, total, facets }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, category: &str, namespace: &str, tags: &[&str]) -> ToolDefinition {
+        ToolDefinition {
+            name: name.to_string(),
+            description: format!("{} tool", name),
+            input_schema: json!({ "type": "object" }),
+            schema_version: String::new(),
+            category: category.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            namespace: namespace.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_registry_category_is_used() {
+        assert_eq!(category_of(&tool("dbus_systemd_list_units", "Systemd", "dbus", &[])), "systemd");
+        assert_eq!(category_of(&tool("mystery", "", "", &[])), UNCATEGORIZED);
+    }
+
+    #[test]
+    fn test_search_filters_facets_and_pages() {
+        let tools = vec![
+            tool("ovs_list_bridges", "networking", "ovs", &["read"]),
+            tool("ovs_create_bridge", "networking", "ovs", &["write"]),
+            tool("dbus_systemd_list_units", "systemd", "dbus", &["read"]),
+        ];
+
+        let read = search(&tools, &ToolQuery { tag: Some("READ".to_string()), ..Default::default() });
+        assert_eq!(read.total, 2);
+        assert_eq!(read.facets.categories.get("networking"), Some(&1));
+        assert_eq!(read.facets.categories.get("systemd"), Some(&1));
+
+        let page = search(&tools, &ToolQuery { namespace: Some("ovs".to_string()), offset: 1, limit: Some(1), ..Default::default() });
+        assert_eq!(page.total, 2);
+        assert_eq!(page.tools.len(), 1);
+        assert_eq!(page.tools[0].name, "ovs_list_bridges");
+    }
+}
@@ -0,0 +1,409 @@
+//! Tool Limits - runtime, concurrency and rate limits around tool execution
+//!
+//! Every execution through [`crate::executor::ToolExecutor`] runs under the
+//! limits resolved for its tool from `/etc/op-dbus/tool-limits.json`:
+//!
+//! ```json
+//! {
+//!   "defaults": { "timeout_secs": 300 },
+//!   "groups": { "shell": { "max_concurrent": 2, "calls_per_minute": 30 } },
+//!   "tools": { "shell_exec": { "timeout_secs": 60 } }
+//! }
+//! ```
+//!
+//! Each setting is taken from the tool, then its group (category, then
+//! namespace), then the defaults; 0 means unlimited. Concurrency and rate
+//! limits set on a group are shared by all tools of the group; rate limits
+//! count calls per caller (MCP session, API key, or IP).
+
+use serde::{Deserialize, Serialize};
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use op_tools::registry::ToolDefinition;
+
+use crate::audit::Caller;
+use crate::tool_catalog;
+
+const TOOL_LIMITS_CONFIG_PATH: &str = "/etc/op-dbus/tool-limits.json";
+
+/// Applies when nothing is configured, so a hung call cannot hold a request forever
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Rate windows tracked before idle ones are dropped
+const MAX_RATE_WINDOWS: usize = 10_000;
+
+/// Limits for a tool, group or the defaults; unset fields fall through
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Limits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calls_per_minute: Option<u32>,
+}
+
+/// `/etc/op-dbus/tool-limits.json`
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct LimitsConfig {
+    #[serde(default)]
+    pub defaults: Limits,
+    /// Keyed by category or namespace
+    #[serde(default)]
+    pub groups: HashMap<String, Limits>,
+    #[serde(default)]
+    pub tools: HashMap<String, Limits>,
+}
+
+impl LimitsConfig {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(TOOL_LIMITS_CONFIG_PATH) else {
+            return Self::default();
+        };
+        let mut raw = content;
+        match unsafe { simd_json::from_str::<LimitsConfig>(&mut raw) } {
+            Ok(config) => {
+                info!(
+                    "Loaded tool limits from {} ({} groups, {} tools)",
+                    TOOL_LIMITS_CONFIG_PATH,
+                    config.groups.len(),
+                    config.tools.len()
+                );
+                config
+            }
+            Err(e) => {
+                warn!("Invalid tool limits {}: {}, using defaults", TOOL_LIMITS_CONFIG_PATH, e);
+                Self::default()
+            }
+        }
+    }
+
+    /// First level (tool, group, defaults) that sets a field, with the scope
+    /// the field is enforced in
+    fn pick<T: Copy>(&self, name: &str, groups: &[String], field: impl Fn(&Limits) -> Option<T>) -> Option<(T, String)> {
+        if let Some(value) = self.tools.get(name).and_then(&field) {
+            return Some((value, format!("tool:{}", name)));
+        }
+        for group in groups {
+            if let Some(value) = self.groups.get(group).and_then(&field) {
+                return Some((value, format!("group:{}", group)));
+            }
+        }
+        field(&self.defaults).map(|value| (value, format!("tool:{}", name)))
+    }
+}
+
+/// Limits in effect for one execution
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedLimits {
+    pub timeout: Option<Duration>,
+    /// Maximum and the scope (`tool:<name>` / `group:<name>`) it is shared in
+    pub concurrency: Option<(usize, String)>,
+    pub rate: Option<(u32, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    Timeout { tool: String, secs: u64 },
+    Concurrency { tool: String, scope: String, max: usize },
+    RateLimited { tool: String, scope: String, per_minute: u32, retry_after_secs: u64 },
+}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout { tool, secs } => {
+                write!(f, "Tool '{}' timed out after {}s and was cancelled", tool, secs)
+            }
+            Self::Concurrency { tool, scope, max } => write!(
+                f,
+                "Tool '{}' rejected: {} concurrent executions already running in {}",
+                tool, max, scope
+            ),
+            Self::RateLimited { tool, scope, per_minute, retry_after_secs } => write!(
+                f,
+                "Tool '{}' rate limited: {} calls per minute in {}, retry in {}s",
+                tool, per_minute, scope, retry_after_secs
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// Violation counters for one tool
+#[derive(Default)]
+struct ToolMetrics {
+    timeouts: AtomicU64,
+    concurrency_rejections: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+pub struct ToolLimiter {
+    config: LimitsConfig,
+    /// Per concurrency scope: its maximum and semaphore
+    semaphores: Mutex<HashMap<String, (usize, Arc<Semaphore>)>>,
+    windows: Mutex<HashMap<String, VecDeque<Instant>>>,
+    metrics: Mutex<HashMap<String, Arc<ToolMetrics>>>,
+}
+
+impl ToolLimiter {
+    /// Limiter configured from `/etc/op-dbus/tool-limits.json`
+    pub fn load() -> Self {
+        Self::new(LimitsConfig::load())
+    }
+
+    pub fn new(config: LimitsConfig) -> Self {
+        Self {
+            config,
+            semaphores: Mutex::new(HashMap::new()),
+            windows: Mutex::new(HashMap::new()),
+            metrics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn resolve(&self, tool: &ToolDefinition) -> ResolvedLimits {
+        self.resolve_in(&tool.name, &[tool_catalog::category_of(tool), tool.namespace.clone()])
+    }
+
+    /// Limits for a tool outside the registry (agent operations on
+    /// `/mcp/agents`): its own entry, then the defaults
+    pub fn resolve_name(&self, tool_name: &str) -> ResolvedLimits {
+        self.resolve_in(tool_name, &[])
+    }
+
+    fn resolve_in(&self, name: &str, groups: &[String]) -> ResolvedLimits {
+        let timeout = self
+            .config
+            .pick(name, groups, |l| l.timeout_secs)
+            .map_or(DEFAULT_TIMEOUT_SECS, |(secs, _)| secs);
+        ResolvedLimits {
+            timeout: (timeout > 0).then(|| Duration::from_secs(timeout)),
+            concurrency: self.config.pick(name, groups, |l| l.max_concurrent).filter(|(max, _)| *max > 0),
+            rate: self.config.pick(name, groups, |l| l.calls_per_minute).filter(|(max, _)| *max > 0),
+        }
+    }
+
+    /// Run `fut` for `caller` within `limits`; the future is dropped on timeout
+    pub async fn run<F, T>(
+        &self,
+        tool_name: &str,
+        caller: &Caller,
+        limits: &ResolvedLimits,
+        fut: F,
+    ) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        if let Some((per_minute, scope)) = &limits.rate {
+            self.check_rate(tool_name, caller, scope, *per_minute)?;
+        }
+        let _permit = match &limits.concurrency {
+            Some((max, scope)) => Some(self.acquire(tool_name, scope, *max)?),
+            None => None,
+        };
+
+        match limits.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, fut).await {
+                Ok(result) => result,
+                Err(_) => {
+                    self.metrics_for(tool_name).timeouts.fetch_add(1, Ordering::Relaxed);
+                    warn!("Tool {} timed out after {:?}", tool_name, timeout);
+                    Err(LimitError::Timeout {
+                        tool: tool_name.to_string(),
+                        secs: timeout.as_secs(),
+                    }
+                    .into())
+                }
+            },
+            None => fut.await,
+        }
+    }
+
+    fn acquire(&self, tool_name: &str, scope: &str, max: usize) -> Result<OwnedSemaphorePermit, LimitError> {
+        let semaphore = self
+            .semaphores
+            .lock()
+            .unwrap()
+            .entry(scope.to_string())
+            .or_insert_with(|| (max, Arc::new(Semaphore::new(max))))
+            .1
+            .clone();
+        semaphore.try_acquire_owned().map_err(|_| {
+            self.metrics_for(tool_name).concurrency_rejections.fetch_add(1, Ordering::Relaxed);
+            LimitError::Concurrency {
+                tool: tool_name.to_string(),
+                scope: scope.to_string(),
+                max,
+            }
+        })
+    }
+
+    fn check_rate(&self, tool_name: &str, caller: &Caller, scope: &str, per_minute: u32) -> Result<(), LimitError> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= MAX_RATE_WINDOWS {
+            windows.retain(|_, calls| calls.back().is_some_and(|last| now.duration_since(*last) < RATE_WINDOW));
+        }
+
+        let calls = windows.entry(format!("{}|{}", caller_key(caller), scope)).or_default();
+        while calls.front().is_some_and(|first| now.duration_since(*first) >= RATE_WINDOW) {
+            calls.pop_front();
+        }
+        if calls.len() >= per_minute as usize {
+            self.metrics_for(tool_name).rate_limited.fetch_add(1, Ordering::Relaxed);
+            let oldest = calls.front().copied().unwrap_or(now);
+            return Err(LimitError::RateLimited {
+                tool: tool_name.to_string(),
+                scope: scope.to_string(),
+                per_minute,
+                retry_after_secs: (RATE_WINDOW - now.duration_since(oldest)).as_secs() + 1,
+            });
+        }
+        calls.push_back(now);
+        Ok(())
+    }
+
+    fn metrics_for(&self, tool_name: &str) -> Arc<ToolMetrics> {
+        self.metrics
+            .lock()
+            .unwrap()
+            .entry(tool_name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Configuration, running executions per concurrency scope and violations per tool
+    pub fn stats(&self) -> Value {
+        let in_flight: BTreeMap<String, usize> = self
+            .semaphores
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(scope, (max, semaphore))| (scope.clone(), max.saturating_sub(semaphore.available_permits())))
+            .collect();
+
+        let mut violations = json!({});
+        if let Some(obj) = violations.as_object_mut() {
+            let metrics = self.metrics.lock().unwrap();
+            let mut names: Vec<&String> = metrics.keys().collect();
+            names.sort();
+            for name in names {
+                let m = &metrics[name];
+                obj.insert(
+                    name.clone(),
+                    json!({
+                        "timeouts": m.timeouts.load(Ordering::Relaxed),
+                        "concurrency_rejections": m.concurrency_rejections.load(Ordering::Relaxed),
+                        "rate_limited": m.rate_limited.load(Ordering::Relaxed)
+                    }),
+                );
+            }
+        }
+
+        json!({
+            "default_timeout_secs": self.config.defaults.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS),
+            "config": &self.config,
+            "in_flight": in_flight,
+            "violations": violations
+        })
+    }
+}
+
+/// Who a rate limit counts against
+fn caller_key(caller: &Caller) -> String {
+    caller
+        .session
+        .as_ref()
+        .map(|s| format!("session:{}", s))
+        .or_else(|| caller.api_key.as_ref().map(|k| format!("key:{}", k)))
+        .or_else(|| caller.ip.as_ref().map(|ip| format!("ip:{}", ip)))
+        .unwrap_or_else(|| format!("source:{}", caller.source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, category: &str) -> ToolDefinition {
+        ToolDefinition {
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: json!({ "type": "object" }),
+            schema_version: String::new(),
+            category: category.to_string(),
+            tags: Vec::new(),
+            namespace: String::new(),
+        }
+    }
+
+    fn caller() -> Caller {
+        Caller {
+            source: "api".to_string(),
+            ip: Some("10.0.0.5".to_string()),
+            zone: None,
+            api_key: None,
+            session: None,
+            profile: None,
+        }
+    }
+
+    fn config() -> LimitsConfig {
+        let mut config = LimitsConfig::default();
+        config.groups.insert(
+            "shell".to_string(),
+            Limits { max_concurrent: Some(1), calls_per_minute: Some(2), ..Default::default() },
+        );
+        config.tools.insert(
+            "shell_exec".to_string(),
+            Limits { timeout_secs: Some(1), ..Default::default() },
+        );
+        config
+    }
+
+    #[test]
+    fn test_resolve_falls_through_tool_group_defaults() {
+        let limiter = ToolLimiter::new(config());
+
+        let exec = limiter.resolve(&tool("shell_exec", "shell"));
+        assert_eq!(exec.timeout, Some(Duration::from_secs(1)));
+        assert_eq!(exec.concurrency, Some((1, "group:shell".to_string())));
+        assert_eq!(exec.rate, Some((2, "group:shell".to_string())));
+
+        let other = limiter.resolve(&tool("ovs_list_bridges", "networking"));
+        assert_eq!(other.timeout, Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS)));
+        assert!(other.concurrency.is_none() && other.rate.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_violations_are_reported() {
+        let limiter = ToolLimiter::new(config());
+        let limits = limiter.resolve(&tool("shell_exec", "shell"));
+
+        let slow = limiter.run("shell_exec", &caller(), &limits, async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        });
+        let rejected = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            limiter.run("shell_exec", &caller(), &limits, async { Ok(()) }).await
+        };
+        let (slow, rejected) = tokio::join!(slow, rejected);
+        assert!(matches!(slow.unwrap_err().downcast_ref(), Some(LimitError::Timeout { .. })));
+        assert!(matches!(rejected.unwrap_err().downcast_ref(), Some(LimitError::Concurrency { .. })));
+
+        // Both calls counted against the two per minute
+        let limited = limiter.run("shell_exec", &caller(), &limits, async { Ok(()) }).await;
+        assert!(matches!(limited.unwrap_err().downcast_ref(), Some(LimitError::RateLimited { .. })));
+    }
+}
@@ -0,0 +1,162 @@
+//! Tool Output - incremental output from long-running tools
+//!
+//! A tool streams output by emitting `tracing` events with the
+//! [`OUTPUT_TARGET`] target while it runs, so tools in other op-* crates need
+//! no dependency on this one:
+//!
+//! ```ignore
+//! tracing::info!(target: "tool_output", stream = "stdout", "{}", line);
+//! ```
+//!
+//! [`ToolOutputLayer`] hands each chunk to the sink of the execution running
+//! on the current task (see [`with_sink`]): SSE for
+//! `POST /api/tools/:name/stream`, `tool_output` orchestrator events for
+//! WebSocket and chat streams, and `notifications/progress` for MCP calls
+//! with a progress token. Without a sink the events are ordinary log lines,
+//! and the tool's result is the same either way.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// `tracing` target tools emit output chunks on
+pub const OUTPUT_TARGET: &str = "tool_output";
+
+/// Stream name when the event has no `stream` field
+const DEFAULT_STREAM: &str = "stdout";
+
+/// One piece of tool output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputChunk {
+    /// 1-based position within the execution
+    pub seq: u64,
+    /// `stdout`, `stderr`, or whatever the tool names it
+    pub stream: String,
+    pub data: String,
+}
+
+/// Receiver of the output chunks of one execution
+#[derive(Clone)]
+pub struct OutputSink {
+    deliver: Arc<dyn Fn(OutputChunk) + Send + Sync>,
+    seq: Arc<AtomicU64>,
+}
+
+impl OutputSink {
+    pub fn new(deliver: impl Fn(OutputChunk) + Send + Sync + 'static) -> Self {
+        Self {
+            deliver: Arc::new(deliver),
+            seq: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn send(&self, stream: &str, data: String) {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        (self.deliver)(OutputChunk {
+            seq,
+            stream: stream.to_string(),
+            data,
+        });
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_SINK: OutputSink;
+}
+
+/// Run `fut` with its tool output relayed to `sink` (if any)
+pub async fn with_sink<F: Future>(sink: Option<OutputSink>, fut: F) -> F::Output {
+    match sink {
+        Some(sink) => CURRENT_SINK.scope(sink, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Emit a chunk to the current sink; for tools implemented in this crate
+pub fn emit(stream: &str, data: impl Into<String>) {
+    let _ = CURRENT_SINK.try_with(|sink| sink.send(stream, data.into()));
+}
+
+/// Whether an event is tool output; use as [`ToolOutputLayer`]'s per-layer filter
+pub fn is_output(metadata: &tracing::Metadata<'_>) -> bool {
+    metadata.target() == OUTPUT_TARGET
+}
+
+/// `tracing` layer turning [`OUTPUT_TARGET`] events into output chunks
+pub struct ToolOutputLayer;
+
+impl<S: Subscriber> Layer<S> for ToolOutputLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != OUTPUT_TARGET {
+            return;
+        }
+        let Ok(sink) = CURRENT_SINK.try_with(|s| s.clone()) else {
+            return;
+        };
+
+        let mut visitor = ChunkVisitor::default();
+        event.record(&mut visitor);
+        let stream = visitor.stream.as_deref().unwrap_or(DEFAULT_STREAM);
+        sink.send(stream, visitor.data);
+    }
+}
+
+#[derive(Default)]
+struct ChunkVisitor {
+    data: String,
+    stream: Option<String>,
+}
+
+impl Visit for ChunkVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let _ = write!(self.data, "{:?}", value);
+            }
+            "stream" => self.stream = Some(format!("{:?}", value)),
+            _ => {}
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.data.push_str(value),
+            "stream" => self.stream = Some(value.to_string()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tracing_subscriber::prelude::*;
+
+    #[tokio::test]
+    async fn test_events_reach_the_current_sink_only() {
+        let _guard = tracing_subscriber::registry().with(ToolOutputLayer).set_default();
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let collected = chunks.clone();
+        let sink = OutputSink::new(move |chunk| collected.lock().unwrap().push(chunk));
+
+        tracing::info!(target: "tool_output", "before any sink");
+        with_sink(Some(sink), async {
+            tracing::info!(target: "tool_output", "line {}", 1);
+            tracing::info!(target: "tool_output", stream = "stderr", "warning");
+            tracing::info!("not output");
+            emit("stdout", "done");
+        })
+        .await;
+
+        let chunks = chunks.lock().unwrap();
+        let got: Vec<(u64, &str, &str)> =
+            chunks.iter().map(|c| (c.seq, c.stream.as_str(), c.data.as_str())).collect();
+        assert_eq!(got, vec![(1, "stdout", "line 1"), (2, "stderr", "warning"), (3, "stdout", "done")]);
+    }
+}
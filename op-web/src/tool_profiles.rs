@@ -0,0 +1,231 @@
+//! Tool Profiles - Scope MCP surfaces to a groups_admin profile
+//!
+//! A profile saved through `/groups-admin` is a set of enabled tool groups.
+//! This module resolves a profile (plus group dependencies) into a filter
+//! that the MCP endpoints apply to `tools/list`, `search_tools` and
+//! `execute_tool`, so `/mcp/p/{profile}` only ever sees its own tools.
+//! Executions under a profile carry its name (see [`with_profile`]).
+//!
+//! A tool belongs to a group when its name (with or without the `dbus_`
+//! projection prefix) matches one of the group's tool patterns: the ones in
+//! `/etc/op-dbus/tool-group-patterns.json`, or `<id>` and `<id>_*`.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+use op_tools::registry::ToolDefinition;
+use tracing::{info, warn};
+
+use crate::groups_admin::GROUPS_CONFIG;
+use crate::scope_policy::glob_match;
+
+/// Name prefix given to tools projected from D-Bus (`dbus_systemd_*`)
+const DBUS_PROJECTION_PREFIX: &str = "dbus_";
+
+const GROUP_PATTERNS_PATH: &str = "/etc/op-dbus/tool-group-patterns.json";
+
+lazy_static::lazy_static! {
+    /// Tool name patterns per group id, replacing the default `<id>` and `<id>_*`
+    static ref GROUP_PATTERNS: HashMap<String, Vec<String>> = load_group_patterns();
+}
+
+fn load_group_patterns() -> HashMap<String, Vec<String>> {
+    let Ok(mut raw) = std::fs::read_to_string(GROUP_PATTERNS_PATH) else {
+        return HashMap::new();
+    };
+    match unsafe { simd_json::from_str::<HashMap<String, Vec<String>>>(&mut raw) } {
+        Ok(patterns) => {
+            info!("Loaded tool patterns for {} groups from {}", patterns.len(), GROUP_PATTERNS_PATH);
+            patterns
+                .into_iter()
+                .map(|(id, globs)| (id.to_lowercase(), globs.iter().map(|g| g.to_lowercase()).collect()))
+                .collect()
+        }
+        Err(e) => {
+            warn!("Invalid tool group patterns in {}: {}", GROUP_PATTERNS_PATH, e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Tool name patterns of a single tool group
+#[derive(Debug, Clone)]
+struct GroupMatcher {
+    patterns: Vec<String>,
+}
+
+impl GroupMatcher {
+    fn for_group(id: &str) -> Self {
+        let id = id.to_lowercase();
+        let patterns = match GROUP_PATTERNS.get(&id) {
+            Some(patterns) => patterns.clone(),
+            None if id.is_empty() => Vec::new(),
+            None => vec![format!("{}_*", id), id],
+        };
+        Self { patterns }
+    }
+
+    fn matches(&self, tool: &ToolDefinition) -> bool {
+        self.matches_name(&tool.name)
+    }
+
+    /// Patterns also apply to projected names without their `dbus_` prefix
+    fn matches_name(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let bare_name = name.strip_prefix(DBUS_PROJECTION_PREFIX).unwrap_or(&name);
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, &name) || glob_match(pattern, bare_name))
+    }
+}
+
+/// A resolved tool profile: enabled groups expanded with their dependencies
+#[derive(Debug, Clone)]
+pub struct ToolProfile {
+    pub name: String,
+    pub groups: HashSet<String>,
+    matchers: Vec<GroupMatcher>,
+}
+
+impl ToolProfile {
+    /// Resolve a saved profile by name, returning `None` if it does not exist
+    pub async fn load(name: &str) -> Option<Self> {
+        let enabled = GROUPS_CONFIG.get_profile(name).await?;
+        Some(Self::from_groups(name, enabled.groups))
+    }
+
+    /// Build a profile from a set of group ids, pulling in dependencies
+    pub fn from_groups(name: &str, groups: HashSet<String>) -> Self {
+        let builtin = op_mcp_aggregator::builtin_groups();
+
+        let mut resolved = groups;
+        loop {
+            let missing: Vec<String> = builtin
+                .iter()
+                .filter(|g| resolved.contains(&g.id.to_string()))
+                .flat_map(|g| g.dependencies.iter().map(|d| d.to_string()))
+                .filter(|dep| !resolved.contains(dep))
+                .collect();
+            if missing.is_empty() {
+                break;
+            }
+            resolved.extend(missing);
+        }
+
+        let matchers = resolved.iter().map(|id| GroupMatcher::for_group(id)).collect();
+
+        Self {
+            name: name.to_string(),
+            groups: resolved,
+            matchers,
+        }
+    }
+
+    /// Whether a tool belongs to one of the profile's groups
+    pub fn allows(&self, tool: &ToolDefinition) -> bool {
+        self.matchers.iter().any(|m| m.matches(tool))
+    }
+
+    /// Keep only the tools allowed by this profile
+    pub fn filter(&self, tools: Vec<ToolDefinition>) -> Vec<ToolDefinition> {
+        tools.into_iter().filter(|t| self.allows(t)).collect()
+    }
+
+    /// Error message for a call outside the profile
+    pub fn rejection(&self, tool_name: &str) -> String {
+        format!(
+            "Tool '{}' is not enabled in profile '{}'. Use list_tools or search_tools to find available tools.",
+            tool_name, self.name
+        )
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_PROFILE: String;
+}
+
+/// Run `fut` with `profile` as the profile of any tool it executes
+pub async fn with_profile<F: Future>(profile: Option<&ToolProfile>, fut: F) -> F::Output {
+    match profile {
+        Some(profile) => CURRENT_PROFILE.scope(profile.name.clone(), fut).await,
+        None => fut.await,
+    }
+}
+
+/// Run `fut` under the profile named `name`, as carried across a spawn
+pub async fn with_profile_name<F: Future>(name: Option<String>, fut: F) -> F::Output {
+    match name {
+        Some(name) => CURRENT_PROFILE.scope(name, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Name of the profile the current task executes under, if any
+pub fn current_profile() -> Option<String> {
+    CURRENT_PROFILE.try_with(|p| p.clone()).ok()
+}
+
+/// Built-in groups a tool belongs to, with their security level
+/// (`public`, `standard`, `elevated` or `restricted`)
+pub fn groups_of(tool: &ToolDefinition) -> Vec<(String, String)> {
+    op_mcp_aggregator::builtin_groups()
+        .iter()
+        .filter(|group| GroupMatcher::for_group(&group.id.to_string()).matches(tool))
+        .map(|group| (group.id.to_string(), format!("{:?}", group.security).to_lowercase()))
+        .collect()
+}
+
+/// List registry tools, scoped to a profile when one is given
+pub async fn list_scoped(
+    registry: &op_tools::ToolRegistry,
+    profile: Option<&ToolProfile>,
+) -> Vec<ToolDefinition> {
+    let tools = registry.list().await;
+    match profile {
+        Some(p) => p.filter(tools),
+        None => tools,
+    }
+}
+
+/// Check that a tool exists and is allowed by the profile before execution
+pub async fn check_allowed(
+    registry: &op_tools::ToolRegistry,
+    profile: Option<&ToolProfile>,
+    tool_name: &str,
+) -> Result<(), String> {
+    let Some(profile) = profile else {
+        return Ok(());
+    };
+    match registry.get_definition(tool_name).await {
+        Some(def) if profile.allows(&def) => Ok(()),
+        _ => Err(profile.rejection(tool_name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[&str]) -> GroupMatcher {
+        GroupMatcher {
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_prefix_and_projected_names_match() {
+        let m = matcher(&["systemd_*"]);
+        assert!(m.matches_name("systemd_status"));
+        assert!(m.matches_name("dbus_systemd_restart_unit"));
+        assert!(!m.matches_name("ovs_list_bridges"));
+    }
+
+    #[test]
+    fn test_only_explicit_patterns_match() {
+        let m = matcher(&["respond", "ovs_list_*"]);
+        assert!(m.matches_name("respond"));
+        assert!(m.matches_name("ovs_list_bridges"));
+        assert!(!m.matches_name("ovs_delete_bridge"));
+        assert!(!matcher(&[]).matches_name("anything"));
+    }
+}
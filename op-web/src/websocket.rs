@@ -14,6 +14,7 @@ use tokio::sync::mpsc;
 use tracing::{info, error, debug};
 
 use op_llm::provider::ChatMessage;
+use crate::audit;
 use crate::state::AppState;
 use crate::orchestrator::OrchestratorEvent;
 
@@ -34,7 +35,10 @@ pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     Extension(state): Extension<Arc<AppState>>,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+    // The upgraded connection runs on its own task; keep the caller the
+    // security middleware identified so chat tools are authorized for it
+    let identity = audit::current_identity().unwrap_or_default();
+    ws.on_upgrade(move |socket| audit::with_identity(identity, handle_socket(socket, state)))
 }
 
 async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
@@ -74,7 +78,7 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     });
 
     // Handle incoming messages
-    let mut recv_task = tokio::spawn(async move {
+    let mut recv_task = audit::spawn_with_identity(async move {
         while let Some(Ok(msg)) = ws_receiver.next().await {
             match msg {
                 Message::Text(text) => {
@@ -105,7 +109,7 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
                     let session_tx_for_events = session_tx_clone.clone();
 
                     // Spawn task to forward orchestrator events to session channel
-                    tokio::spawn(async move {
+                    audit::spawn_with_identity(async move {
                         while let Some(event) = event_rx.recv().await {
                             let ws_event = WsMessage::Event { data: event };
                             if let Ok(json) = simd_json::to_string(&ws_event) {
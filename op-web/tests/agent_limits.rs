@@ -0,0 +1,106 @@
+//! Limits on the agents MCP server
+//!
+//! Agent operations on `/mcp/agents/message` are not registry tools, but run
+//! through the executor like every other call. Limits one agent tool to a
+//! single call per minute and checks that the second call is refused before
+//! the agent runs, and that the refusal is audited.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use op_tools::ToolRegistry;
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+use op_web::audit::AuditQuery;
+use op_web::routes::create_router;
+use op_web::scope_policy::ScopePolicies;
+use op_web::tool_limits::LimitsConfig;
+
+use common::{permissive_access, state_with};
+
+async fn call(app: &Router, request: Value) -> Value {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/mcp/agents/message")
+        .header("content-type", "application/json")
+        .body(Body::from(simd_json::to_string(&request).unwrap()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec();
+    simd_json::from_slice(&mut bytes).unwrap()
+}
+
+fn content_text(response: &Value) -> String {
+    response
+        .get("result")
+        .and_then(|r| r.get("content"))
+        .and_then(|c| c.as_array())
+        .and_then(|c| c.first())
+        .and_then(|c| c.get("text"))
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_agent_calls_are_rate_limited() {
+    // Any agent tool will do; find one the same way a client would
+    let probe = create_router(Arc::new(
+        state_with(
+            Arc::new(ToolRegistry::new()),
+            ScopePolicies::new(Default::default()),
+            LimitsConfig::default(),
+            permissive_access(),
+        )
+        .await,
+    ));
+    let list = call(&probe, json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" })).await;
+    let tool = list
+        .get("result")
+        .and_then(|r| r.get("tools"))
+        .and_then(|t| t.as_array())
+        .and_then(|t| t.first())
+        .and_then(|t| t.get("name"))
+        .and_then(|n| n.as_str())
+        .expect("the default roster exposes agent tools")
+        .to_string();
+
+    let mut raw = format!(r#"{{ "tools": {{ "{}": {{ "calls_per_minute": 1 }} }} }}"#, tool);
+    let limits: LimitsConfig = unsafe { simd_json::from_str(&mut raw) }.unwrap();
+    let state = state_with(
+        Arc::new(ToolRegistry::new()),
+        ScopePolicies::new(Default::default()),
+        limits,
+        permissive_access(),
+    )
+    .await;
+    let audit = state.audit.clone();
+    let app = create_router(Arc::new(state));
+
+    let request = |id: u64| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": tool.as_str(), "arguments": {} }
+        })
+    };
+    // The first call may succeed or fail inside the agent; either way it counts
+    call(&app, request(2)).await;
+    let limited = call(&app, request(3)).await;
+    assert_eq!(limited.get("result").and_then(|r| r.get("isError")), Some(&json!(true)), "{:?}", limited);
+    assert!(content_text(&limited).contains("rate limited"), "{:?}", limited);
+
+    let records = audit.query(&AuditQuery { tool: Some(tool.clone()), ..Default::default() });
+    assert_eq!(records.len(), 2, "both calls are audited");
+    let refused = &records[0];
+    assert_eq!(refused.caller.source, "mcp-agents");
+    assert!(!refused.success);
+    assert!(refused.error.as_deref().unwrap_or_default().contains("rate limited"));
+}
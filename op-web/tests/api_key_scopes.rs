@@ -0,0 +1,54 @@
+//! API key scopes at the security middleware
+//!
+//! Creates a managed key that only holds `chat` and checks that routes the
+//! scope table does not grant it are refused with 403 before any handler
+//! runs.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use op_tools::ToolRegistry;
+use simd_json::json;
+use simd_json::prelude::*;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+use op_web::api_keys::{CreateApiKey, Scope};
+use op_web::routes::create_router;
+use op_web::scope_policy::ScopePolicies;
+use op_web::tool_limits::LimitsConfig;
+
+use common::{permissive_access, state_with};
+
+#[tokio::test]
+async fn test_chat_key_cannot_switch_llm_provider() {
+    let state = state_with(
+        Arc::new(ToolRegistry::new()),
+        ScopePolicies::new(Default::default()),
+        LimitsConfig::default(),
+        permissive_access(),
+    )
+    .await;
+    let (_, secret) = state
+        .api_keys
+        .create(CreateApiKey { name: "chat-only".to_string(), scopes: vec![Scope::Chat], expires_at: None })
+        .await
+        .unwrap();
+    let app = create_router(Arc::new(state));
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/llm/provider")
+        .header("content-type", "application/json")
+        .header("x-api-key", secret.as_str())
+        .body(Body::from(simd_json::to_string(&json!({ "provider": "anthropic" })).unwrap()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let mut bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec();
+    let body: simd_json::OwnedValue = simd_json::from_slice(&mut bytes).unwrap();
+    let reason = body.get("reason").and_then(|r| r.as_str()).unwrap();
+    assert!(reason.contains("'admin'"), "{}", reason);
+}
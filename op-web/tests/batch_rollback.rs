@@ -0,0 +1,56 @@
+//! Batch rollback through the built-in inverse map
+//!
+//! `ovs_create_bridge` declares no `x-inverse`; the built-in map names
+//! `ovs_delete_bridge`. Runs a two-step batch whose second step fails and
+//! checks that the first is undone with only the arguments the inverse takes.
+
+mod common;
+
+use op_tools::ToolRegistry;
+use simd_json::json;
+use std::sync::Arc;
+
+use op_web::batch::{self, BatchMode, BatchRequest, BatchStep, StepStatus};
+use op_web::scope_policy::ScopePolicies;
+use op_web::tool_limits::LimitsConfig;
+
+use common::{permissive_access, state_with, StubTool};
+
+#[tokio::test]
+async fn test_failed_step_rolls_back_earlier_ones() {
+    let registry = Arc::new(ToolRegistry::new());
+    let create = StubTool::new("ovs_create_bridge");
+    let add_port = StubTool::failing("ovs_add_port");
+    let delete = StubTool::with_schema(
+        "ovs_delete_bridge",
+        json!({ "type": "object", "properties": { "name": { "type": "string" } } }),
+    );
+    registry.register_tool(create.clone()).await.unwrap();
+    registry.register_tool(add_port.clone()).await.unwrap();
+    registry.register_tool(delete.clone()).await.unwrap();
+
+    let scope = ScopePolicies::new(Default::default());
+    let state = state_with(registry, scope, LimitsConfig::default(), permissive_access()).await;
+    let request = BatchRequest {
+        steps: vec![
+            BatchStep {
+                tool_name: "ovs_create_bridge".to_string(),
+                arguments: json!({ "name": "br9", "datapath_type": "netdev" }),
+            },
+            BatchStep {
+                tool_name: "ovs_add_port".to_string(),
+                arguments: json!({ "bridge": "br9", "port": "eth9" }),
+            },
+        ],
+        mode: BatchMode::Sequential,
+        stop_on_error: true,
+        rollback: true,
+    };
+    let response = batch::run(&state.executor, &state.tool_registry, request).await;
+
+    assert!(!response.success);
+    assert!(response.rolled_back);
+    assert_eq!(response.steps[0].status, StepStatus::Compensated);
+    assert_eq!(response.steps[1].status, StepStatus::Failed);
+    assert_eq!(delete.input(), Some(json!({ "name": "br9" })), "only the arguments the inverse accepts");
+}
@@ -0,0 +1,72 @@
+//! Tool policies on the chat path
+//!
+//! Chat runs tools from a spawned orchestrator task, away from the request
+//! that started it. Drives `POST /api/chat/stream` with a direct
+//! `run <tool> {args}` command (no LLM involved) and checks that the scope
+//! policy still applies to the caller and that the refusal is audited.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use op_tools::ToolRegistry;
+use simd_json::json;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+use op_web::audit::AuditQuery;
+use op_web::routes::create_router;
+use op_web::scope_policy::{ScopeConfig, ScopePolicies};
+use op_web::tool_limits::LimitsConfig;
+
+use common::{permissive_access, state_with, StubTool};
+
+/// Every zone gets the `restricted` policy, which only allows `ip`
+fn scope() -> ScopePolicies {
+    let mut raw = r#"{
+        "policies": { "restricted": { "commands": { "allow": ["ip"] } } },
+        "select": {
+            "zones": {
+                "Localhost": "restricted",
+                "TrustedMesh": "restricted",
+                "PrivateNetwork": "restricted",
+                "Public": "restricted"
+            }
+        }
+    }"#
+    .to_string();
+    ScopePolicies::new(unsafe { simd_json::from_str::<ScopeConfig>(&mut raw) }.unwrap())
+}
+
+#[tokio::test]
+async fn test_chat_honors_scope_policy() {
+    let registry = Arc::new(ToolRegistry::new());
+    let shell = StubTool::new("shell_exec");
+    registry.register_tool(shell.clone()).await.unwrap();
+
+    // Every zone may reach every tool level, so only the scope policy decides
+    let state = state_with(registry, scope(), LimitsConfig::default(), permissive_access()).await;
+    let audit = state.audit.clone();
+    let app = create_router(Arc::new(state));
+
+    let message = json!({ "message": r#"run shell_exec {"command":"rm -rf /tmp/op-web-chat-policy"}"# });
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/chat/stream")
+        .header("content-type", "application/json")
+        .body(Body::from(simd_json::to_string(&message).unwrap()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let events = String::from_utf8_lossy(&bytes);
+
+    assert!(events.contains("Policy violation"), "{}", events);
+    assert!(!shell.ran(), "the command must not run");
+
+    let records = audit.query(&AuditQuery { tool: Some("shell_exec".to_string()), ..Default::default() });
+    let record = records.first().expect("the refusal is audited");
+    assert_eq!(record.caller.source, "chat");
+    assert!(!record.success);
+    assert!(record.caller.zone.is_some(), "the chat caller keeps its zone");
+}
@@ -0,0 +1,131 @@
+//! Shared fixtures for the integration tests
+//!
+//! Stub tools that record their calls, an access policy that lets every zone
+//! reach every tool level, and an in-memory `AppState` whose executor uses
+//! the policies a test asks for.
+
+#![allow(dead_code)]
+
+use anyhow::Result;
+use async_trait::async_trait;
+use op_tools::tool::Tool;
+use op_tools::ToolRegistry;
+use simd_json::{json, OwnedValue as Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use op_web::access_policy::{AccessConfig, AccessPolicy};
+use op_web::executor::{self, ToolExecutor};
+use op_web::scope_policy::ScopePolicies;
+use op_web::tool_cache::{CacheConfig, ToolCache};
+use op_web::tool_limits::{LimitsConfig, ToolLimiter};
+use op_web::{AppState, UnifiedOrchestrator};
+
+/// Stands in for a system tool: reports its input as `tool_output` and
+/// records that it ran, and with what, instead of doing anything
+pub struct StubTool {
+    name: &'static str,
+    schema: Value,
+    fails: bool,
+    ran: AtomicBool,
+    input: Mutex<Option<Value>>,
+}
+
+impl StubTool {
+    pub fn new(name: &'static str) -> Arc<Self> {
+        Self::build(name, json!({ "type": "object", "properties": {} }), false)
+    }
+
+    pub fn with_schema(name: &'static str, schema: Value) -> Arc<Self> {
+        Self::build(name, schema, false)
+    }
+
+    /// A stub whose every call fails
+    pub fn failing(name: &'static str) -> Arc<Self> {
+        Self::build(name, json!({ "type": "object", "properties": {} }), true)
+    }
+
+    fn build(name: &'static str, schema: Value, fails: bool) -> Arc<Self> {
+        Arc::new(Self { name, schema, fails, ran: AtomicBool::new(false), input: Mutex::new(None) })
+    }
+
+    pub fn ran(&self) -> bool {
+        self.ran.load(Ordering::SeqCst)
+    }
+
+    /// Arguments of the last call
+    pub fn input(&self) -> Option<Value> {
+        self.input.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Tool for StubTool {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        "Stub that records its calls"
+    }
+
+    fn input_schema(&self) -> Value {
+        self.schema.clone()
+    }
+
+    fn category(&self) -> &str {
+        "test"
+    }
+
+    fn namespace(&self) -> &str {
+        "test"
+    }
+
+    async fn execute(&self, input: Value) -> Result<Value> {
+        self.ran.store(true, Ordering::SeqCst);
+        tracing::info!(target: "tool_output", "{} {}", self.name, simd_json::to_string(&input)?);
+        *self.input.lock().unwrap() = Some(input);
+        if self.fails {
+            anyhow::bail!("{} failed", self.name);
+        }
+        Ok(json!({ "tool": self.name }))
+    }
+}
+
+/// Every zone may reach every tool level
+pub fn permissive_access() -> AccessPolicy {
+    let mut raw = r#"{
+        "zones": {
+            "Localhost": { "max_security": "restricted" },
+            "TrustedMesh": { "max_security": "restricted" },
+            "PrivateNetwork": { "max_security": "restricted" },
+            "Public": { "max_security": "restricted" }
+        }
+    }"#
+    .to_string();
+    AccessPolicy::new(unsafe { simd_json::from_str::<AccessConfig>(&mut raw) }.unwrap())
+}
+
+/// In-memory state over `registry` whose executor (also the global one and
+/// the orchestrator's) applies `scope`, `limits` and `access`
+pub async fn state_with(
+    registry: Arc<ToolRegistry>,
+    scope: ScopePolicies,
+    limits: LimitsConfig,
+    access: AccessPolicy,
+) -> AppState {
+    let mut state = AppState::in_memory(registry.clone()).await.unwrap();
+    let executor = Arc::new(ToolExecutor::new(
+        registry.clone(),
+        state.jobs.clone(),
+        state.audit.clone(),
+        Arc::new(ToolCache::new(CacheConfig::default())),
+        Arc::new(ToolLimiter::new(limits)),
+        Arc::new(scope),
+        Arc::new(access),
+    ));
+    executor::install(executor.clone());
+    state.orchestrator = Arc::new(UnifiedOrchestrator::new(registry, state.chat_manager.clone(), executor.clone()));
+    state.executor = executor;
+    state
+}
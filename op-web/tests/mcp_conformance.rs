@@ -0,0 +1,363 @@
+//! MCP protocol conformance tests
+//!
+//! Starts the full router in-process over a stub registry and in-memory
+//! state (nothing read from or written to the host), and drives real
+//! client flows against the three JSON-RPC implementations: the full surface
+//! (`/mcp/message`), compact mode (`/mcp/compact/message`) and the agents
+//! server (`/mcp/agents/message`), plus the negotiated `/mcp` entry point.
+//! Version fixtures live in `tests/fixtures/mcp/<protocolVersion>.json`.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use futures::StreamExt;
+use op_tools::tool::Tool;
+use op_tools::ToolRegistry;
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+use op_web::routes::create_router;
+use op_web::AppState;
+
+/// Message endpoints of the three JSON-RPC implementations
+const SURFACES: &[&str] = &["/mcp/message", "/mcp/compact/message", "/mcp/agents/message"];
+
+const FIXTURES: &[(&str, &str)] = &[
+    ("2024-11-05", include_str!("fixtures/mcp/2024-11-05.json")),
+    ("2025-03-26", include_str!("fixtures/mcp/2025-03-26.json")),
+];
+
+/// Echoes its arguments back
+struct EchoTool;
+
+#[async_trait]
+impl Tool for EchoTool {
+    fn name(&self) -> &str {
+        "test_echo"
+    }
+
+    fn description(&self) -> &str {
+        "Echo the arguments"
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "message": { "type": "string" } }
+        })
+    }
+
+    fn category(&self) -> &str {
+        "test"
+    }
+
+    fn namespace(&self) -> &str {
+        "test"
+    }
+
+    async fn execute(&self, input: Value) -> Result<Value> {
+        Ok(json!({ "echo": input }))
+    }
+}
+
+/// Always fails
+struct FailTool;
+
+#[async_trait]
+impl Tool for FailTool {
+    fn name(&self) -> &str {
+        "test_fail"
+    }
+
+    fn description(&self) -> &str {
+        "Fail every call"
+    }
+
+    fn input_schema(&self) -> Value {
+        json!({ "type": "object", "properties": {} })
+    }
+
+    fn category(&self) -> &str {
+        "test"
+    }
+
+    fn namespace(&self) -> &str {
+        "test"
+    }
+
+    async fn execute(&self, _input: Value) -> Result<Value> {
+        Err(anyhow!("stub failure"))
+    }
+}
+
+async fn app() -> Router {
+    let registry = Arc::new(ToolRegistry::new());
+    registry.register_tool(Arc::new(EchoTool)).await.unwrap();
+    registry.register_tool(Arc::new(FailTool)).await.unwrap();
+
+    let state = AppState::in_memory(registry).await.unwrap();
+    create_router(Arc::new(state))
+}
+
+/// POST a JSON body; returns the status and the parsed body (if any)
+async fn post(app: &Router, path: &str, body: &Value) -> (StatusCode, Option<Value>) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(path)
+        .header("content-type", "application/json")
+        .header("accept", "application/json")
+        .body(Body::from(simd_json::to_string(body).unwrap()))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    if bytes.is_empty() {
+        return (status, None);
+    }
+    let mut bytes = bytes.to_vec();
+    (status, Some(simd_json::from_slice(&mut bytes).unwrap()))
+}
+
+async fn call(app: &Router, path: &str, request: Value) -> Value {
+    let (status, body) = post(app, path, &request).await;
+    assert_eq!(status, StatusCode::OK, "{} {:?}", path, request);
+    body.unwrap()
+}
+
+fn error_code(response: &Value) -> Option<i64> {
+    response.get("error")?.get("code")?.as_i64()
+}
+
+fn content_text(response: &Value) -> String {
+    response
+        .get("result")
+        .and_then(|r| r.get("content"))
+        .and_then(|c| c.as_array())
+        .and_then(|c| c.first())
+        .and_then(|c| c.get("text"))
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn is_error(response: &Value) -> bool {
+    response
+        .get("result")
+        .and_then(|r| r.get("isError"))
+        .and_then(|e| e.as_bool())
+        .unwrap_or(false)
+}
+
+#[tokio::test]
+async fn test_version_fixtures_on_every_surface() {
+    let app = app().await;
+
+    for (version, fixture) in FIXTURES {
+        let mut raw = fixture.to_string();
+        let fixture: Value = unsafe { simd_json::from_str(&mut raw) }.unwrap();
+        let steps = fixture.get("steps").and_then(|s| s.as_array()).unwrap();
+
+        for surface in SURFACES {
+            for step in steps {
+                let name = step.get("name").and_then(|n| n.as_str()).unwrap();
+                let request = step.get("request").cloned().unwrap();
+                let expect = step.get("expect").unwrap();
+                let response = call(&app, surface, request.clone()).await;
+                let context = format!("{} {} [{}]", version, surface, name);
+
+                assert_eq!(response.get("jsonrpc"), Some(&json!("2.0")), "{}", context);
+                assert_eq!(response.get("id"), request.get("id"), "{}: id must be echoed", context);
+
+                if let Some(code) = expect.get("error_code").and_then(|c| c.as_i64()) {
+                    assert_eq!(error_code(&response), Some(code), "{}: {:?}", context, response);
+                    assert!(response.get("result").is_none(), "{}", context);
+                }
+                if let Some(keys) = expect.get("result_has").and_then(|k| k.as_array()) {
+                    let result = response
+                        .get("result")
+                        .unwrap_or_else(|| panic!("{}: no result in {:?}", context, response));
+                    for key in keys.iter().filter_map(|k| k.as_str()) {
+                        assert!(result.get(key).is_some(), "{}: result lacks {}", context, key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_full_surface_tool_calls() {
+    let app = app().await;
+
+    let list = call(&app, "/mcp/message", json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" })).await;
+    let tools = list.get("result").and_then(|r| r.get("tools")).and_then(|t| t.as_array()).unwrap();
+    let echo = tools
+        .iter()
+        .find(|t| t.get("name").and_then(|n| n.as_str()) == Some("test_echo"))
+        .expect("stub tool listed");
+    assert!(echo.get("inputSchema").is_some());
+
+    let ok = call(&app, "/mcp/message", json!({
+        "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+        "params": { "name": "test_echo", "arguments": { "message": "hi" } }
+    }))
+    .await;
+    assert!(!is_error(&ok));
+    assert!(content_text(&ok).contains("hi"));
+
+    let failed = call(&app, "/mcp/message", json!({
+        "jsonrpc": "2.0", "id": 3, "method": "tools/call",
+        "params": { "name": "test_fail", "arguments": {} }
+    }))
+    .await;
+    assert!(is_error(&failed));
+    assert!(content_text(&failed).contains("stub failure"));
+
+    let missing = call(&app, "/mcp/message", json!({
+        "jsonrpc": "2.0", "id": 4, "method": "tools/call",
+        "params": { "name": "no_such_tool" }
+    }))
+    .await;
+    assert_eq!(error_code(&missing), Some(-32602));
+}
+
+#[tokio::test]
+async fn test_compact_surface_tool_calls() {
+    let app = app().await;
+
+    let list = call(&app, "/mcp/compact/message", json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" })).await;
+    let names: Vec<&str> = list
+        .get("result")
+        .and_then(|r| r.get("tools"))
+        .and_then(|t| t.as_array())
+        .unwrap()
+        .iter()
+        .filter_map(|t| t.get("name").and_then(|n| n.as_str()))
+        .collect();
+    for meta in ["list_tools", "search_tools", "get_tool_schema", "execute_tool"] {
+        assert!(names.contains(&meta), "missing meta-tool {}", meta);
+    }
+
+    let ok = call(&app, "/mcp/compact/message", json!({
+        "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+        "params": { "name": "execute_tool", "arguments": { "tool_name": "test_echo", "arguments": { "message": "hi" } } }
+    }))
+    .await;
+    assert!(!is_error(&ok));
+    assert!(content_text(&ok).contains("hi"));
+
+    let failed = call(&app, "/mcp/compact/message", json!({
+        "jsonrpc": "2.0", "id": 3, "method": "tools/call",
+        "params": { "name": "execute_tool", "arguments": { "tool_name": "test_fail" } }
+    }))
+    .await;
+    assert!(content_text(&failed).contains("stub failure"));
+
+    let unknown = call(&app, "/mcp/compact/message", json!({
+        "jsonrpc": "2.0", "id": 4, "method": "tools/call",
+        "params": { "name": "not_a_meta_tool" }
+    }))
+    .await;
+    assert!(is_error(&unknown));
+}
+
+#[tokio::test]
+async fn test_agents_surface_unknown_tool() {
+    let app = app().await;
+
+    let response = call(&app, "/mcp/agents/message", json!({
+        "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+        "params": { "name": "no_such_agent_op", "arguments": {} }
+    }))
+    .await;
+    assert!(is_error(&response));
+}
+
+#[tokio::test]
+async fn test_negotiated_batches_and_notifications() {
+    let app = app().await;
+
+    let (status, body) = post(&app, "/mcp", &json!([
+        { "jsonrpc": "2.0", "id": 1, "method": "ping" },
+        { "jsonrpc": "2.0", "method": "notifications/initialized" },
+        { "jsonrpc": "2.0", "id": 2, "method": "does/not/exist" }
+    ]))
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let responses = body.unwrap();
+    let responses = responses.as_array().unwrap();
+    assert_eq!(responses.len(), 2, "notifications get no response");
+    assert_eq!(responses[0].get("id"), Some(&json!(1)));
+    assert_eq!(error_code(&responses[1]), Some(-32601));
+
+    let (status, body) = post(&app, "/mcp", &json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(body.is_none());
+
+    let (status, body) = post(&app, "/mcp", &json!([])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error_code(&body.unwrap()), Some(-32600));
+}
+
+#[tokio::test]
+async fn test_sse_endpoint_event() {
+    let app = app().await;
+
+    for (path, message_path) in [
+        ("/mcp/sse", "/mcp/message"),
+        ("/mcp/compact", "/mcp/compact/message"),
+        ("/mcp/agents", "/mcp/agents/message"),
+        ("/mcp", "/mcp"),
+    ] {
+        let request = Request::builder()
+            .uri(path)
+            .header("accept", "text/event-stream")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+
+        let mut frames = response.into_body().into_data_stream();
+        let first = frames.next().await.unwrap().unwrap();
+        let first = String::from_utf8_lossy(&first);
+        assert!(first.contains("event: endpoint"), "{}: {}", path, first);
+        assert!(first.contains(&format!("{}?sessionId=", message_path)), "{}: {}", path, first);
+    }
+}
+
+#[tokio::test]
+async fn test_sse_session_replies_once_on_the_stream() {
+    let app = app().await;
+
+    let request = Request::builder()
+        .uri("/mcp")
+        .header("accept", "text/event-stream")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let mut frames = response.into_body().into_data_stream();
+    let endpoint = frames.next().await.unwrap().unwrap();
+    let endpoint = String::from_utf8_lossy(&endpoint).to_string();
+    let session_id: String = endpoint
+        .split("sessionId=")
+        .nth(1)
+        .unwrap()
+        .chars()
+        .take_while(|c| !c.is_whitespace())
+        .collect();
+
+    // The POST is only acknowledged; the reply arrives on the stream
+    let path = format!("/mcp?sessionId={}", session_id);
+    let (status, body) = post(&app, &path, &json!({ "jsonrpc": "2.0", "id": 7, "method": "ping" })).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(body.is_none());
+
+    let reply = frames.next().await.unwrap().unwrap();
+    let reply = String::from_utf8_lossy(&reply);
+    assert!(reply.contains(r#""id":7"#), "{}", reply);
+}
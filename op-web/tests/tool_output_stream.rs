@@ -0,0 +1,93 @@
+//! Streaming tool output end to end
+//!
+//! Registers stubs for the tools behind the built-in `create_internal_bridge`
+//! macro and streams the macro through `POST /api/tools/:name/stream`: the
+//! stubs' own `tool_output` events and the batch runner's step reports must
+//! arrive as `output` events ahead of the final `result` event.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use op_tools::ToolRegistry;
+use simd_json::{json, OwnedValue as Value};
+use simd_json::prelude::*;
+use std::sync::Arc;
+use tower::ServiceExt;
+use tracing_subscriber::prelude::*;
+
+use op_web::routes::create_router;
+use op_web::scope_policy::ScopePolicies;
+use op_web::tool_limits::LimitsConfig;
+use op_web::tool_output::ToolOutputLayer;
+
+use common::{permissive_access, state_with, StubTool};
+
+/// `(event, data)` pairs of an SSE body
+fn sse_events(body: &str) -> Vec<(String, String)> {
+    body.split("\n\n")
+        .filter_map(|block| {
+            let mut event = None;
+            let mut data = None;
+            for line in block.lines() {
+                if let Some(name) = line.strip_prefix("event:") {
+                    event = Some(name.trim().to_string());
+                } else if let Some(payload) = line.strip_prefix("data:") {
+                    data = Some(payload.trim().to_string());
+                }
+            }
+            Some((event?, data?))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_macro_streams_step_output() {
+    let _guard = tracing_subscriber::registry().with(ToolOutputLayer).set_default();
+
+    let registry = Arc::new(ToolRegistry::new());
+    for name in ["ovs_create_bridge", "ovs_add_port", "rtnetlink_add_address"] {
+        registry.register_tool(StubTool::new(name)).await.unwrap();
+    }
+
+    // Macro steps run through the global executor, which `state_with` installs
+    let state = state_with(
+        registry,
+        ScopePolicies::new(Default::default()),
+        LimitsConfig::default(),
+        permissive_access(),
+    )
+    .await;
+    let app = create_router(Arc::new(state));
+
+    let arguments = json!({ "name": "br-test", "cidr": "10.0.0.1/24" });
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/tools/create_internal_bridge/stream")
+        .header("content-type", "application/json")
+        .body(Body::from(simd_json::to_string(&arguments).unwrap()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let events = sse_events(&String::from_utf8_lossy(&bytes));
+
+    let outputs: Vec<Value> = events
+        .iter()
+        .filter(|(event, _)| event == "output")
+        .map(|(_, data)| {
+            let mut raw = data.clone();
+            unsafe { simd_json::from_str(&mut raw) }.unwrap()
+        })
+        .collect();
+    let streams: Vec<&str> = outputs.iter().filter_map(|o| o.get("stream").and_then(|s| s.as_str())).collect();
+    assert_eq!(streams, vec!["stdout", "progress", "stdout", "progress", "stdout", "progress"]);
+    let first = outputs[0].get("data").and_then(|d| d.as_str()).unwrap();
+    assert!(first.starts_with("ovs_create_bridge"), "{}", first);
+    let seqs: Vec<u64> = outputs.iter().filter_map(|o| o.get("seq").and_then(|s| s.as_u64())).collect();
+    assert_eq!(seqs, (1..=6).collect::<Vec<u64>>());
+
+    let (last, result) = events.last().expect("a result event");
+    assert_eq!(last, "result");
+    assert!(result.contains("\"success\":true"), "{}", result);
+}